@@ -0,0 +1,89 @@
+//! Benchmarks for GTFS feed decoding and train position extraction
+//!
+//! Runs against saved feeds in `tests/fixtures` so results don't depend on the
+//! network or the time of day. There is one fixture per feed size tier:
+//!
+//! - `gtfs-si.pb`: small (Staten Island Railway)
+//! - `gtfs-l.pb`: medium (L)
+//! - `gtfs.pb`: large (numbered lines)
+//!
+//! The fixtures are synthetic feeds rather than captures of the live ones, so
+//! timings compare one revision against another rather than predict production
+//! cost. Only time is measured, not allocations.
+//!
+//! The fixture feeds are timestamped around [`FIXTURE_TIME`], which is used as
+//! "now" when picking active segments.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use gtfs_rt::FeedMessage;
+use nyc_pulse_backend::gtfs::{positions_from_feed, GtfsHandler, SegmentLimits};
+use prost::Message;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Header timestamp of the fixture feeds
+const FIXTURE_TIME: i64 = 1_700_000_000;
+
+/// Feed fixtures, smallest to largest
+const FEED_FIXTURES: [&str; 3] = ["gtfs-si.pb", "gtfs-l.pb", "gtfs.pb"];
+
+/// Resolves a file in the backend's fixture directory
+fn fixture_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+/// Builds the directional stop coordinate and name tables from the saved station dataset
+fn load_stops() -> (HashMap<String, (f64, f64)>, HashMap<String, String>) {
+    let raw = std::fs::read_to_string(fixture_path("stations.json")).unwrap();
+    let stations: Vec<Value> = serde_json::from_str(&raw).unwrap();
+
+    let mut stop_locations = HashMap::new();
+    let mut stop_names = HashMap::new();
+    for station in stations {
+        let stop_id = station["gtfs_stop_id"].as_str().unwrap();
+        let lat: f64 = station["gtfs_latitude"].as_str().unwrap().parse().unwrap();
+        let lon: f64 = station["gtfs_longitude"].as_str().unwrap().parse().unwrap();
+        stop_locations.insert(format!("{}N", stop_id), (lat, lon));
+        stop_locations.insert(format!("{}S", stop_id), (lat, lon));
+        let name = station["stop_name"].as_str().unwrap();
+        stop_names.insert(format!("{}N", stop_id), name.to_string());
+        stop_names.insert(format!("{}S", stop_id), name.to_string());
+    }
+    (stop_locations, stop_names)
+}
+
+fn bench_decode_positions(c: &mut Criterion) {
+    let (stop_locations, stop_names) = load_stops();
+    let handler = GtfsHandler::from_stop_locations(stop_locations.clone())
+        .with_stop_names(stop_names.clone());
+
+    for name in FEED_FIXTURES {
+        let bytes = std::fs::read(fixture_path(name)).unwrap();
+        let feed = FeedMessage::decode(bytes.as_slice()).unwrap();
+
+        c.bench_function(&format!("decode_positions/{}", name), |b| {
+            b.iter(|| {
+                handler
+                    .decode_positions(black_box(&bytes), FIXTURE_TIME)
+                    .unwrap()
+            })
+        });
+        c.bench_function(&format!("positions_from_feed/{}", name), |b| {
+            b.iter(|| {
+                positions_from_feed(
+                    black_box(&feed),
+                    &stop_locations,
+                    &stop_names,
+                    FIXTURE_TIME,
+                    SegmentLimits::default(),
+                )
+            })
+        });
+    }
+}
+
+criterion_group!(benches, bench_decode_positions);
+criterion_main!(benches);
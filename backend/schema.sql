@@ -5,7 +5,19 @@ CREATE TABLE IF NOT EXISTS subway_status (
     line VARCHAR(10) NOT NULL,
     status VARCHAR(100) NOT NULL,
     timestamp TIMESTAMPTZ NOT NULL,
-    delays BOOLEAN NOT NULL
+    delays BOOLEAN NOT NULL,
+    -- When status or delays last changed; NULL for rows stored before it existed
+    changed_at TIMESTAMPTZ
+);
+
+-- Only written when STATUS_RETENTION=latest and STATUS_HISTORY=true
+CREATE TABLE IF NOT EXISTS subway_status_history (
+    id SERIAL PRIMARY KEY,
+    line VARCHAR(10) NOT NULL,
+    status VARCHAR(100) NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    delays BOOLEAN NOT NULL,
+    changed_at TIMESTAMPTZ
 );
 
 CREATE TABLE bike_stations (
@@ -37,10 +49,25 @@ CREATE TABLE service_requests (
     longitude DOUBLE PRECISION
 );
 
+-- Newest train positions stored by the collector, one JSON position per row
+CREATE TABLE IF NOT EXISTS train_positions (
+    id SERIAL PRIMARY KEY,
+    recorded_at TIMESTAMPTZ NOT NULL,
+    trip_id TEXT NOT NULL,
+    route_id VARCHAR(10) NOT NULL,
+    position JSONB NOT NULL
+);
+
+-- Databases created before changed_at existed
+ALTER TABLE subway_status ADD COLUMN IF NOT EXISTS changed_at TIMESTAMPTZ;
+ALTER TABLE subway_status_history ADD COLUMN IF NOT EXISTS changed_at TIMESTAMPTZ;
+
 -- Create indexes for time-based queries
 CREATE INDEX idx_subway_status_timestamp ON subway_status(timestamp);
 CREATE INDEX idx_bike_stations_timestamp ON bike_stations(timestamp);
 CREATE INDEX idx_air_quality_timestamp ON air_quality(timestamp);
 CREATE INDEX idx_service_requests_created_at ON service_requests(created_at);
 CREATE INDEX IF NOT EXISTS idx_subway_status_timestamp ON subway_status(timestamp DESC);
-CREATE INDEX IF NOT EXISTS idx_subway_status_line ON subway_status(line);
\ No newline at end of file
+CREATE INDEX IF NOT EXISTS idx_subway_status_line ON subway_status(line);
+CREATE INDEX IF NOT EXISTS idx_subway_status_history_line_timestamp ON subway_status_history(line, timestamp DESC);
+CREATE INDEX IF NOT EXISTS idx_train_positions_recorded_at ON train_positions(recorded_at DESC);
@@ -0,0 +1,484 @@
+//! Startup configuration shared by the server and the collector
+//!
+//! [`Config::from_env`] reads every environment variable either binary uses,
+//! validates them all up front and reports every problem at once, so a
+//! misconfigured deployment fails before connecting to anything.
+//!
+//! | Variable | Default | Used by |
+//! |---|---|---|
+//! | `DATABASE_URL` | required, except in demo mode | both |
+//! | `FEEDS` | every feed | both |
+//! | `STATUS_RETENTION`, `STATUS_HISTORY` | `history` | both |
+//! | `LOG_FORMAT` | `pretty` | both |
+//! | `LINES_FILE` | built-in lines | both |
+//! | `BIND_ADDR`, `PORT` | `0.0.0.0`, `3000` | server |
+//! | `RATE_LIMIT_PER_MIN` | 120 | server |
+//! | `QUERY_TIMEOUT_SECS` | 5 | server |
+//! | `STATIONS_SOURCE`, `STATIONS_FILE` | `api`, `stops.json` | server |
+//! | `INGEST_TOKEN` | ingestion disabled | server |
+//! | `DEBUG_ENDPOINTS` | `false` | server |
+//! | `DEMO_MODE` | `false` | server |
+//! | `INCLUDE_IDLE_TRAINS` | `false` | server |
+//! | `FEED_CONCURRENCY` | 4 | server |
+//! | `STATUS_RETENTION_DAYS` | 7 | collector |
+//! | `COLLECTOR_SEED` | system entropy | collector |
+
+use crate::feeds::{parse_feed_selection, FeedInfo};
+use crate::gtfs::{StationSource, DEFAULT_FEED_CONCURRENCY};
+use crate::lines::{load_lines_file, LineInfo};
+use crate::logging::{parse_log_format, LogFormat};
+use crate::status_store::{parse_retention_days, StatusRetention};
+use crate::{Error, Result};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+/// Default interface the server listens on
+pub const DEFAULT_BIND_ADDR: &str = "0.0.0.0";
+
+/// Default port the server listens on
+pub const DEFAULT_PORT: u16 = 3000;
+
+/// Default number of requests allowed per client per minute
+pub const DEFAULT_RATE_LIMIT_PER_MIN: u32 = 120;
+
+/// Default number of seconds a request's database query may take
+pub const DEFAULT_QUERY_TIMEOUT_SECS: u64 = 5;
+
+/// Every setting read from the environment at startup
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// PostgreSQL connection string (`DATABASE_URL`), empty when unset in demo mode
+    pub database_url: String,
+    /// Feeds polled for train positions and collected statuses (`FEEDS`)
+    pub feeds: Vec<&'static FeedInfo>,
+    /// How past statuses are retained (`STATUS_RETENTION`, `STATUS_HISTORY`)
+    pub status_retention: StatusRetention,
+    /// Log output format (`LOG_FORMAT`)
+    pub log_format: LogFormat,
+    /// Line colors and names, with any overrides applied (`LINES_FILE`)
+    pub lines: Vec<LineInfo>,
+    /// Address the server listens on (`BIND_ADDR`, `PORT`)
+    pub bind_addr: SocketAddr,
+    /// Requests allowed per client IP per minute (`RATE_LIMIT_PER_MIN`)
+    pub rate_limit_per_min: u32,
+    /// Longest a request's database query may run (`QUERY_TIMEOUT_SECS`)
+    pub query_timeout: Duration,
+    /// Where the server loads stations from (`STATIONS_SOURCE`, `STATIONS_FILE`)
+    pub station_source: StationSource,
+    /// Bearer token accepted for status ingestion, which is disabled without one
+    /// (`INGEST_TOKEN`)
+    pub ingest_token: Option<String>,
+    /// Whether `/api/debug` routes are served (`DEBUG_ENDPOINTS`)
+    pub debug_endpoints: bool,
+    /// Whether the server answers from [`crate::demo`] data instead of the
+    /// database and the GTFS feeds (`DEMO_MODE`)
+    pub demo_mode: bool,
+    /// Whether trains standing still at terminals and yards are kept in train
+    /// positions, for debugging (`INCLUDE_IDLE_TRAINS`)
+    pub include_idle_trains: bool,
+    /// Most GTFS feeds fetched at once (`FEED_CONCURRENCY`)
+    pub feed_concurrency: usize,
+    /// Days of history kept before the collector prunes it (`STATUS_RETENTION_DAYS`)
+    pub retention_days: i32,
+    /// Seed making the collector's sample statuses reproducible (`COLLECTOR_SEED`)
+    pub collector_seed: Option<u64>,
+}
+
+impl Config {
+    /// Reads and validates the configuration from the process environment
+    ///
+    /// # Errors
+    /// - `Error::Environment` listing every missing or invalid variable
+    pub fn from_env() -> Result<Self> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    /// Reads and validates the configuration from `lookup`, which maps a
+    /// variable name to its value
+    ///
+    /// # Errors
+    /// - `Error::Environment` listing every missing or invalid variable
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let var = |name: &str| lookup(name);
+        let mut problems = Vec::new();
+
+        let demo_mode = check(
+            &mut problems,
+            parse_flag("DEMO_MODE", var("DEMO_MODE").as_deref()),
+        );
+        let database_url = match var("DATABASE_URL").filter(|url| !url.trim().is_empty()) {
+            Some(url) => Some(url),
+            // Demo mode never connects, so it runs with no setup at all
+            None if demo_mode == Some(true) => Some(String::new()),
+            None => {
+                problems.push("DATABASE_URL must be set".to_string());
+                None
+            }
+        };
+        let feeds = check(&mut problems, parse_feed_selection(var("FEEDS").as_deref()));
+        let status_retention = check(
+            &mut problems,
+            StatusRetention::parse(
+                var("STATUS_RETENTION").as_deref(),
+                var("STATUS_HISTORY").as_deref(),
+            ),
+        );
+        let log_format = check(
+            &mut problems,
+            parse_log_format(var("LOG_FORMAT").as_deref()),
+        );
+        let lines = check(&mut problems, load_lines_file(var("LINES_FILE").as_deref()));
+        let bind_addr = check(
+            &mut problems,
+            parse_bind_addr(var("BIND_ADDR").as_deref(), var("PORT").as_deref()),
+        );
+        let rate_limit_per_min = check(
+            &mut problems,
+            parse_rate_limit(var("RATE_LIMIT_PER_MIN").as_deref()),
+        );
+        let query_timeout = check(
+            &mut problems,
+            parse_query_timeout(var("QUERY_TIMEOUT_SECS").as_deref()),
+        );
+        let station_source = check(
+            &mut problems,
+            StationSource::parse(
+                var("STATIONS_SOURCE").as_deref(),
+                var("STATIONS_FILE").as_deref(),
+            ),
+        );
+        let retention_days = check(
+            &mut problems,
+            parse_retention_days(var("STATUS_RETENTION_DAYS").as_deref()),
+        );
+        let collector_seed = check(&mut problems, parse_seed(var("COLLECTOR_SEED").as_deref()));
+        let debug_endpoints = check(
+            &mut problems,
+            parse_flag("DEBUG_ENDPOINTS", var("DEBUG_ENDPOINTS").as_deref()),
+        );
+        let include_idle_trains = check(
+            &mut problems,
+            parse_flag("INCLUDE_IDLE_TRAINS", var("INCLUDE_IDLE_TRAINS").as_deref()),
+        );
+        let feed_concurrency = check(
+            &mut problems,
+            parse_feed_concurrency(var("FEED_CONCURRENCY").as_deref()),
+        );
+
+        match (
+            database_url,
+            feeds,
+            status_retention,
+            log_format,
+            lines,
+            bind_addr,
+            rate_limit_per_min,
+            query_timeout,
+            station_source,
+            retention_days,
+            collector_seed,
+            debug_endpoints,
+            demo_mode,
+            include_idle_trains,
+            feed_concurrency,
+        ) {
+            (
+                Some(database_url),
+                Some(feeds),
+                Some(status_retention),
+                Some(log_format),
+                Some(lines),
+                Some(bind_addr),
+                Some(rate_limit_per_min),
+                Some(query_timeout),
+                Some(station_source),
+                Some(retention_days),
+                Some(collector_seed),
+                Some(debug_endpoints),
+                Some(demo_mode),
+                Some(include_idle_trains),
+                Some(feed_concurrency),
+            ) if problems.is_empty() => Ok(Self {
+                database_url,
+                feeds,
+                status_retention,
+                log_format,
+                lines,
+                bind_addr,
+                rate_limit_per_min,
+                query_timeout,
+                station_source,
+                ingest_token: var("INGEST_TOKEN").filter(|token| !token.is_empty()),
+                debug_endpoints,
+                demo_mode,
+                include_idle_trains,
+                feed_concurrency,
+                retention_days,
+                collector_seed,
+            }),
+            _ => Err(Error::Environment(format!(
+                "Invalid configuration:\n  - {}",
+                problems.join("\n  - ")
+            ))),
+        }
+    }
+}
+
+/// Returns the value of `result`, or records its message in `problems`
+fn check<T>(problems: &mut Vec<String>, result: Result<T>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(Error::Environment(message)) => {
+            problems.push(message);
+            None
+        }
+        Err(e) => {
+            problems.push(e.to_string());
+            None
+        }
+    }
+}
+
+/// Resolves the listen address from `BIND_ADDR` and `PORT` values
+///
+/// Missing values fall back to [`DEFAULT_BIND_ADDR`] and [`DEFAULT_PORT`].
+///
+/// # Errors
+/// - `Error::Environment` if the address isn't an IP address or the port isn't a `u16`
+pub fn parse_bind_addr(bind_addr: Option<&str>, port: Option<&str>) -> Result<SocketAddr> {
+    let bind_addr = bind_addr.unwrap_or(DEFAULT_BIND_ADDR).trim();
+    let ip: IpAddr = bind_addr.parse().map_err(|_| {
+        Error::Environment(format!(
+            "BIND_ADDR must be an IP address, got {:?}",
+            bind_addr
+        ))
+    })?;
+    let port = match port {
+        Some(port) => port.trim().parse().map_err(|_| {
+            Error::Environment(format!(
+                "PORT must be a number between 0 and 65535, got {:?}",
+                port
+            ))
+        })?,
+        None => DEFAULT_PORT,
+    };
+    Ok(SocketAddr::new(ip, port))
+}
+
+/// Parses a `RATE_LIMIT_PER_MIN` value, defaulting to [`DEFAULT_RATE_LIMIT_PER_MIN`]
+///
+/// # Errors
+/// - `Error::Environment` if the value isn't a positive integer
+pub fn parse_rate_limit(value: Option<&str>) -> Result<u32> {
+    match value {
+        Some(value) => value
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| {
+                Error::Environment(format!(
+                    "RATE_LIMIT_PER_MIN must be a positive integer, got {:?}",
+                    value
+                ))
+            }),
+        None => Ok(DEFAULT_RATE_LIMIT_PER_MIN),
+    }
+}
+
+/// Parses a `QUERY_TIMEOUT_SECS` value, defaulting to [`DEFAULT_QUERY_TIMEOUT_SECS`]
+///
+/// # Errors
+/// - `Error::Environment` if the value isn't a positive integer
+pub fn parse_query_timeout(value: Option<&str>) -> Result<Duration> {
+    match value {
+        Some(value) => value
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .filter(|&secs| secs > 0)
+            .map(Duration::from_secs)
+            .ok_or_else(|| {
+                Error::Environment(format!(
+                    "QUERY_TIMEOUT_SECS must be a positive integer, got {:?}",
+                    value
+                ))
+            }),
+        None => Ok(Duration::from_secs(DEFAULT_QUERY_TIMEOUT_SECS)),
+    }
+}
+
+/// Parses a `FEED_CONCURRENCY` value, defaulting to [`DEFAULT_FEED_CONCURRENCY`]
+///
+/// # Errors
+/// - `Error::Environment` if the value isn't a positive integer
+pub fn parse_feed_concurrency(value: Option<&str>) -> Result<usize> {
+    match value {
+        Some(value) => value
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| {
+                Error::Environment(format!(
+                    "FEED_CONCURRENCY must be a positive integer, got {:?}",
+                    value
+                ))
+            }),
+        None => Ok(DEFAULT_FEED_CONCURRENCY),
+    }
+}
+
+/// Parses a `COLLECTOR_SEED` value; `None` leaves the collector unseeded
+///
+/// # Errors
+/// - `Error::Environment` if the value isn't an unsigned integer
+pub fn parse_seed(value: Option<&str>) -> Result<Option<u64>> {
+    value
+        .map(|value| {
+            value.trim().parse::<u64>().map_err(|_| {
+                Error::Environment(format!(
+                    "COLLECTOR_SEED must be an unsigned integer, got {:?}",
+                    value
+                ))
+            })
+        })
+        .transpose()
+}
+
+/// Parses the value of the on/off variable `name`, such as `DEBUG_ENDPOINTS`;
+/// `None` or a blank value is off
+///
+/// # Errors
+/// - `Error::Environment` if the value isn't `1`, `true`, `0` or `false`
+pub fn parse_flag(name: &str, value: Option<&str>) -> Result<bool> {
+    match value.map(|value| value.trim().to_lowercase()).as_deref() {
+        None | Some("") | Some("0") | Some("false") => Ok(false),
+        Some("1") | Some("true") => Ok(true),
+        Some(other) => Err(Error::Environment(format!(
+            "{} must be 1, 0, true or false, got {:?}",
+            name, other
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config(vars: &[(&str, &str)]) -> Result<Config> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        Config::from_lookup(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = config(&[("DATABASE_URL", "postgres://localhost/nyc_pulse")]).unwrap();
+
+        assert_eq!(config.database_url, "postgres://localhost/nyc_pulse");
+        assert_eq!(config.bind_addr, "0.0.0.0:3000".parse().unwrap());
+        assert_eq!(config.rate_limit_per_min, DEFAULT_RATE_LIMIT_PER_MIN);
+        assert_eq!(
+            config.query_timeout,
+            Duration::from_secs(DEFAULT_QUERY_TIMEOUT_SECS)
+        );
+        assert_eq!(config.status_retention, StatusRetention::History);
+        assert_eq!(config.log_format, LogFormat::Pretty);
+        assert_eq!(config.ingest_token, None);
+        assert_eq!(config.collector_seed, None);
+        assert!(!config.debug_endpoints);
+        assert!(!config.demo_mode);
+        assert!(!config.include_idle_trains);
+        assert_eq!(config.feed_concurrency, DEFAULT_FEED_CONCURRENCY);
+        assert_eq!(config.feeds.len(), crate::feeds::SUBWAY_FEEDS.len());
+        assert_eq!(config.lines, crate::lines::LINES);
+    }
+
+    #[test]
+    fn test_every_problem_is_reported() {
+        let message = config(&[
+            ("PORT", "http"),
+            ("FEEDS", "xyz"),
+            ("RATE_LIMIT_PER_MIN", "0"),
+            ("COLLECTOR_SEED", "-1"),
+            ("LINES_FILE", "no-such-lines.json"),
+        ])
+        .unwrap_err()
+        .to_string();
+
+        for name in [
+            "DATABASE_URL",
+            "PORT",
+            "FEEDS",
+            "RATE_LIMIT_PER_MIN",
+            "COLLECTOR_SEED",
+            "LINES_FILE",
+        ] {
+            assert!(message.contains(name), "{} missing from {}", name, message);
+        }
+    }
+
+    #[test]
+    fn test_demo_mode_needs_no_database_url() {
+        let config = config(&[("DEMO_MODE", "1")]).unwrap();
+
+        assert!(config.demo_mode);
+        assert_eq!(config.database_url, "");
+    }
+
+    #[test]
+    fn test_parse_flag() {
+        let flag = |value| parse_flag("DEBUG_ENDPOINTS", value);
+        assert!(flag(Some("1")).unwrap());
+        assert!(flag(Some(" TRUE ")).unwrap());
+        assert!(!flag(Some("0")).unwrap());
+        assert!(!flag(None).unwrap());
+        let message = flag(Some("on")).unwrap_err().to_string();
+        assert!(message.contains("DEBUG_ENDPOINTS"), "{}", message);
+        assert!(message.contains("1, 0, true or false"), "{}", message);
+    }
+
+    #[test]
+    fn test_parse_feed_concurrency() {
+        assert_eq!(
+            parse_feed_concurrency(None).unwrap(),
+            DEFAULT_FEED_CONCURRENCY
+        );
+        assert_eq!(parse_feed_concurrency(Some(" 2 ")).unwrap(), 2);
+        assert!(parse_feed_concurrency(Some("0")).is_err());
+        assert!(parse_feed_concurrency(Some("many")).is_err());
+    }
+
+    #[test]
+    fn test_parse_query_timeout() {
+        assert_eq!(
+            parse_query_timeout(Some(" 2 ")).unwrap(),
+            Duration::from_secs(2)
+        );
+        assert!(parse_query_timeout(Some("0")).is_err());
+        assert!(parse_query_timeout(Some("1.5")).is_err());
+    }
+
+    #[test]
+    fn test_parse_bind_addr() {
+        assert_eq!(
+            parse_bind_addr(None, None).unwrap(),
+            "0.0.0.0:3000".parse().unwrap()
+        );
+        assert_eq!(
+            parse_bind_addr(Some("127.0.0.1"), Some("8080")).unwrap(),
+            "127.0.0.1:8080".parse().unwrap()
+        );
+        assert_eq!(
+            parse_bind_addr(Some("::1"), None).unwrap(),
+            "[::1]:3000".parse().unwrap()
+        );
+        assert!(parse_bind_addr(Some("localhost"), None).is_err());
+        assert!(parse_bind_addr(None, Some("70000")).is_err());
+        assert!(parse_bind_addr(None, Some("http")).is_err());
+    }
+}
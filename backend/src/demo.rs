@@ -0,0 +1,89 @@
+//! Canned data served in demo mode
+//!
+//! With `DEMO_MODE=1` the server answers status and train requests from
+//! [`demo_data`] instead of the database and the GTFS feeds, so the whole app
+//! runs, with the same data every time, without Postgres or MTA access. The
+//! data is the committed `demo.json` next to this module, built into the
+//! binary.
+
+use crate::gtfs::TrainPositions;
+use crate::SubwayStatus;
+use nyc_pulse_common::trains::TrainPosition;
+use serde::Deserialize;
+use std::sync::OnceLock;
+
+/// Statuses and train positions served in demo mode
+#[derive(Debug, Clone, Deserialize)]
+pub struct DemoData {
+    /// Latest status of each line, sorted by line
+    pub statuses: Vec<SubwayStatus>,
+    /// Trains in transit
+    pub trains: Vec<TrainPosition>,
+}
+
+impl DemoData {
+    /// The canned statuses, only the delayed lines with `delays_only`
+    pub fn statuses(&self, delays_only: bool) -> Vec<SubwayStatus> {
+        self.statuses
+            .iter()
+            .filter(|status| !delays_only || status.delays)
+            .cloned()
+            .collect()
+    }
+
+    /// The canned trains, as if every feed had been fetched
+    pub fn train_positions(&self) -> TrainPositions {
+        TrainPositions {
+            positions: self.trains.clone(),
+            scheduled: Vec::new(),
+            feeds_total: 0,
+            feeds_failed: 0,
+        }
+    }
+}
+
+/// Demo data parsed from the built-in `demo.json`
+static DEMO_DATA: OnceLock<DemoData> = OnceLock::new();
+
+/// The built-in demo data, parsed on first use
+pub fn demo_data() -> &'static DemoData {
+    DEMO_DATA.get_or_init(|| {
+        serde_json::from_str(include_str!("demo.json")).expect("demo.json is valid demo data")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_demo_data_parses() {
+        let data = demo_data();
+
+        assert!(!data.statuses.is_empty());
+        assert!(data
+            .statuses
+            .windows(2)
+            .all(|pair| pair[0].line < pair[1].line));
+        assert!(data.trains.iter().all(|train| train.is_known));
+        assert!(data
+            .trains
+            .iter()
+            .all(|train| train.from_stop.is_valid_nyc() && train.to_stop.is_valid_nyc()));
+    }
+
+    #[test]
+    fn test_demo_statuses_filter_delays() {
+        let delayed: Vec<String> = demo_data()
+            .statuses(true)
+            .into_iter()
+            .map(|status| status.line)
+            .collect();
+
+        assert_eq!(delayed, ["4", "L"]);
+        assert_eq!(
+            demo_data().statuses(false).len(),
+            demo_data().statuses.len()
+        );
+    }
+}
@@ -0,0 +1,85 @@
+//! Feed selection for the collector and the GTFS handler
+//!
+//! The feed catalogue lives in [`nyc_pulse_common::feeds`] so the frontend can
+//! share it, and is re-exported here. This module resolves the `FEEDS`
+//! environment variable, a comma-separated list of feed keys such as
+//! `ace,l,1234567`, into the subset both the collector and the GTFS handler poll.
+
+use crate::{Error, Result};
+pub use nyc_pulse_common::feeds::{
+    feed_for_route, FeedInfo, MTA_FEED_BASE_URL, SUBWAY_ALERTS_FEED, SUBWAY_FEEDS,
+};
+
+/// Resolves a comma-separated list of feed keys into entries of [`SUBWAY_FEEDS`]
+///
+/// Keys are case-insensitive and may be surrounded by whitespace. `None` or a
+/// blank value selects every feed. The result keeps [`SUBWAY_FEEDS`] order.
+///
+/// # Errors
+/// - `Error::Environment` naming every unknown key and listing the valid ones
+pub fn parse_feed_selection(value: Option<&str>) -> Result<Vec<&'static FeedInfo>> {
+    let keys: Vec<String> = value
+        .unwrap_or_default()
+        .split(',')
+        .map(|key| key.trim().to_lowercase())
+        .filter(|key| !key.is_empty())
+        .collect();
+
+    if keys.is_empty() {
+        return Ok(SUBWAY_FEEDS.iter().collect());
+    }
+
+    let unknown: Vec<&str> = keys
+        .iter()
+        .map(String::as_str)
+        .filter(|key| !SUBWAY_FEEDS.iter().any(|feed| feed.key == *key))
+        .collect();
+    if !unknown.is_empty() {
+        let valid: Vec<&str> = SUBWAY_FEEDS.iter().map(|feed| feed.key).collect();
+        return Err(Error::Environment(format!(
+            "Unknown feed key(s) in FEEDS: {} (expected any of: {})",
+            unknown.join(", "),
+            valid.join(", ")
+        )));
+    }
+
+    Ok(SUBWAY_FEEDS
+        .iter()
+        .filter(|feed| keys.iter().any(|key| key == feed.key))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(feeds: &[&FeedInfo]) -> Vec<&'static str> {
+        feeds.iter().map(|feed| feed.key).collect()
+    }
+
+    #[test]
+    fn test_parse_feed_selection_defaults_to_all() {
+        assert_eq!(
+            parse_feed_selection(None).unwrap().len(),
+            SUBWAY_FEEDS.len()
+        );
+        assert_eq!(
+            parse_feed_selection(Some("  ")).unwrap().len(),
+            SUBWAY_FEEDS.len()
+        );
+    }
+
+    #[test]
+    fn test_parse_feed_selection_subset() {
+        let feeds = parse_feed_selection(Some("l, ACE,1234567,ace")).unwrap();
+        assert_eq!(keys(&feeds), ["1234567", "ace", "l"]);
+    }
+
+    #[test]
+    fn test_parse_feed_selection_unknown_keys() {
+        let err = parse_feed_selection(Some("ace,xyz,123")).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("xyz, 123"), "{}", message);
+        assert!(message.contains("nqrw"), "{}", message);
+    }
+}
@@ -9,24 +9,353 @@
 //!
 //! The module uses the GTFS Realtime protobuf format for parsing feed data and maintains
 //! an in-memory cache of subway station locations for position calculations.
+//! Feeds are serialized through [`snapshot::FeedSnapshot`] rather than the
+//! protobuf types themselves.
 
-use chrono::Utc;
-use gtfs_rt::FeedMessage;
-use log::{debug, info};
-use nyc_pulse_backend::{Error, Result, StopLocation, TrainPosition};
+pub mod snapshot;
+
+use crate::feeds::{feed_for_route, FeedInfo, MTA_FEED_BASE_URL, SUBWAY_ALERTS_FEED, SUBWAY_FEEDS};
+use crate::http::{build_http_client, HttpClientConfig};
+use crate::{
+    Departure, DepartureGroup, Direction, Error, OccupancyStatus, Result, StopInfo, StopLocation,
+    TrainPosition,
+};
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use gtfs_rt::{alert, trip_update, vehicle_position, Alert, FeedMessage};
+use log::{debug, error, info, warn};
+use nyc_pulse_common::borough::Borough;
+use nyc_pulse_common::lines;
+use nyc_pulse_common::open_data::{self, STATIONS_API_URL, STATIONS_PAGE_SIZE};
+use nyc_pulse_common::serde_helpers::{rfc3339, string_or_number_to_f64};
+use nyc_pulse_common::service_status::ServiceStatus;
+use nyc_pulse_common::trains::is_valid_nyc_coordinate;
+use parking_lot::{Mutex, RwLock};
 use prost::Message;
-use serde::Deserialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// Most upcoming stops listed per train in [`TrainPosition::remaining_stops`]
+pub const MAX_REMAINING_STOPS: usize = 10;
+
+/// Most departures listed per route and direction by [`GtfsHandler::get_departures`]
+pub const DEPARTURES_PER_DIRECTION: usize = 3;
+
+/// Default number of feeds fetched at once (see [`GtfsHandler::with_feed_concurrency`])
+pub const DEFAULT_FEED_CONCURRENCY: usize = 4;
+
+/// Default for [`SegmentLimits::max_segment_secs`]
+pub const DEFAULT_MAX_SEGMENT_SECS: i64 = 600;
+
+/// Default for [`SegmentLimits::stale_after_secs`]
+pub const DEFAULT_STALE_AFTER_SECS: i64 = 600;
+
+/// Default for [`SegmentLimits::scheduled_within_secs`]
+pub const DEFAULT_SCHEDULED_WITHIN_SECS: i64 = 600;
+
+/// Plausibility limits that keep stale feed data from producing stuck trains
+///
+/// A feed that stops updating a trip, or pushes far-future arrival times, can
+/// leave a segment "active" long after the train has moved on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentLimits {
+    /// Longest believable time between two consecutive stops, in seconds
+    pub max_segment_secs: i64,
+    /// Segments that started more than this many seconds ago are skipped
+    pub stale_after_secs: i64,
+    /// Trips whose first segment starts at most this many seconds from now are
+    /// placed at their origin stop with progress 0.0, as scheduled trains
+    pub scheduled_within_secs: i64,
+}
+
+impl Default for SegmentLimits {
+    fn default() -> Self {
+        Self {
+            max_segment_secs: DEFAULT_MAX_SEGMENT_SECS,
+            stale_after_secs: DEFAULT_STALE_AFTER_SECS,
+            scheduled_within_secs: DEFAULT_SCHEDULED_WITHIN_SECS,
+        }
+    }
+}
+
+impl SegmentLimits {
+    /// Whether a segment lasting `duration_secs`, which started `elapsed_secs`
+    /// ago, is plausible
+    fn allows(&self, duration_secs: i64, elapsed_secs: i64) -> bool {
+        duration_secs <= self.max_segment_secs && elapsed_secs <= self.stale_after_secs
+    }
+
+    /// Whether a trip whose first segment starts at `from_time` leaves soon
+    /// enough after `current_time` to be listed as scheduled
+    fn schedules(&self, from_time: i64, current_time: i64) -> bool {
+        from_time > current_time
+            && from_time.saturating_sub(current_time) <= self.scheduled_within_secs
+    }
+}
+
+/// Health of a single GTFS feed, updated each time it is polled
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct FeedHealth {
+    /// When the feed was last fetched and decoded successfully
+    #[serde(with = "rfc3339::option")]
+    pub last_success: Option<DateTime<Utc>>,
+    /// Message from the most recent failure, if any
+    pub last_error: Option<String>,
+    /// Number of failures since the last success
+    pub consecutive_failures: u32,
+}
+
+impl FeedHealth {
+    /// Records a successful poll, resetting the failure count
+    pub fn record_success(&mut self, at: DateTime<Utc>) {
+        self.last_success = Some(at);
+        self.consecutive_failures = 0;
+    }
+
+    /// Records a failed poll with its error message
+    pub fn record_failure(&mut self, error: String) {
+        self.last_error = Some(error);
+        self.consecutive_failures += 1;
+    }
+}
 
 /// Response structure for station location data from the NY Open Data API
 #[derive(Deserialize)]
 struct StationResponse {
     /// GTFS stop ID for the station
     gtfs_stop_id: String,
-    /// Latitude coordinate as string
-    gtfs_latitude: String,
-    /// Longitude coordinate as string
-    gtfs_longitude: String,
+    /// Station name as shown on signage
+    stop_name: String,
+    /// Space-separated routes stopping here during the day (e.g. "A C E")
+    #[serde(default)]
+    daytime_routes: String,
+    /// Borough as a name or MTA abbreviation (e.g. "Bk")
+    #[serde(default)]
+    borough: String,
+    /// Latitude coordinate, sent as a string
+    #[serde(deserialize_with = "string_or_number_to_f64")]
+    gtfs_latitude: f64,
+    /// Longitude coordinate, sent as a string
+    #[serde(deserialize_with = "string_or_number_to_f64")]
+    gtfs_longitude: f64,
+}
+
+/// Default path of the bundled station dataset
+pub const DEFAULT_STATIONS_FILE: &str = "stops.json";
+
+/// Where [`GtfsHandler::new`] loads station locations from
+#[derive(Debug, Clone, PartialEq)]
+pub enum StationSource {
+    /// NY Open Data API, falling back to the file if the request fails
+    Api {
+        /// Station dataset read when the API is unreachable
+        fallback: PathBuf,
+    },
+    /// Station dataset file only, never touching the network
+    File(PathBuf),
+}
+
+impl StationSource {
+    /// Parses `STATIONS_SOURCE` (`api` or `file`, default `api`) and `STATIONS_FILE` values
+    ///
+    /// `STATIONS_FILE` defaults to [`DEFAULT_STATIONS_FILE`].
+    ///
+    /// # Errors
+    /// - `Error::Environment` if `STATIONS_SOURCE` is neither `api` nor `file`
+    pub fn parse(source: Option<&str>, file: Option<&str>) -> Result<Self> {
+        let path = PathBuf::from(file.unwrap_or(DEFAULT_STATIONS_FILE));
+        match source.unwrap_or_default().trim().to_lowercase().as_str() {
+            "" | "api" => Ok(Self::Api { fallback: path }),
+            "file" => Ok(Self::File(path)),
+            other => Err(Error::Environment(format!(
+                "STATIONS_SOURCE must be \"api\" or \"file\", got {:?}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Fetches every station from NY Open Data
+async fn fetch_stations(client: &reqwest::Client) -> Result<Vec<StationResponse>> {
+    fetch_station_pages(client, STATIONS_API_URL, STATIONS_PAGE_SIZE).await
+}
+
+/// Fetches stations from `url` `page_size` rows at a time until a short page
+///
+/// # Errors
+/// - If any page fails to load or parse
+async fn fetch_station_pages(
+    client: &reqwest::Client,
+    url: &str,
+    page_size: usize,
+) -> Result<Vec<StationResponse>> {
+    let mut stations = Vec::new();
+    loop {
+        let page: Vec<StationResponse> = client
+            .get(open_data::page_url(url, page_size, stations.len()))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let last = open_data::is_last_page(page.len(), page_size);
+        stations.extend(page);
+        if last {
+            break;
+        }
+    }
+    info!("Fetched {} stations from NY Open Data", stations.len());
+    Ok(stations)
+}
+
+/// Reads a station dataset saved in the NY Open Data format
+fn load_stations_file(path: &Path) -> Result<Vec<StationResponse>> {
+    let raw = std::fs::read_to_string(path)?;
+    serde_json::from_str(&raw)
+        .map_err(|e| Error::Environment(format!("Invalid stations file {}: {}", path.display(), e)))
+}
+
+/// Lookup tables built from a station dataset, with the ETag of the station
+/// list they make
+///
+/// The tables are only built through [`StopTables::new`], so the ETag always
+/// matches them.
+#[derive(Debug, Clone)]
+pub struct StopTables {
+    /// Coordinates keyed by directional stop ID
+    locations: HashMap<String, (f64, f64)>,
+    /// Station names keyed by directional stop ID
+    names: HashMap<String, String>,
+    /// Daytime routes keyed by parent stop ID
+    routes: HashMap<String, Vec<String>>,
+    /// Boroughs keyed by parent stop ID
+    boroughs: HashMap<String, Borough>,
+    /// Strong ETag of [`StopTables::stops`]: a hash of its JSON, quoted
+    etag: String,
+}
+
+impl StopTables {
+    /// Bundles the lookup tables and computes the ETag of their station list
+    ///
+    /// # Arguments
+    /// * `locations` - `(latitude, longitude)` pairs keyed by directional stop ID
+    /// * `names` - Station names keyed by directional stop ID
+    /// * `routes` - Daytime routes keyed by parent stop ID
+    /// * `boroughs` - Boroughs keyed by parent stop ID
+    pub fn new(
+        locations: HashMap<String, (f64, f64)>,
+        names: HashMap<String, String>,
+        routes: HashMap<String, Vec<String>>,
+        boroughs: HashMap<String, Borough>,
+    ) -> Self {
+        let mut tables = Self {
+            locations,
+            names,
+            routes,
+            boroughs,
+            etag: String::new(),
+        };
+        tables.update_etag();
+        tables
+    }
+
+    /// Recomputes the ETag after the tables changed
+    fn update_etag(&mut self) {
+        let mut hasher = DefaultHasher::new();
+        serde_json::to_vec(&self.stops())
+            .expect("stop locations serialize")
+            .hash(&mut hasher);
+        self.etag = format!("\"{:016x}\"", hasher.finish());
+    }
+
+    /// Every stop with its coordinates and name, sorted by stop ID
+    fn stops(&self) -> Vec<StopLocation> {
+        let mut stops: Vec<StopLocation> = self
+            .locations
+            .iter()
+            .map(|(stop_id, &(latitude, longitude))| {
+                StopLocation::new(
+                    stop_id.clone(),
+                    self.names.get(stop_id).cloned(),
+                    latitude,
+                    longitude,
+                )
+            })
+            .collect();
+        stops.sort_by(|a, b| a.stop_id.cmp(&b.stop_id));
+        stops
+    }
+}
+
+/// Builds the stop lookup tables from a station dataset
+///
+/// Each station's coordinates and name are stored under both its northbound
+/// and southbound stop IDs; its routes and borough under the parent stop ID.
+/// Stations with an unrecognized borough are left out of the borough table,
+/// and stations whose coordinates fall outside NYC (see
+/// [`is_valid_nyc_coordinate`]) are skipped entirely.
+fn stop_tables(stations: Vec<StationResponse>) -> StopTables {
+    let mut locations = HashMap::new();
+    let mut names = HashMap::new();
+    let mut routes = HashMap::new();
+    let mut boroughs = HashMap::new();
+    for station in stations {
+        let (lat, lon) = (station.gtfs_latitude, station.gtfs_longitude);
+        if !is_valid_nyc_coordinate(lat, lon) {
+            warn!(
+                "Skipping station {} ({}) at ({}, {}), outside NYC",
+                station.gtfs_stop_id, station.stop_name, lat, lon
+            );
+            continue;
+        }
+
+        for direction in ["N", "S"] {
+            let stop_id = format!("{}{}", station.gtfs_stop_id, direction);
+            locations.insert(stop_id.clone(), (lat, lon));
+            names.insert(stop_id, station.stop_name.clone());
+        }
+        // The dataset's route IDs can differ from the feeds' (`SIR` for `SI`)
+        let station_routes = station
+            .daytime_routes
+            .split_whitespace()
+            .map(|route| lines::normalize_route(route).unwrap_or(route).to_string())
+            .collect();
+        if let Ok(borough) = station.borough.parse() {
+            boroughs.insert(station.gtfs_stop_id.clone(), borough);
+        }
+        routes.insert(station.gtfs_stop_id, station_routes);
+    }
+    StopTables::new(locations, names, routes, boroughs)
+}
+
+/// Train positions from one round of feed fetches, with how many feeds failed
+///
+/// Lets callers tell "no trains running" apart from "no feed answered", which
+/// both have no positions.
+#[derive(Debug, Clone, Default)]
+pub struct TrainPositions {
+    /// Current positions, merged in feed order
+    pub positions: Vec<TrainPosition>,
+    /// Trains that haven't left their origin yet, at progress 0.0 (see
+    /// [`SegmentLimits::scheduled_within_secs`])
+    pub scheduled: Vec<TrainPosition>,
+    /// Feeds that were fetched
+    pub feeds_total: usize,
+    /// Feeds that failed to fetch or decode
+    pub feeds_failed: usize,
+}
+
+impl TrainPositions {
+    /// Whether at least one feed was fetched and every one of them failed
+    pub fn all_feeds_failed(&self) -> bool {
+        self.feeds_total > 0 && self.feeds_failed == self.feeds_total
+    }
 }
 
 /// Main handler for GTFS real-time data processing
@@ -37,206 +366,2467 @@ struct StationResponse {
 pub struct GtfsHandler {
     /// HTTP client for making API requests
     client: reqwest::Client,
-    /// Cache of station locations indexed by stop ID
-    stop_locations: HashMap<String, (f64, f64)>,
+    /// Station locations, names, routes and boroughs, shared by every clone
+    /// and replaced together
+    stop_tables: Arc<RwLock<StopTables>>,
+    /// Feeds polled for train positions
+    feeds: Vec<&'static FeedInfo>,
+    /// Base URL the feed paths are resolved against
+    feed_base_url: String,
+    /// Limits applied to every segment before it becomes a position
+    segment_limits: SegmentLimits,
+    /// Whether [`GtfsHandler::get_train_positions`] keeps idle trains
+    include_idle_trains: bool,
+    /// Permits for in-flight feed fetches, shared by every clone
+    feed_permits: Arc<Semaphore>,
 }
 
 impl GtfsHandler {
     /// Creates a new GtfsHandler instance
     ///
-    /// Initializes by fetching station location data from NY Open Data API
-    /// and building an in-memory lookup table of stop coordinates.
+    /// Initializes by loading station location data from `source` and building
+    /// an in-memory lookup table of stop coordinates. With [`StationSource::Api`]
+    /// the NY Open Data API is tried first and the fallback file is only read
+    /// if that request fails.
+    ///
+    /// # Arguments
+    /// * `feeds` - Feeds to poll for train positions (see [`crate::config::Config::feeds`])
+    /// * `source` - Where to load stations from (see [`crate::config::Config::station_source`])
     ///
     /// # Returns
     /// - `Result<GtfsHandler>` - New handler instance or error if initialization fails
     ///
     /// # Errors
-    /// - If station data API request fails
-    /// - If station coordinate parsing fails
-    pub async fn new() -> Result<Self> {
-        let client = reqwest::Client::new();
+    /// - If the station data API request fails and the fallback file can't be loaded
+    /// - If the station file can't be read or parsed, including non-numeric coordinates
+    pub async fn new(feeds: Vec<&'static FeedInfo>, source: StationSource) -> Result<Self> {
+        let client = build_http_client(&HttpClientConfig::default())?;
 
-        // Fetch all station locations
-        let response = client
-            .get("https://data.ny.gov/resource/39hk-dx4f.json")
-            .send()
-            .await?;
+        let stations = match source {
+            StationSource::Api { fallback } => match fetch_stations(&client).await {
+                Ok(stations) => stations,
+                Err(e) => {
+                    error!(
+                        "Failed to fetch stations from NY Open Data ({}), loading {}",
+                        e,
+                        fallback.display()
+                    );
+                    load_stations_file(&fallback)?
+                }
+            },
+            StationSource::File(path) => load_stations_file(&path)?,
+        };
 
-        let stations: Vec<StationResponse> = response.json().await?;
+        let tables = stop_tables(stations);
+        info!("Loaded {} stop locations", tables.locations.len() / 2);
+
+        Ok(Self {
+            client,
+            stop_tables: Arc::new(RwLock::new(tables)),
+            feeds,
+            feed_base_url: MTA_FEED_BASE_URL.to_string(),
+            segment_limits: SegmentLimits::default(),
+            include_idle_trains: false,
+            feed_permits: Arc::new(Semaphore::new(DEFAULT_FEED_CONCURRENCY)),
+        })
+    }
 
-        // Create stop locations map with both N and S directions
-        let mut stop_locations = HashMap::new();
-        for station in stations {
-            let lat: f64 = station
-                .gtfs_latitude
-                .parse()
-                .map_err(|e| Error::Environment(format!("Invalid latitude: {}", e)))?;
-            let lon: f64 = station
-                .gtfs_longitude
-                .parse()
-                .map_err(|e| Error::Environment(format!("Invalid longitude: {}", e)))?;
+    /// Creates a handler from a saved station dataset without touching the network
+    ///
+    /// All feeds are selected.
+    ///
+    /// # Arguments
+    /// * `path` - JSON file in the NY Open Data station format
+    ///
+    /// # Errors
+    /// - If the file can't be read or parsed, including non-numeric coordinates
+    pub fn from_stations_file(path: &Path) -> Result<Self> {
+        let handler = Self::from_stop_locations(HashMap::new());
+        handler.refresh_stop_tables(stop_tables(load_stations_file(path)?));
+        Ok(handler)
+    }
 
-            // Add both northbound and southbound stops
-            stop_locations.insert(format!("{}N", station.gtfs_stop_id), (lat, lon));
-            stop_locations.insert(format!("{}S", station.gtfs_stop_id), (lat, lon));
+    /// Creates a handler from an already-built table of stop coordinates
+    ///
+    /// Skips the NY Open Data request, which makes it suitable for benchmarks
+    /// and for processing saved feeds offline. All feeds are selected and no
+    /// station names, routes or boroughs are known until [`GtfsHandler::with_stop_names`],
+    /// [`GtfsHandler::with_stop_routes`] and [`GtfsHandler::with_stop_boroughs`] are called.
+    ///
+    /// # Arguments
+    /// * `stop_locations` - `(latitude, longitude)` pairs keyed by directional stop ID
+    pub fn from_stop_locations(stop_locations: HashMap<String, (f64, f64)>) -> Self {
+        Self {
+            client: build_http_client(&HttpClientConfig::default())
+                .expect("default HTTP client configuration is valid"),
+            stop_tables: Arc::new(RwLock::new(StopTables::new(
+                stop_locations,
+                HashMap::new(),
+                HashMap::new(),
+                HashMap::new(),
+            ))),
+            feeds: SUBWAY_FEEDS.iter().collect(),
+            feed_base_url: MTA_FEED_BASE_URL.to_string(),
+            segment_limits: SegmentLimits::default(),
+            include_idle_trains: false,
+            feed_permits: Arc::new(Semaphore::new(DEFAULT_FEED_CONCURRENCY)),
         }
+    }
 
-        println!("Loaded {} stop locations", stop_locations.len() / 2);
+    /// Replaces the station name cache
+    ///
+    /// # Arguments
+    /// * `stop_names` - Station names keyed by directional stop ID
+    pub fn with_stop_names(self, stop_names: HashMap<String, String>) -> Self {
+        self.update_stop_tables(|tables| tables.names = stop_names);
+        self
+    }
 
-        Ok(Self {
-            client,
-            stop_locations,
+    /// Replaces the station route cache
+    ///
+    /// # Arguments
+    /// * `stop_routes` - Daytime routes keyed by parent stop ID
+    pub fn with_stop_routes(self, stop_routes: HashMap<String, Vec<String>>) -> Self {
+        self.update_stop_tables(|tables| tables.routes = stop_routes);
+        self
+    }
+
+    /// Replaces the station borough cache
+    ///
+    /// # Arguments
+    /// * `stop_boroughs` - Boroughs keyed by parent stop ID
+    pub fn with_stop_boroughs(self, stop_boroughs: HashMap<String, Borough>) -> Self {
+        self.update_stop_tables(|tables| tables.boroughs = stop_boroughs);
+        self
+    }
+
+    /// Changes one of the stop tables in place, then recomputes their ETag
+    fn update_stop_tables(&self, update: impl FnOnce(&mut StopTables)) {
+        let mut tables = self.stop_tables.write();
+        update(&mut tables);
+        tables.update_etag();
+    }
+
+    /// Replaces every stop table for this handler and every clone of it
+    ///
+    /// The coordinates, names, routes, boroughs and station list ETag are
+    /// swapped under one lock, so readers never see a mix of old and new.
+    ///
+    /// # Arguments
+    /// * `tables` - Tables built with [`StopTables::new`]
+    pub fn refresh_stop_tables(&self, tables: StopTables) {
+        *self.stop_tables.write() = tables;
+    }
+
+    /// Strong ETag of the list returned by [`GtfsHandler::stops`]
+    pub fn stations_etag(&self) -> String {
+        self.stop_tables.read().etag.clone()
+    }
+
+    /// Borough of the station with a parent (`L08`) or directional (`L08N`) stop ID
+    pub fn borough(&self, stop_id: &str) -> Option<Borough> {
+        let tables = self.stop_tables.read();
+        tables
+            .boroughs
+            .get(stop_id)
+            .or_else(|| tables.boroughs.get(parent_stop_id(stop_id)))
+            .copied()
+    }
+
+    /// Fetches feeds from `base_url` instead of the MTA API, e.g. a mirror or test server
+    ///
+    /// # Arguments
+    /// * `base_url` - URL prefix for the feed paths, ending in `/`
+    pub fn with_feed_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.feed_base_url = base_url.into();
+        self
+    }
+
+    /// Every cached stop with its coordinates and name, sorted by stop ID
+    pub fn stops(&self) -> Vec<StopLocation> {
+        self.stop_tables.read().stops()
+    }
+
+    /// Every cached station, one per parent stop ID, sorted by stop ID
+    ///
+    /// `train_approaching` is always `false`, as for [`GtfsHandler::station`].
+    pub fn stations(&self) -> Vec<StopInfo> {
+        let mut parents: Vec<String> = self
+            .stop_tables
+            .read()
+            .locations
+            .keys()
+            .map(|stop_id| parent_stop_id(stop_id).to_string())
+            .collect();
+        parents.sort();
+        parents.dedup();
+        parents
+            .iter()
+            .filter_map(|parent| self.station(parent))
+            .collect()
+    }
+
+    /// Replaces the stale-segment limits, which default to [`SegmentLimits::default`]
+    pub fn with_segment_limits(mut self, segment_limits: SegmentLimits) -> Self {
+        self.segment_limits = segment_limits;
+        self
+    }
+
+    /// Limits how many feeds are fetched at once, across every clone of the handler
+    ///
+    /// Feeds are requested concurrently, so a poll takes about as long as the
+    /// slowest feed. Lower limits are gentler on MTA rate limits but make polls
+    /// slower: with 8 feeds and a limit of 4 a poll takes two round trips.
+    /// Defaults to [`DEFAULT_FEED_CONCURRENCY`]; a limit of 0 is treated as 1.
+    pub fn with_feed_concurrency(mut self, feed_concurrency: usize) -> Self {
+        self.feed_permits = Arc::new(Semaphore::new(feed_concurrency.max(1)));
+        self
+    }
+
+    /// Keeps trains standing still at terminals and yards (see
+    /// [`TrainPosition::is_idle`]) in [`GtfsHandler::get_train_positions`], for debugging
+    pub fn with_idle_trains(mut self, include_idle_trains: bool) -> Self {
+        self.include_idle_trains = include_idle_trains;
+        self
+    }
+
+    /// Looks up a single station by parent (`L08`) or directional (`L08N`) stop ID
+    ///
+    /// `train_approaching` is always `false`; see [`train_approaching`] to fill it
+    /// in from current positions.
+    ///
+    /// # Returns
+    /// - `None` if neither platform of the station has known coordinates
+    pub fn station(&self, stop_id: &str) -> Option<StopInfo> {
+        let tables = self.stop_tables.read();
+        let stop_locations = &tables.locations;
+        let parent = if stop_locations.contains_key(&format!("{}N", stop_id))
+            || stop_locations.contains_key(&format!("{}S", stop_id))
+        {
+            stop_id
+        } else {
+            parent_stop_id(stop_id)
+        };
+        let (platform, &(latitude, longitude)) = ["N", "S"].iter().find_map(|direction| {
+            stop_locations.get_key_value(&format!("{}{}", parent, direction))
+        })?;
+
+        Some(StopInfo {
+            stop_id: parent.to_string(),
+            stop_name: tables.names.get(platform).cloned(),
+            latitude,
+            longitude,
+            routes: tables.routes.get(parent).cloned().unwrap_or_default(),
+            borough: tables.boroughs.get(parent).copied(),
+            train_approaching: false,
         })
     }
 
+    /// Feeds polled by [`GtfsHandler::get_train_positions`]
+    pub fn feeds(&self) -> &[&'static FeedInfo] {
+        &self.feeds
+    }
+
     /// Fetches current train positions from all GTFS feeds
     ///
-    /// Queries each MTA GTFS feed URL, processes the protobuf responses,
-    /// and calculates current train positions based on timing data.
+    /// Queries all selected MTA GTFS feeds concurrently, processes the protobuf
+    /// responses, and calculates current train positions based on timing data.
+    /// Positions are merged in feed order. A feed that fails is logged, recorded
+    /// in `feed_health`, and skipped so the remaining feeds still contribute
+    /// positions. Trains between stops outside NYC (see [`StopLocation::is_valid_nyc`])
+    /// are logged and dropped, as are idle trains (see [`TrainPosition::is_idle`])
+    /// unless [`GtfsHandler::with_idle_trains`] keeps them. Trains that haven't
+    /// left their origin yet are kept apart in [`TrainPositions::scheduled`].
+    ///
+    /// # Arguments
+    /// * `feed_health` - Per-feed health map, keyed by feed key, updated with each outcome
+    ///
+    /// # Returns
+    /// - `TrainPositions` - Current train positions and how many feeds failed
+    pub async fn get_train_positions(
+        &self,
+        feed_health: &Mutex<HashMap<String, FeedHealth>>,
+    ) -> TrainPositions {
+        let current_time = Utc::now().timestamp();
+        let feeds = self.fetch_feeds(&self.feeds, feed_health).await;
+        let feeds_failed = self.feeds.len() - feeds.len();
+        let tables = self.stop_tables.read();
+        let positions: Vec<TrainPosition> = feeds
+            .iter()
+            .flat_map(|feed| {
+                positions_from_feed(
+                    feed,
+                    &tables.locations,
+                    &tables.names,
+                    current_time,
+                    self.segment_limits,
+                )
+            })
+            .filter(|position| {
+                let valid = position.from_stop.is_valid_nyc() && position.to_stop.is_valid_nyc();
+                if !valid {
+                    warn!(
+                        "Skipping train {} between {} and {}, outside NYC",
+                        position.trip_id, position.from_stop.stop_id, position.to_stop.stop_id
+                    );
+                }
+                valid
+            })
+            .filter(|position| self.include_idle_trains || !position.is_idle())
+            .collect();
+        let (scheduled, positions): (Vec<_>, Vec<_>) = positions
+            .into_iter()
+            .partition(|position| position.start_time > current_time);
+
+        info!(
+            "Found {} trains in transit and {} scheduled",
+            positions.len(),
+            scheduled.len()
+        );
+        TrainPositions {
+            positions,
+            scheduled,
+            feeds_total: self.feeds.len(),
+            feeds_failed,
+        }
+    }
+
+    /// Fetches the next departures from `station`, grouped by route and direction
+    ///
+    /// Only the selected feeds carrying one of the station's routes are
+    /// fetched, so a station without known routes has no departures. Failed
+    /// feeds are handled as in [`GtfsHandler::get_train_positions`]. At most
+    /// [`DEPARTURES_PER_DIRECTION`] departures are kept per group.
+    ///
+    /// # Arguments
+    /// * `station` - Station from [`GtfsHandler::station`]
+    /// * `feed_health` - Per-feed health map, updated with each outcome
+    pub async fn get_departures(
+        &self,
+        station: &StopInfo,
+        feed_health: &Mutex<HashMap<String, FeedHealth>>,
+    ) -> Vec<DepartureGroup> {
+        let feeds: Vec<&'static FeedInfo> = self
+            .feeds
+            .iter()
+            .copied()
+            .filter(|feed| {
+                station
+                    .routes
+                    .iter()
+                    .any(|route| feed_for_route(route) == Some(*feed))
+            })
+            .collect();
+
+        let current_time = Utc::now().timestamp();
+        let fetched = self.fetch_feeds(&feeds, feed_health).await;
+        let tables = self.stop_tables.read();
+        let departures = fetched
+            .iter()
+            .flat_map(|feed| {
+                departures_from_feed(feed, &station.stop_id, &tables.names, current_time)
+            })
+            .collect();
+        departure_board(departures, DEPARTURES_PER_DIRECTION)
+    }
+
+    /// Fetches and decodes `feeds` concurrently, in order, skipping failures
+    ///
+    /// At most [`GtfsHandler::with_feed_concurrency`] fetches run at once. Each
+    /// outcome is recorded in `feed_health`; failures are also logged.
+    async fn fetch_feeds(
+        &self,
+        feeds: &[&'static FeedInfo],
+        feed_health: &Mutex<HashMap<String, FeedHealth>>,
+    ) -> Vec<FeedMessage> {
+        let results = join_all(feeds.iter().map(|feed| async move {
+            let url = feed.url_from(&self.feed_base_url);
+            let _permit = self
+                .feed_permits
+                .acquire()
+                .await
+                .expect("feed semaphore is never closed");
+            let result = self.fetch_feed(&url).await;
+            (feed, url, result)
+        }))
+        .await;
+
+        let mut messages = Vec::new();
+        for (feed, url, result) in results {
+            match result {
+                Ok(message) => {
+                    messages.push(message);
+                    feed_health
+                        .lock()
+                        .entry(feed.key.to_string())
+                        .or_default()
+                        .record_success(Utc::now());
+                }
+                Err(e) => {
+                    error!("Failed to fetch GTFS feed {}: {}", url, e);
+                    feed_health
+                        .lock()
+                        .entry(feed.key.to_string())
+                        .or_default()
+                        .record_failure(e.to_string());
+                }
+            }
+        }
+        messages
+    }
+
+    /// Fetches and decodes the feed with `key` (e.g. `ace`) for inspection
+    ///
+    /// Any feed in [`SUBWAY_FEEDS`] can be fetched, whether or not it is polled,
+    /// and feed health is left untouched.
+    ///
+    /// # Returns
+    /// - `Ok(None)` if no feed has that key
+    ///
+    /// # Errors
+    /// - If the feed request fails, returns an error status or can't be decoded
+    pub async fn fetch_feed_by_key(&self, key: &str) -> Result<Option<FeedMessage>> {
+        let Some(feed) = SUBWAY_FEEDS.iter().find(|feed| feed.key == key) else {
+            return Ok(None);
+        };
+        self.fetch_feed(&feed.url_from(&self.feed_base_url))
+            .await
+            .map(Some)
+    }
+
+    /// Fetches and decodes a single GTFS feed
+    ///
+    /// # Errors
+    /// - If the feed request fails or returns an error status
+    /// - `Error::FeedBadContentType` if the body is HTML (see [`check_feed_body`])
+    /// - If protobuf decoding fails
+    async fn fetch_feed(&self, url: &str) -> Result<FeedMessage> {
+        let response = self.client.get(url).send().await?.error_for_status()?;
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let bytes = response.bytes().await?;
+        check_feed_body(url, content_type.as_deref(), bytes.as_ref())?;
+        decode_feed(bytes.as_ref())
+    }
+
+    /// Fetches the subway alerts feed and keeps the alerts in effect now
+    ///
+    /// Expired and upcoming alerts are dropped; an alert with several active
+    /// periods is kept if any of them contains the current time (see
+    /// [`alert_is_active`]).
     ///
     /// # Returns
-    /// - `Result<Vec<TrainPosition>>` - List of current train positions or error
+    /// - The active alerts, in feed order
+    ///
+    /// # Errors
+    /// - If the alerts feed can't be fetched or decoded
+    pub async fn get_alerts(&self) -> Result<Vec<Alert>> {
+        let url = SUBWAY_ALERTS_FEED.url_from(&self.feed_base_url);
+        let feed = self.fetch_feed(&url).await?;
+        Ok(active_alerts(&feed, Utc::now().timestamp())
+            .cloned()
+            .collect())
+    }
+
+    /// Fetches the subway alerts feed and derives each line's status from it
+    ///
+    /// # Returns
+    /// - The most disruptive status of each line with an alert in effect now
+    ///   (see [`line_statuses_from_alerts`]); lines without one are absent
+    ///
+    /// # Errors
+    /// - If the alerts feed can't be fetched or decoded
+    pub async fn get_line_statuses(&self) -> Result<BTreeMap<&'static str, ServiceStatus>> {
+        let url = SUBWAY_ALERTS_FEED.url_from(&self.feed_base_url);
+        let feed = self.fetch_feed(&url).await?;
+        Ok(line_statuses_from_alerts(&feed, Utc::now().timestamp()))
+    }
+
+    /// Decodes a raw GTFS-realtime payload into the trains currently between stops
+    ///
+    /// # Arguments
+    /// * `bytes` - Protobuf-encoded `FeedMessage`
+    /// * `current_time` - Unix timestamp used to pick each train's active segment
     ///
     /// # Errors
-    /// - If any feed request fails
     /// - If protobuf decoding fails
-    pub async fn get_train_positions(&self) -> Result<Vec<TrainPosition>> {
-        let mut positions = Vec::new();
-        let feeds = vec![
-            "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs", // 1234567
-            "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-ace", // ACE
-            "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-bdfm", // BDFM
-            "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-g", // G
-            "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-jz", // JZ
-            "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-nqrw", // NQRW
-            "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-l", // L
-            "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-si", // Staten Island Railway
-        ];
-
-        for url in feeds {
-            // Print the raw response
-            let response = self.client.get(url).send().await?;
-            // println!("\n=== API RESPONSE for {} ===", url);
-            // println!("Status: {:?}", response.status());
-
-            let bytes = response.bytes().await?;
-            // println!("Got {} bytes", bytes.len());
-
-            // Print stop locations we're looking for
-            // println!("\n=== STOP LOCATIONS WE HAVE ===");
-            // for (stop_id, (lat, lon)) in &self.stop_locations {
-            //     println!("Stop {}: ({}, {})", stop_id, lat, lon);
-            // }
-
-            let current_time = Utc::now().timestamp();
-            // println!("\nCurrent time: {}", current_time);
-
-            let feed = FeedMessage::decode(bytes.as_ref())
-                .map_err(|e| Error::Environment(format!("Failed to decode GTFS feed: {}", e)))?;
-            debug!("Decoded Feed: {:?}", feed);
-
-            // println!("\n=== STOPS IN FEED ===");
-            // for entity in &feed.entity {
-            //     if let Some(trip_update) = &entity.trip_update {
-            //         for stop_time in &trip_update.stop_time_update {
-            //             if let Some(stop_id) = &stop_time.stop_id {
-            //                 println!(
-            //                     "Stop {} - exists in our map: {}",
-            //                     stop_id,
-            //                     self.stop_locations.contains_key(stop_id)
-            //                 );
-            //             }
-            //         }
-            //     }
-            // }
-
-            for entity in feed.entity {
-                if let Some(trip_update) = entity.trip_update {
-                    let trip = &trip_update.trip;
-                    let route_id = trip.route_id.clone().unwrap_or_default();
-                    info!(
-                        "Processing Trip: {} on Route: {}",
-                        trip.trip_id.clone().unwrap_or_default(),
-                        route_id
-                    );
+    pub fn decode_positions(&self, bytes: &[u8], current_time: i64) -> Result<Vec<TrainPosition>> {
+        let tables = self.stop_tables.read();
+        Ok(positions_from_feed(
+            &decode_feed(bytes)?,
+            &tables.locations,
+            &tables.names,
+            current_time,
+            self.segment_limits,
+        ))
+    }
+}
 
-                    for window in trip_update.stop_time_update.windows(2) {
-                        let from_stop = &window[0];
-                        let to_stop = &window[1];
-
-                        let from_time = from_stop
-                            .departure
-                            .as_ref()
-                            .or(from_stop.arrival.as_ref())
-                            .and_then(|t| t.time);
-                        let to_time = to_stop
-                            .arrival
-                            .as_ref()
-                            .or(to_stop.departure.as_ref())
-                            .and_then(|t| t.time);
-
-                        if let (
-                            Some(from_time),
-                            Some(to_time),
-                            Some(from_stop_id),
-                            Some(to_stop_id),
-                        ) = (
-                            from_time,
-                            to_time,
-                            from_stop.stop_id.as_ref(),
-                            to_stop.stop_id.as_ref(),
-                        ) {
-                            debug!(
-                                "From Stop: {}, To Stop: {}, From Time: {}, To Time: {}",
-                                from_stop_id, to_stop_id, from_time, to_time
-                            );
-
-                            if current_time >= from_time && current_time <= to_time {
-                                if let (Some(from_loc), Some(to_loc)) = (
-                                    self.stop_locations.get(from_stop_id),
-                                    self.stop_locations.get(to_stop_id),
-                                ) {
-                                    let progress = (current_time - from_time) as f64
-                                        / (to_time - from_time) as f64;
-
-                                    positions.push(TrainPosition {
-                                        trip_id: trip.trip_id.clone().unwrap_or_default(),
-                                        route_id: route_id.clone(),
-                                        from_stop: StopLocation {
-                                            stop_id: from_stop_id.clone(),
-                                            latitude: from_loc.0,
-                                            longitude: from_loc.1,
-                                        },
-                                        to_stop: StopLocation {
-                                            stop_id: to_stop_id.clone(),
-                                            latitude: to_loc.0,
-                                            longitude: to_loc.1,
-                                        },
-                                        progress,
-                                        start_time: from_time,
-                                        end_time: to_time,
-                                    });
-                                }
-                            }
-                        }
-                    }
-                }
+/// Rejects a feed response that is an HTML page rather than protobuf
+///
+/// The MTA sometimes answers a failing feed with an HTML error page and a 200
+/// status, which would otherwise surface as an obscure decode error. A body is
+/// rejected if its `content_type` is HTML or it starts with `<`; an encoded
+/// `FeedMessage` always starts with its header's field tag instead.
+///
+/// # Errors
+/// - `Error::FeedBadContentType` if the body looks like HTML
+fn check_feed_body(url: &str, content_type: Option<&str>, bytes: &[u8]) -> Result<()> {
+    let html_type = content_type.is_some_and(|content_type| {
+        content_type
+            .trim_start()
+            .to_ascii_lowercase()
+            .starts_with("text/html")
+    });
+    if html_type || bytes.first() == Some(&b'<') {
+        return Err(Error::FeedBadContentType {
+            url: url.to_string(),
+            content_type: content_type.unwrap_or("no content type").to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Decodes a protobuf-encoded `FeedMessage`
+///
+/// # Errors
+/// - `Error::Environment` if decoding fails
+fn decode_feed(bytes: &[u8]) -> Result<FeedMessage> {
+    let feed = FeedMessage::decode(bytes)
+        .map_err(|e| Error::Environment(format!("Failed to decode GTFS feed: {}", e)))?;
+    debug!("Decoded Feed: {:?}", feed);
+    Ok(feed)
+}
+
+/// Extracts the trains currently between two stops from a decoded feed
+///
+/// For every trip update, consecutive stop time updates (in `stop_sequence`
+/// order, see [`ordered_stop_time_updates`]) form segments; a train is emitted
+/// for the segment whose departure/arrival window contains
+/// `current_time`, provided both stops have known coordinates.
+/// Stops are named via [`stop_name`], and a train's occupancy is taken from the
+/// feed's vehicle entity with the same trip ID, if any. Up to
+/// [`MAX_REMAINING_STOPS`] stops after the active segment are listed so
+/// clients can tell which branch the train takes, and the ETA to the last
+/// stop is measured from `current_time`. Segments outside `limits` are skipped.
+/// A trip that hasn't left its origin yet (see [`trip_not_started`]), whose
+/// first segment starts within `limits.scheduled_within_secs` of
+/// `current_time`, is emitted at its origin stop with progress 0.0.
+/// Route IDs are normalized via [`lines::normalize_route`]; one that matches no
+/// known line is kept as reported and flagged with `is_known: false`, and an
+/// express variant such as `6X` is flagged with `express: true`.
+///
+/// Malformed data never panics: a trip update without a trip ID, or with stop
+/// times too far apart to subtract, is logged and skipped while the rest of
+/// the feed is still processed.
+///
+/// # Arguments
+/// * `feed` - Decoded GTFS-realtime feed
+/// * `stop_locations` - `(latitude, longitude)` pairs keyed by directional stop ID
+/// * `stop_names` - Station names keyed by directional stop ID
+/// * `current_time` - Unix timestamp used to pick each train's active segment
+/// * `limits` - Longest believable segment and oldest believable departure
+pub fn positions_from_feed(
+    feed: &FeedMessage,
+    stop_locations: &HashMap<String, (f64, f64)>,
+    stop_names: &HashMap<String, String>,
+    current_time: i64,
+    limits: SegmentLimits,
+) -> Vec<TrainPosition> {
+    let occupancy_by_trip: HashMap<&str, OccupancyStatus> = feed
+        .entity
+        .iter()
+        .filter_map(|e| e.vehicle.as_ref())
+        .filter_map(|vehicle| {
+            let trip_id = vehicle.trip.as_ref()?.trip_id.as_deref()?;
+            Some((trip_id, occupancy_status(vehicle.occupancy_status?)?))
+        })
+        .collect();
+
+    let mut positions = Vec::new();
+    for entity in &feed.entity {
+        let Some(trip_update) = entity.trip_update.as_ref() else {
+            continue;
+        };
+        match trip_positions(
+            trip_update,
+            &occupancy_by_trip,
+            stop_locations,
+            stop_names,
+            current_time,
+            limits,
+        ) {
+            Ok(trip) => positions.extend(trip),
+            Err(reason) => warn!("Skipping entity {}: {}", entity.id, reason),
+        }
+    }
+
+    positions
+}
+
+/// Whether a trip hasn't left its origin by `current_time`
+///
+/// NYCT feeds drop the stops a train has passed, so `first`, the first
+/// remaining stop time update, is usually the train's next stop rather than
+/// its origin. It only counts as the origin when its `stop_sequence` is the
+/// trip's first (0 or 1). Otherwise the trip descriptor's `start_date` and
+/// `start_time` must be after `current_time`; a trip without them is assumed
+/// to be running.
+fn trip_not_started(
+    trip: &gtfs_rt::TripDescriptor,
+    first: &trip_update::StopTimeUpdate,
+    current_time: i64,
+) -> bool {
+    if let Some(stop_sequence) = first.stop_sequence {
+        return stop_sequence <= 1;
+    }
+    match (trip.start_date.as_deref(), trip.start_time.as_deref()) {
+        (Some(start_date), Some(start_time)) => {
+            trip_start_timestamp(start_date, start_time).is_some_and(|start| start > current_time)
+        }
+        _ => false,
+    }
+}
+
+/// Unix timestamp of a trip descriptor's `start_date` (`YYYYMMDD`) and
+/// `start_time` (`HH:MM:SS`, past 24:00:00 for trips after midnight), which
+/// are New York local time
+///
+/// # Returns
+/// - `None` if either is malformed
+fn trip_start_timestamp(start_date: &str, start_time: &str) -> Option<i64> {
+    let date = chrono::NaiveDate::parse_from_str(start_date, "%Y%m%d").ok()?;
+    let mut fields = start_time.split(':').map(|field| field.parse::<i64>().ok());
+    let (Some(Some(hours)), Some(Some(minutes)), Some(Some(seconds)), None) =
+        (fields.next(), fields.next(), fields.next(), fields.next())
+    else {
+        return None;
+    };
+    if !(0..60).contains(&minutes) || !(0..60).contains(&seconds) || hours < 0 {
+        return None;
+    }
+    let midnight = date.and_hms_opt(0, 0, 0)?.and_utc().timestamp();
+    Some(midnight + hours * 3600 + minutes * 60 + seconds - new_york_utc_offset_secs(date))
+}
+
+/// Seconds New York local time is ahead of UTC on `date`: -4 hours from the
+/// second Sunday in March to the first Sunday in November, -5 hours otherwise
+fn new_york_utc_offset_secs(date: chrono::NaiveDate) -> i64 {
+    use chrono::{Datelike, Weekday};
+    let sunday = |month, n| {
+        chrono::NaiveDate::from_weekday_of_month_opt(date.year(), month, Weekday::Sun, n)
+    };
+    match (sunday(3, 2), sunday(11, 1)) {
+        (Some(dst_start), Some(dst_end)) if date >= dst_start && date < dst_end => -4 * 3600,
+        _ => -5 * 3600,
+    }
+}
+
+/// Turns one trip update into the position of its train, if it is between stops
+///
+/// See [`positions_from_feed`] for how the active segment is picked.
+///
+/// # Errors
+/// - A description of the problem if the trip has no trip ID or its stop
+///   times are too far apart to subtract
+fn trip_positions(
+    trip_update: &gtfs_rt::TripUpdate,
+    occupancy_by_trip: &HashMap<&str, OccupancyStatus>,
+    stop_locations: &HashMap<String, (f64, f64)>,
+    stop_names: &HashMap<String, String>,
+    current_time: i64,
+    limits: SegmentLimits,
+) -> std::result::Result<Vec<TrainPosition>, String> {
+    let trip_id = trip_update
+        .trip
+        .trip_id
+        .as_deref()
+        .filter(|trip_id| !trip_id.is_empty())
+        .ok_or("trip update has no trip ID")?;
+    let feed_route_id = trip_update.trip.route_id.as_deref().unwrap_or_default();
+    debug!("Processing Trip: {} on Route: {}", trip_id, feed_route_id);
+    let known_route_id = lines::normalize_route(feed_route_id);
+    let route_id = known_route_id.unwrap_or(feed_route_id);
+    let direction = trip_direction(trip_id);
+    let occupancy = occupancy_by_trip.get(trip_id).copied();
+    let updates = ordered_stop_time_updates(&trip_update.stop_time_update);
+    let destination_stop_id = updates.last().and_then(|update| update.stop_id.as_deref());
+
+    let not_started = updates
+        .first()
+        .is_some_and(|first| trip_not_started(&trip_update.trip, first, current_time));
+
+    let mut positions = Vec::new();
+    for (index, window) in updates.windows(2).enumerate() {
+        let from_stop = &window[0];
+        let to_stop = &window[1];
+
+        let from_time = from_stop
+            .departure
+            .as_ref()
+            .or(from_stop.arrival.as_ref())
+            .and_then(|t| t.time);
+        let to_time = to_stop
+            .arrival
+            .as_ref()
+            .or(to_stop.departure.as_ref())
+            .and_then(|t| t.time);
+
+        let (Some(from_time), Some(to_time), Some(from_stop_id), Some(to_stop_id)) = (
+            from_time,
+            to_time,
+            from_stop.stop_id.as_deref(),
+            to_stop.stop_id.as_deref(),
+        ) else {
+            continue;
+        };
+        debug!(
+            "From Stop: {}, To Stop: {}, From Time: {}, To Time: {}",
+            from_stop_id, to_stop_id, from_time, to_time
+        );
+
+        let scheduled = index == 0 && not_started && limits.schedules(from_time, current_time);
+        if !scheduled && (current_time < from_time || current_time > to_time) {
+            continue;
+        }
+        // With current_time inside the window, elapsed can't exceed duration
+        let duration = to_time.checked_sub(from_time).ok_or_else(|| {
+            format!(
+                "stop times {} and {} of trip {} are too far apart",
+                from_time, to_time, trip_id
+            )
+        })?;
+        // A scheduled train waits at its origin until from_time
+        let elapsed = if scheduled {
+            0
+        } else {
+            current_time - from_time
+        };
+        if !limits.allows(duration, elapsed) {
+            continue;
+        }
+
+        let (Some(from_loc), Some(to_loc)) = (
+            stop_locations.get(from_stop_id),
+            stop_locations.get(to_stop_id),
+        ) else {
+            continue;
+        };
+        // A zero-length segment has already been completed
+        let progress = if scheduled {
+            0.0
+        } else if duration > 0 {
+            elapsed as f64 / duration as f64
+        } else {
+            1.0
+        };
+
+        let mut position = TrainPosition {
+            trip_id: trip_id.to_string(),
+            route_id: route_id.to_string(),
+            is_known: known_route_id.is_some(),
+            express: lines::is_express(feed_route_id),
+            from_stop: StopLocation::new(
+                from_stop_id.to_string(),
+                Some(stop_name(stop_names, from_stop_id)),
+                from_loc.0,
+                from_loc.1,
+            ),
+            to_stop: StopLocation::new(
+                to_stop_id.to_string(),
+                Some(stop_name(stop_names, to_stop_id)),
+                to_loc.0,
+                to_loc.1,
+            ),
+            progress,
+            start_time: from_time,
+            end_time: to_time,
+            direction,
+            destination_stop_id: destination_stop_id.map(str::to_string),
+            destination_name: destination_stop_id.map(|stop_id| stop_name(stop_names, stop_id)),
+            occupancy,
+            remaining_stops: updates[index + 2..]
+                .iter()
+                .filter_map(|update| update.stop_id.clone())
+                .take(MAX_REMAINING_STOPS)
+                .collect(),
+            eta_to_destination_secs: eta_to_destination(&updates[index + 1..], current_time),
+            speed_kmh: None,
+        };
+        position.speed_kmh = position.speed_kmh();
+        positions.push(position);
+    }
+
+    Ok(positions)
+}
+
+/// A trip's stop time updates in the order the train visits them
+///
+/// Feeds can list updates out of order. When every update has a
+/// `stop_sequence` they are sorted by it; otherwise the feed order is kept, as
+/// it is when the updates are already in order.
+fn ordered_stop_time_updates(
+    updates: &[trip_update::StopTimeUpdate],
+) -> Cow<'_, [trip_update::StopTimeUpdate]> {
+    let sequences: Option<Vec<u32>> = updates.iter().map(|update| update.stop_sequence).collect();
+    match sequences {
+        Some(sequences) if sequences.windows(2).any(|pair| pair[0] > pair[1]) => {
+            let mut sorted = updates.to_vec();
+            sorted.sort_by_key(|update| update.stop_sequence);
+            Cow::Owned(sorted)
+        }
+        _ => Cow::Borrowed(updates),
+    }
+}
+
+/// Extracts the upcoming departures from the station `stop_id`
+///
+/// Every stop time update at one of the station's platforms, predicted at or
+/// after `current_time`, is a departure, except a trip's last stop, where the
+/// train terminates. The departure time falls back to the arrival time. Both
+/// platforms (`N` and `S`) are included whichever one `stop_id` names, and
+/// each departure is tagged with its direction.
+///
+/// # Arguments
+/// * `feed` - Decoded GTFS-realtime feed
+/// * `stop_id` - Station stop ID, with or without the `N`/`S` suffix
+/// * `stop_names` - Station names keyed by directional stop ID
+/// * `current_time` - Unix timestamp; earlier departures are left out
+pub fn departures_from_feed(
+    feed: &FeedMessage,
+    stop_id: &str,
+    stop_names: &HashMap<String, String>,
+    current_time: i64,
+) -> Vec<Departure> {
+    let station_id = parent_stop_id(stop_id);
+    let mut departures = Vec::new();
+    for trip_update in feed.entity.iter().filter_map(|e| e.trip_update.as_ref()) {
+        let Some(trip_id) = trip_update
+            .trip
+            .trip_id
+            .as_deref()
+            .filter(|trip_id| !trip_id.is_empty())
+        else {
+            continue;
+        };
+        let route_id = trip_update.trip.route_id.as_deref().unwrap_or_default();
+        let Some((last, updates)) = trip_update.stop_time_update.split_last() else {
+            continue;
+        };
+        let destination_name = last
+            .stop_id
+            .as_deref()
+            .map(|stop_id| stop_name(stop_names, stop_id));
+
+        for update in updates {
+            let Some(stop_id) = update.stop_id.as_deref() else {
+                continue;
+            };
+            if parent_stop_id(stop_id) != station_id {
+                continue;
             }
+            let Some(time) = update
+                .departure
+                .as_ref()
+                .or(update.arrival.as_ref())
+                .and_then(|t| t.time)
+                .filter(|&time| time >= current_time)
+            else {
+                continue;
+            };
+
+            departures.push(Departure {
+                trip_id: trip_id.to_string(),
+                route_id: route_id.to_string(),
+                stop_id: stop_id.to_string(),
+                direction: stop_direction(stop_id).or_else(|| trip_direction(trip_id)),
+                time,
+                destination_name: destination_name.clone(),
+            });
+        }
+    }
+    departures
+}
+
+/// Groups `departures` by route and direction, keeping the soonest `per_direction` of each
+///
+/// Groups are ordered by route, then direction.
+pub fn departure_board(
+    mut departures: Vec<Departure>,
+    per_direction: usize,
+) -> Vec<DepartureGroup> {
+    departures.sort_by_key(|departure| departure.time);
+
+    let mut groups: BTreeMap<(String, Option<Direction>), Vec<Departure>> = BTreeMap::new();
+    for departure in departures {
+        let group = groups
+            .entry((departure.route_id.clone(), departure.direction))
+            .or_default();
+        if group.len() < per_direction {
+            group.push(departure);
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|((route_id, direction), departures)| DepartureGroup {
+            route_id,
+            direction,
+            departures,
+        })
+        .collect()
+}
+
+/// Seconds from `current_time` until the last of `upcoming` stops is reached
+///
+/// `upcoming` starts with the stop the train is heading to. The time left on
+/// the current segment is added to each following stop-to-stop interval, using
+/// predicted arrival times (falling back to departures).
+///
+/// # Returns
+/// - `None` if any upcoming stop lacks a predicted time, or the times are too
+///   far apart to add up
+fn eta_to_destination(upcoming: &[trip_update::StopTimeUpdate], current_time: i64) -> Option<i64> {
+    let times = upcoming
+        .iter()
+        .map(|update| {
+            update
+                .arrival
+                .as_ref()
+                .or(update.departure.as_ref())
+                .and_then(|t| t.time)
+        })
+        .collect::<Option<Vec<i64>>>()?;
+
+    let next_stop = *times.first()?;
+    let intervals = times.windows(2).try_fold(0i64, |total, pair| {
+        total.checked_add(pair[1].checked_sub(pair[0])?)
+    })?;
+    next_stop.checked_sub(current_time)?.checked_add(intervals)
+}
+
+/// Converts a raw GTFS-realtime occupancy value, ignoring values the spec doesn't define
+fn occupancy_status(value: i32) -> Option<OccupancyStatus> {
+    use vehicle_position::OccupancyStatus as Gtfs;
+
+    Some(match Gtfs::from_i32(value)? {
+        Gtfs::Empty => OccupancyStatus::Empty,
+        Gtfs::ManySeatsAvailable => OccupancyStatus::ManySeatsAvailable,
+        Gtfs::FewSeatsAvailable => OccupancyStatus::FewSeatsAvailable,
+        Gtfs::StandingRoomOnly => OccupancyStatus::StandingRoomOnly,
+        Gtfs::CrushedStandingRoomOnly => OccupancyStatus::CrushedStandingRoomOnly,
+        Gtfs::Full => OccupancyStatus::Full,
+        Gtfs::NotAcceptingPassengers => OccupancyStatus::NotAcceptingPassengers,
+        Gtfs::NoDataAvailable => OccupancyStatus::NoDataAvailable,
+        Gtfs::NotBoardable => OccupancyStatus::NotBoardable,
+    })
+}
+
+/// Status an alert puts the lines it names in, if any
+///
+/// Planned reroutes (`MODIFIED_SERVICE`, `DETOUR`) are [`ServiceStatus::PlannedWork`]
+/// rather than delays; suspensions, reduced service and significant delays are
+/// [`ServiceStatus::Delays`]. Other effects, or an alert without one, don't change
+/// the line's status.
+pub fn alert_status(alert: &Alert) -> Option<ServiceStatus> {
+    use alert::Effect;
+
+    match Effect::from_i32(alert.effect?)? {
+        Effect::NoService | Effect::ReducedService | Effect::SignificantDelays => {
+            Some(ServiceStatus::Delays)
+        }
+        Effect::ModifiedService | Effect::Detour => Some(ServiceStatus::PlannedWork),
+        _ => None,
+    }
+}
+
+/// Whether `alert` is in effect at `current_time` (Unix seconds)
+///
+/// An alert without an `active_period` is ongoing. Otherwise it is active if any
+/// of its periods contains `current_time`, a period missing its start or end
+/// being open on that side.
+pub fn alert_is_active(alert: &Alert, current_time: i64) -> bool {
+    alert.active_period.is_empty()
+        || alert.active_period.iter().any(|period| {
+            let upcoming = period
+                .start
+                .is_some_and(|start| start as i64 > current_time);
+            let ended = period.end.is_some_and(|end| (end as i64) < current_time);
+            !upcoming && !ended
+        })
+}
+
+/// Alerts in `feed` that are in effect at `current_time` (see [`alert_is_active`])
+pub fn active_alerts(feed: &FeedMessage, current_time: i64) -> impl Iterator<Item = &Alert> {
+    feed.entity
+        .iter()
+        .filter_map(|entity| entity.alert.as_ref())
+        .filter(move |alert| alert_is_active(alert, current_time))
+}
+
+/// Most disruptive [`alert_status`] of each line named by an alert in `feed`
+/// active at `current_time`
+///
+/// Route IDs are resolved with [`lines::normalize_route`]; alerts naming only
+/// unknown routes, stops or trips are ignored, as are expired and upcoming
+/// alerts (see [`active_alerts`]). Lines without an alert are absent.
+pub fn line_statuses_from_alerts(
+    feed: &FeedMessage,
+    current_time: i64,
+) -> BTreeMap<&'static str, ServiceStatus> {
+    let mut statuses = BTreeMap::new();
+    for alert in active_alerts(feed, current_time) {
+        let Some(status) = alert_status(alert) else {
+            continue;
+        };
+        let routes = alert
+            .informed_entity
+            .iter()
+            .filter_map(|selector| lines::normalize_route(selector.route_id.as_deref()?));
+        for route in routes {
+            let worst = statuses.entry(route).or_insert(status);
+            *worst = (*worst).max(status);
+        }
+    }
+    statuses
+}
+
+/// Derives the direction of travel from an NYCT trip ID
+///
+/// NYCT trip IDs look like `063350_L..N01R`: the origin time in hundredths of a
+/// minute, an underscore, the route, one or two dots, then `N` or `S` followed
+/// by the shape path. Any trip ID without that shape yields `None`.
+pub fn trip_direction(trip_id: &str) -> Option<Direction> {
+    let (_, trip) = trip_id.split_once('_')?;
+    let (route, path) = trip.split_once('.')?;
+    if route.is_empty() {
+        return None;
+    }
+    match path.trim_start_matches('.').chars().next()? {
+        'N' => Some(Direction::North),
+        'S' => Some(Direction::South),
+        _ => None,
+    }
+}
+
+/// Direction served by a directional platform stop ID such as `L08N`
+fn stop_direction(stop_id: &str) -> Option<Direction> {
+    if parent_stop_id(stop_id) == stop_id {
+        return None;
+    }
+    match stop_id.chars().last()? {
+        'N' => Some(Direction::North),
+        _ => Some(Direction::South),
+    }
+}
+
+/// Strips the `N`/`S` direction suffix from a GTFS stop ID
+///
+/// Parent stop IDs end in a digit, so any other ID is returned unchanged.
+pub fn parent_stop_id(stop_id: &str) -> &str {
+    stop_id
+        .strip_suffix(['N', 'S'])
+        .filter(|parent| !parent.is_empty())
+        .unwrap_or(stop_id)
+}
+
+/// Whether any of `positions` is heading to the station `parent_stop_id`
+pub fn train_approaching(positions: &[TrainPosition], parent_stop_id: &str) -> bool {
+    positions
+        .iter()
+        .any(|position| self::parent_stop_id(&position.to_stop.stop_id) == parent_stop_id)
+}
+
+/// Looks up the station name for a directional stop ID
+///
+/// Falls back to the raw stop ID when the station isn't in the cache, so
+/// popups always have something to show.
+pub fn stop_name(stop_names: &HashMap<String, String>, stop_id: &str) -> String {
+    stop_names
+        .get(stop_id)
+        .cloned()
+        .unwrap_or_else(|| stop_id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gtfs_rt::{FeedEntity, FeedHeader, TripDescriptor, TripUpdate, VehiclePosition};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    fn stop_time(stop_id: &str, time: i64) -> trip_update::StopTimeUpdate {
+        let event = trip_update::StopTimeEvent {
+            time: Some(time),
+            ..Default::default()
+        };
+        trip_update::StopTimeUpdate {
+            stop_id: Some(stop_id.to_string()),
+            arrival: Some(event.clone()),
+            departure: Some(event),
+            ..Default::default()
+        }
+    }
+
+    fn trip_entity(
+        trip_id: &str,
+        route_id: &str,
+        updates: Vec<trip_update::StopTimeUpdate>,
+    ) -> FeedEntity {
+        FeedEntity {
+            id: trip_id.to_string(),
+            trip_update: Some(TripUpdate {
+                trip: TripDescriptor {
+                    trip_id: Some(trip_id.to_string()),
+                    route_id: Some(route_id.to_string()),
+                    ..Default::default()
+                },
+                stop_time_update: updates,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn feed(entity: Vec<FeedEntity>) -> FeedMessage {
+        FeedMessage {
+            header: FeedHeader {
+                gtfs_realtime_version: "2.0".to_string(),
+                ..Default::default()
+            },
+            entity,
+        }
+    }
+
+    fn stop_locations() -> HashMap<String, (f64, f64)> {
+        HashMap::from([
+            ("L06N".to_string(), (40.730953, -73.981628)),
+            ("L08N".to_string(), (40.717304, -73.956872)),
+            ("L10N".to_string(), (40.714063, -73.950275)),
+        ])
+    }
+
+    /// Deterministic xorshift bytes, so failures reproduce without a `rand` dependency
+    fn pseudo_random_bytes(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed.max(1);
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_garbage_bytes_never_panic() {
+        let handler = GtfsHandler::from_stop_locations(stop_locations());
+        let now = 1_700_000_000;
+
+        for seed in 0..2_000 {
+            let bytes = pseudo_random_bytes(seed, (seed % 256) as usize);
+            // Errors are fine; panics are not
+            let _ = handler.decode_positions(&bytes, now);
+        }
+
+        let valid = feed(vec![trip_entity(
+            "063350_L..N01R",
+            "L",
+            vec![stop_time("L08N", now - 60), stop_time("L06N", now + 60)],
+        )])
+        .encode_to_vec();
+        for len in 0..=valid.len() {
+            let _ = handler.decode_positions(&valid[..len], now);
+        }
+        assert_eq!(handler.decode_positions(&valid, now).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_extreme_stop_times_never_panic() {
+        let extremes = [i64::MIN, i64::MIN + 1, -1, 0, 1, i64::MAX - 1, i64::MAX];
+        for &from in &extremes {
+            for &to in &extremes {
+                for &now in &extremes {
+                    let feed = feed(vec![trip_entity(
+                        "063350_L..N01R",
+                        "L",
+                        vec![
+                            stop_time("L08N", from),
+                            stop_time("L06N", to),
+                            stop_time("L10N", from),
+                        ],
+                    )]);
+                    let positions = positions_from_feed(
+                        &feed,
+                        &stop_locations(),
+                        &HashMap::new(),
+                        now,
+                        SegmentLimits {
+                            max_segment_secs: i64::MAX,
+                            stale_after_secs: i64::MAX,
+                            scheduled_within_secs: i64::MAX,
+                        },
+                    );
+                    for position in positions {
+                        assert!((0.0..=1.0).contains(&position.progress));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_bad_entity_is_skipped_not_the_feed() {
+        let now = 1_700_000_000;
+        let segment = || vec![stop_time("L08N", now - 60), stop_time("L06N", now + 60)];
+        let mut no_trip_id = trip_entity("", "L", segment());
+        no_trip_id.id = "bad".to_string();
+        let overflowing = trip_entity(
+            "overflow",
+            "L",
+            vec![stop_time("L08N", i64::MIN), stop_time("L06N", i64::MAX)],
+        );
+        let feed = feed(vec![
+            no_trip_id,
+            overflowing,
+            trip_entity("063350_L..N01R", "L", segment()),
+        ]);
+
+        let positions = positions_from_feed(
+            &feed,
+            &stop_locations(),
+            &HashMap::new(),
+            now,
+            SegmentLimits {
+                max_segment_secs: i64::MAX,
+                stale_after_secs: i64::MAX,
+                scheduled_within_secs: i64::MAX,
+            },
+        );
+
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].trip_id, "063350_L..N01R");
+    }
+
+    #[test]
+    fn test_positions_from_feed_active_segment() {
+        let feed = feed(vec![trip_entity(
+            "063350_L..N01R",
+            "L",
+            vec![
+                stop_time("L10N", 1000),
+                stop_time("L08N", 1100),
+                stop_time("L06N", 1200),
+            ],
+        )]);
+
+        let positions = positions_from_feed(
+            &feed,
+            &stop_locations(),
+            &HashMap::new(),
+            1150,
+            SegmentLimits::default(),
+        );
+
+        assert_eq!(positions.len(), 1);
+        let position = &positions[0];
+        assert_eq!(position.trip_id, "063350_L..N01R");
+        assert_eq!(position.route_id, "L");
+        assert_eq!(position.from_stop.stop_id, "L08N");
+        assert_eq!(position.to_stop.stop_id, "L06N");
+        assert_eq!(position.progress, 0.5);
+        assert_eq!(position.start_time, 1100);
+        assert_eq!(position.end_time, 1200);
+    }
+
+    #[test]
+    fn test_positions_normalize_route_ids() {
+        let segment = || {
+            vec![
+                stop_time("L10N", 1000),
+                stop_time("L08N", 1100),
+                stop_time("L06N", 1200),
+            ]
+        };
+        let feed = feed(vec![
+            trip_entity("known", "L", segment()),
+            trip_entity("express", "6X", segment()),
+            trip_entity("unknown", "SS", segment()),
+        ]);
+
+        let positions = positions_from_feed(
+            &feed,
+            &stop_locations(),
+            &HashMap::new(),
+            1150,
+            SegmentLimits::default(),
+        );
+        let routes: HashMap<&str, (&str, bool)> = positions
+            .iter()
+            .map(|position| {
+                (
+                    position.trip_id.as_str(),
+                    (position.route_id.as_str(), position.is_known),
+                )
+            })
+            .collect();
+
+        assert_eq!(routes["known"], ("L", true));
+        assert_eq!(routes["express"], ("6", true));
+        assert_eq!(routes["unknown"], ("SS", false));
+    }
+
+    #[test]
+    fn test_express_trip_gets_express_bullet() {
+        let segment = || vec![stop_time("L08N", 1100), stop_time("L06N", 1200)];
+        let feed = feed(vec![
+            trip_entity("local", "6", segment()),
+            trip_entity("express", "6X", segment()),
+        ]);
+
+        let positions = positions_from_feed(
+            &feed,
+            &stop_locations(),
+            &HashMap::new(),
+            1150,
+            SegmentLimits::default(),
+        );
+        let bullets: HashMap<&str, (bool, String)> = positions
+            .iter()
+            .map(|position| {
+                let bullet = position.to_geojson_feature().properties.bullet;
+                (position.trip_id.as_str(), (position.express, bullet))
+            })
+            .collect();
+
+        assert_eq!(bullets["local"], (false, "6".to_string()));
+        assert_eq!(bullets["express"], (true, "6◆".to_string()));
+    }
+
+    fn vehicle_entity(trip_id: &str, occupancy: vehicle_position::OccupancyStatus) -> FeedEntity {
+        FeedEntity {
+            id: format!("{}-vehicle", trip_id),
+            vehicle: Some(VehiclePosition {
+                trip: Some(TripDescriptor {
+                    trip_id: Some(trip_id.to_string()),
+                    ..Default::default()
+                }),
+                occupancy_status: Some(occupancy as i32),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_positions_list_remaining_stops() {
+        let mut updates = vec![stop_time("L10N", 1000), stop_time("L08N", 1100)];
+        updates.extend((0..12).map(|i| stop_time(&format!("L{:02}N", 20 + i), 1200 + i * 100)));
+        let feed = feed(vec![trip_entity("063350_L..N01R", "L", updates)]);
+
+        let positions = positions_from_feed(
+            &feed,
+            &stop_locations(),
+            &HashMap::new(),
+            1050,
+            SegmentLimits::default(),
+        );
+
+        let remaining = &positions[0].remaining_stops;
+        assert_eq!(remaining.len(), MAX_REMAINING_STOPS);
+        assert_eq!(remaining[0], "L20N");
+        assert_eq!(remaining[9], "L29N");
+    }
+
+    #[test]
+    fn test_eta_sums_remaining_intervals() {
+        let feed = feed(vec![trip_entity(
+            "063350_L..N01R",
+            "L",
+            vec![
+                stop_time("L10N", 1000),
+                stop_time("L08N", 1100),
+                stop_time("L06N", 1250),
+                stop_time("L03N", 1400),
+            ],
+        )]);
+
+        let positions = positions_from_feed(
+            &feed,
+            &stop_locations(),
+            &HashMap::new(),
+            1040,
+            SegmentLimits::default(),
+        );
+
+        // 60s left to L08N, then 150s and 150s more to L03N
+        assert_eq!(positions[0].eta_to_destination_secs, Some(60 + 150 + 150));
+    }
+
+    #[test]
+    fn test_eta_skipped_without_complete_timing() {
+        let mut untimed = stop_time("L03N", 0);
+        untimed.arrival = None;
+        untimed.departure = None;
+        let feed = feed(vec![trip_entity(
+            "063350_L..N01R",
+            "L",
+            vec![stop_time("L10N", 1000), stop_time("L08N", 1100), untimed],
+        )]);
+
+        let positions = positions_from_feed(
+            &feed,
+            &stop_locations(),
+            &HashMap::new(),
+            1040,
+            SegmentLimits::default(),
+        );
+
+        assert_eq!(positions[0].eta_to_destination_secs, None);
+    }
+
+    #[test]
+    fn test_positions_at_last_segment_have_no_remaining_stops() {
+        let feed = feed(vec![trip_entity(
+            "063350_L..N01R",
+            "L",
+            vec![stop_time("L10N", 1000), stop_time("L08N", 1100)],
+        )]);
+
+        let positions = positions_from_feed(
+            &feed,
+            &stop_locations(),
+            &HashMap::new(),
+            1050,
+            SegmentLimits::default(),
+        );
+
+        assert!(positions[0].remaining_stops.is_empty());
+    }
+
+    #[test]
+    fn test_positions_include_vehicle_occupancy() {
+        let feed = feed(vec![
+            trip_entity(
+                "063350_L..N01R",
+                "L",
+                vec![stop_time("L10N", 1000), stop_time("L08N", 1100)],
+            ),
+            trip_entity(
+                "064000_L..N01R",
+                "L",
+                vec![stop_time("L08N", 1000), stop_time("L06N", 1100)],
+            ),
+            vehicle_entity(
+                "063350_L..N01R",
+                vehicle_position::OccupancyStatus::StandingRoomOnly,
+            ),
+        ]);
+
+        let positions = positions_from_feed(
+            &feed,
+            &stop_locations(),
+            &HashMap::new(),
+            1050,
+            SegmentLimits::default(),
+        );
+
+        assert_eq!(positions.len(), 2);
+        assert_eq!(
+            positions[0].occupancy,
+            Some(OccupancyStatus::StandingRoomOnly)
+        );
+        assert_eq!(positions[1].occupancy, None);
+    }
+
+    #[test]
+    fn test_occupancy_status_rejects_unknown_values() {
+        assert_eq!(occupancy_status(5), Some(OccupancyStatus::Full));
+        assert_eq!(occupancy_status(42), None);
+    }
+
+    fn alert_entity(id: &str, effect: gtfs_rt::alert::Effect, routes: &[&str]) -> FeedEntity {
+        FeedEntity {
+            id: id.to_string(),
+            alert: Some(Alert {
+                effect: Some(effect as i32),
+                informed_entity: routes
+                    .iter()
+                    .map(|route| gtfs_rt::EntitySelector {
+                        route_id: Some(route.to_string()),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_planned_work_alert_is_not_delays() {
+        use gtfs_rt::alert::Effect;
+
+        let feed = feed(vec![
+            alert_entity("weekend", Effect::ModifiedService, &["L", "6X"]),
+            alert_entity("detour", Effect::Detour, &["G"]),
+            alert_entity("signal", Effect::SignificantDelays, &["6"]),
+            alert_entity("elevator", Effect::AccessibilityIssue, &["A"]),
+        ]);
+
+        let statuses = line_statuses_from_alerts(&feed, 1_704_110_400);
+
+        assert_eq!(statuses["L"], ServiceStatus::PlannedWork);
+        assert_eq!(statuses["G"], ServiceStatus::PlannedWork);
+        // Delays outrank planned work on the same line
+        assert_eq!(statuses["6"], ServiceStatus::Delays);
+        assert!(!statuses.contains_key("A"));
+        assert!(!statuses["L"].has_delays());
+    }
+
+    #[tokio::test]
+    async fn test_expired_alerts_are_filtered() {
+        use gtfs_rt::alert::Effect;
+
+        let now = Utc::now().timestamp();
+        let period = |start: Option<i64>, end: Option<i64>| gtfs_rt::TimeRange {
+            start: start.map(|start| start as u64),
+            end: end.map(|end| end as u64),
+        };
+        let with_periods = |mut entity: FeedEntity, periods| {
+            entity.alert.as_mut().unwrap().active_period = periods;
+            entity
+        };
+        let feed = feed(vec![
+            with_periods(
+                alert_entity("expired", Effect::SignificantDelays, &["A"]),
+                vec![period(Some(now - 7200), Some(now - 3600))],
+            ),
+            with_periods(
+                alert_entity("current", Effect::SignificantDelays, &["L"]),
+                // An earlier period ended, a later open-ended one covers now
+                vec![
+                    period(Some(now - 7200), Some(now - 3600)),
+                    period(Some(now - 60), None),
+                ],
+            ),
+            with_periods(
+                alert_entity("upcoming", Effect::ModifiedService, &["G"]),
+                vec![period(Some(now + 3600), Some(now + 7200))],
+            ),
+            alert_entity("ongoing", Effect::Detour, &["7"]),
+        ]);
+
+        let base_url = spawn_feed_server(feed.clone(), Duration::ZERO, &[]).await;
+        let handler = GtfsHandler::from_stop_locations(HashMap::new()).with_feed_base_url(base_url);
+
+        let alerts = handler.get_alerts().await.unwrap();
+        let routes: Vec<&str> = alerts
+            .iter()
+            .filter_map(|alert| alert.informed_entity[0].route_id.as_deref())
+            .collect();
+        assert_eq!(routes, ["L", "7"]);
+
+        let statuses = handler.get_line_statuses().await.unwrap();
+        assert_eq!(statuses.keys().copied().collect::<Vec<_>>(), ["7", "L"]);
+        assert_eq!(statuses["L"], ServiceStatus::Delays);
+    }
+
+    #[test]
+    fn test_trip_direction() {
+        assert_eq!(trip_direction("063350_L..N01R"), Some(Direction::North));
+        assert_eq!(trip_direction("128200_6..S01X009"), Some(Direction::South));
+        assert_eq!(trip_direction("097550_GS.N01R"), Some(Direction::North));
+        assert_eq!(
+            trip_direction("A20230101WKD_036700_SI..S"),
+            Some(Direction::South)
+        );
+        assert_eq!(trip_direction("063350_L..X01R"), None);
+        assert_eq!(trip_direction("063350_L"), None);
+        assert_eq!(trip_direction("not-a-trip"), None);
+        assert_eq!(trip_direction(""), None);
+    }
+
+    #[test]
+    fn test_positions_include_direction_and_destination() {
+        let stop_names = HashMap::from([("L06N".to_string(), "1 Av".to_string())]);
+        let feed = feed(vec![trip_entity(
+            "063350_L..N01R",
+            "L",
+            vec![
+                stop_time("L10N", 1000),
+                stop_time("L08N", 1100),
+                stop_time("L06N", 1200),
+            ],
+        )]);
+
+        let positions = positions_from_feed(
+            &feed,
+            &stop_locations(),
+            &stop_names,
+            1050,
+            SegmentLimits::default(),
+        );
+
+        assert_eq!(positions[0].direction, Some(Direction::North));
+        assert_eq!(positions[0].destination_stop_id.as_deref(), Some("L06N"));
+        assert_eq!(positions[0].destination_name.as_deref(), Some("1 Av"));
+    }
+
+    #[test]
+    fn test_stop_name_lookup_falls_back_to_stop_id() {
+        let stop_names = HashMap::from([("L08N".to_string(), "Bedford Av".to_string())]);
+
+        assert_eq!(stop_name(&stop_names, "L08N"), "Bedford Av");
+        assert_eq!(stop_name(&stop_names, "L06N"), "L06N");
+
+        let feed = feed(vec![trip_entity(
+            "063350_L..N01R",
+            "L",
+            vec![stop_time("L08N", 1100), stop_time("L06N", 1200)],
+        )]);
+        let positions = positions_from_feed(
+            &feed,
+            &stop_locations(),
+            &stop_names,
+            1150,
+            SegmentLimits::default(),
+        );
+
+        assert_eq!(
+            positions[0].from_stop.stop_name.as_deref(),
+            Some("Bedford Av")
+        );
+        assert_eq!(positions[0].to_stop.stop_name.as_deref(), Some("L06N"));
+    }
+
+    #[test]
+    fn test_shuffled_updates_follow_stop_sequence() {
+        let sequenced = |stop_id, time, stop_sequence| trip_update::StopTimeUpdate {
+            stop_sequence: Some(stop_sequence),
+            ..stop_time(stop_id, time)
+        };
+        let feed = feed(vec![trip_entity(
+            "063350_L..N01R",
+            "L",
+            vec![
+                sequenced("L06N", 1200, 3),
+                sequenced("L10N", 1000, 1),
+                sequenced("L08N", 1100, 2),
+            ],
+        )]);
+
+        let positions = positions_from_feed(
+            &feed,
+            &stop_locations(),
+            &HashMap::new(),
+            1150,
+            SegmentLimits::default(),
+        );
+
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].from_stop.stop_id, "L08N");
+        assert_eq!(positions[0].to_stop.stop_id, "L06N");
+        assert_eq!(positions[0].progress, 0.5);
+        assert_eq!(positions[0].destination_stop_id.as_deref(), Some("L06N"));
+
+        // Without a stop_sequence on every update the feed order is kept
+        let partial = vec![sequenced("L08N", 1100, 2), stop_time("L10N", 1000)];
+        let ordered = ordered_stop_time_updates(&partial);
+        assert!(matches!(ordered, Cow::Borrowed(_)));
+        assert_eq!(ordered[0].stop_id.as_deref(), Some("L08N"));
+    }
+
+    #[test]
+    fn test_positions_from_feed_outside_window() {
+        let feed = feed(vec![trip_entity(
+            "063350_L..N01R",
+            "L",
+            vec![stop_time("L10N", 1000), stop_time("L08N", 1100)],
+        )]);
+
+        assert!(positions_from_feed(
+            &feed,
+            &stop_locations(),
+            &HashMap::new(),
+            1000 - DEFAULT_SCHEDULED_WITHIN_SECS - 1,
+            SegmentLimits::default()
+        )
+        .is_empty());
+        assert!(positions_from_feed(
+            &feed,
+            &stop_locations(),
+            &HashMap::new(),
+            1200,
+            SegmentLimits::default()
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_implausibly_long_segment_is_dropped() {
+        let feed = feed(vec![trip_entity(
+            "063350_L..N01R",
+            "L",
+            vec![stop_time("L08N", 1000), stop_time("L06N", 1000 + 3600)],
+        )]);
+
+        assert!(positions_from_feed(
+            &feed,
+            &stop_locations(),
+            &HashMap::new(),
+            1100,
+            SegmentLimits::default()
+        )
+        .is_empty());
+        let relaxed = SegmentLimits {
+            max_segment_secs: 7200,
+            stale_after_secs: 7200,
+            ..SegmentLimits::default()
+        };
+        assert_eq!(
+            positions_from_feed(&feed, &stop_locations(), &HashMap::new(), 1100, relaxed).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_stale_segment_is_dropped() {
+        let limits = SegmentLimits {
+            max_segment_secs: 600,
+            stale_after_secs: 120,
+            ..SegmentLimits::default()
+        };
+        let feed = feed(vec![trip_entity(
+            "063350_L..N01R",
+            "L",
+            vec![stop_time("L08N", 1000), stop_time("L06N", 1500)],
+        )]);
+
+        assert_eq!(
+            positions_from_feed(&feed, &stop_locations(), &HashMap::new(), 1100, limits).len(),
+            1
+        );
+        assert!(
+            positions_from_feed(&feed, &stop_locations(), &HashMap::new(), 1200, limits).is_empty()
+        );
+    }
+
+    #[test]
+    fn test_positions_from_feed_unknown_stop() {
+        let feed = feed(vec![trip_entity(
+            "063350_L..N01R",
+            "L",
+            vec![stop_time("L10N", 1000), stop_time("X99N", 1100)],
+        )]);
+
+        assert!(positions_from_feed(
+            &feed,
+            &stop_locations(),
+            &HashMap::new(),
+            1050,
+            SegmentLimits::default()
+        )
+        .is_empty());
+    }
+
+    fn fixture_path(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn test_from_stations_file_fixture() {
+        let handler = GtfsHandler::from_stations_file(&fixture_path("stations.json")).unwrap();
+        let stops = handler.stops();
+
+        assert!(!stops.is_empty());
+        let bedford = stops.iter().find(|stop| stop.stop_id == "L08N").unwrap();
+        assert_eq!(bedford.stop_name.as_deref(), Some("Bedford Av"));
+        assert!(stops.iter().any(|stop| stop.stop_id == "L08S"));
+    }
+
+    #[test]
+    fn test_staten_island_trip_resolves_coordinates() {
+        let handler = GtfsHandler::from_stations_file(&fixture_path("stations.json")).unwrap();
+        let now = 1_700_000_000;
+        let feed = feed(vec![trip_entity(
+            "AFA23GEN-1037-Weekday-00_069500_SI..N03R",
+            "SI",
+            vec![stop_time("S30N", now - 60), stop_time("S31N", now + 60)],
+        )]);
+
+        let positions = handler
+            .decode_positions(&feed.encode_to_vec(), now)
+            .unwrap();
+
+        assert_eq!(positions.len(), 1);
+        let train = &positions[0];
+        assert_eq!(train.route_id, "SI");
+        assert!(train.is_known);
+        assert!(train.from_stop.is_valid_nyc() && train.to_stop.is_valid_nyc());
+        assert_eq!(train.to_stop.stop_name.as_deref(), Some("St George"));
+        let station = handler.station("S31").unwrap();
+        assert_eq!(station.routes, ["SI"]);
+        assert_eq!(feed_for_route(&station.routes[0]).unwrap().key, "si");
+    }
+
+    /// Serves the first `rows` fixture stations, paginated by `$limit` and `$offset`
+    async fn spawn_stations_server(rows: usize) -> String {
+        let raw = std::fs::read_to_string(fixture_path("stations.json")).unwrap();
+        let mut stations: Vec<serde_json::Value> = serde_json::from_str(&raw).unwrap();
+        stations.truncate(rows);
+        let app = axum::Router::new().fallback(
+            move |axum::extract::Query(query): axum::extract::Query<HashMap<String, String>>| {
+                let stations = stations.clone();
+                async move {
+                    let limit: usize = query["$limit"].parse().unwrap();
+                    let offset: usize = query["$offset"].parse().unwrap();
+                    let page: Vec<_> = stations.into_iter().skip(offset).take(limit).collect();
+                    axum::Json(page)
+                }
+            },
+        );
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+        format!("http://{}/stations.json", addr)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_station_pages_merges_pages() {
+        let url = spawn_stations_server(3).await;
+        let client = reqwest::Client::new();
+
+        let stations = fetch_station_pages(&client, &url, 2).await.unwrap();
+
+        let ids: Vec<&str> = stations.iter().map(|s| s.gtfs_stop_id.as_str()).collect();
+        assert_eq!(ids.len(), 3);
+        let mut unique = ids.clone();
+        unique.dedup();
+        assert_eq!(unique, ids);
+    }
+
+    #[test]
+    fn test_from_stations_file_missing() {
+        let result = GtfsHandler::from_stations_file(&fixture_path("missing.json"));
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn test_station_accepts_parent_or_directional_id() {
+        let handler = GtfsHandler::from_stations_file(&fixture_path("stations.json")).unwrap();
+
+        for stop_id in ["L08", "L08N", "L08S"] {
+            let station = handler.station(stop_id).unwrap();
+            assert_eq!(station.stop_id, "L08");
+            assert_eq!(station.stop_name.as_deref(), Some("Bedford Av"));
+            assert_eq!(station.routes, ["L"]);
+            assert_eq!(station.borough, Some(Borough::Brooklyn));
+            assert!(!station.train_approaching);
+        }
+        assert!(handler.station("X99").is_none());
+        assert_eq!(handler.borough("101N"), Some(Borough::Bronx));
+        assert_eq!(handler.borough("X99"), None);
+        assert!(handler.station("").is_none());
+    }
+
+    #[test]
+    fn test_refresh_is_visible_through_clones() {
+        let handler = GtfsHandler::from_stop_locations(stop_locations()).with_stop_names(
+            HashMap::from([("L08N".to_string(), "Bedford Av".to_string())]),
+        );
+        let clone = handler.clone();
+        let etag = clone.stations_etag();
+        assert!(clone.station("G14").is_none());
+
+        handler.refresh_stop_tables(StopTables::new(
+            HashMap::from([("G14N".to_string(), (40.74, -73.87))]),
+            HashMap::from([("G14N".to_string(), "Jackson Hts-Roosevelt Av".to_string())]),
+            HashMap::from([("G14".to_string(), vec!["E".to_string(), "F".to_string()])]),
+            HashMap::from([("G14".to_string(), Borough::Queens)]),
+        ));
+
+        // Every table and the ETag change together
+        let station = clone.station("G14").unwrap();
+        assert_eq!(station.latitude, 40.74);
+        assert_eq!(
+            station.stop_name.as_deref(),
+            Some("Jackson Hts-Roosevelt Av")
+        );
+        assert_eq!(station.routes, vec!["E", "F"]);
+        assert_eq!(clone.borough("G14N"), Some(Borough::Queens));
+        assert_eq!(clone.stops().len(), 1);
+        assert_ne!(clone.stations_etag(), etag);
+        assert!(handler.station("L08").is_none());
+    }
+
+    #[test]
+    fn test_stations_etag_follows_stop_names() {
+        let unnamed = GtfsHandler::from_stop_locations(stop_locations());
+        let named = GtfsHandler::from_stop_locations(stop_locations()).with_stop_names(
+            HashMap::from([("L08N".to_string(), "Bedford Av".to_string())]),
+        );
+
+        assert_eq!(
+            unnamed.stations_etag(),
+            GtfsHandler::from_stop_locations(stop_locations()).stations_etag()
+        );
+        assert_ne!(unnamed.stations_etag(), named.stations_etag());
+    }
+
+    #[test]
+    fn test_parent_stop_id() {
+        assert_eq!(parent_stop_id("L08N"), "L08");
+        assert_eq!(parent_stop_id("101S"), "101");
+        assert_eq!(parent_stop_id("L08"), "L08");
+        assert_eq!(parent_stop_id("N"), "N");
+    }
+
+    #[test]
+    fn test_train_approaching_matches_next_stop() {
+        let now = 1_700_000_000;
+        let feed = feed(vec![trip_entity(
+            "063350_L..N01R",
+            "L",
+            vec![stop_time("L08N", now - 60), stop_time("L06N", now + 60)],
+        )]);
+        let positions = positions_from_feed(
+            &feed,
+            &stop_locations(),
+            &HashMap::new(),
+            now,
+            SegmentLimits::default(),
+        );
+
+        assert!(train_approaching(&positions, "L06"));
+        assert!(!train_approaching(&positions, "L08"));
+    }
+
+    #[test]
+    fn test_decode_positions_fixture() {
+        let handler = GtfsHandler::from_stations_file(&fixture_path("stations.json")).unwrap();
+
+        let bytes = include_bytes!("../../tests/fixtures/gtfs-l.pb");
+        let positions = handler.decode_positions(bytes, 1_700_000_000).unwrap();
+
+        assert!(!positions.is_empty());
+        for position in &positions {
+            assert_eq!(position.route_id, "L");
+            assert!((0.0..=1.0).contains(&position.progress));
+            assert_ne!(
+                position.from_stop.stop_name.as_deref(),
+                Some(position.from_stop.stop_id.as_str())
+            );
+        }
+    }
+
+    /// Serves `feed` for every feed path after `delay`, except paths ending in
+    /// one of `failing` which return 500, and returns the server's base URL
+    async fn spawn_feed_server(
+        feed: FeedMessage,
+        delay: Duration,
+        failing: &'static [&'static str],
+    ) -> String {
+        let body = feed.encode_to_vec();
+        let app = axum::Router::new().fallback(move |uri: axum::http::Uri| {
+            let body = body.clone();
+            async move {
+                tokio::time::sleep(delay).await;
+                if failing.iter().any(|path| uri.path().ends_with(path)) {
+                    Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+                } else {
+                    Ok(body)
+                }
+            }
+        });
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+        format!("http://{}/", addr)
+    }
+
+    #[test]
+    fn test_html_bodies_are_rejected() {
+        let url = "http://feeds.test/nyct%2Fgtfs-l";
+        let bytes = feed(vec![]).encode_to_vec();
+        assert!(check_feed_body(url, Some("application/x-protobuf"), &bytes).is_ok());
+        assert!(check_feed_body(url, None, &bytes).is_ok());
+
+        let sniffed = check_feed_body(url, Some("application/octet-stream"), b"<html>");
+        assert!(matches!(
+            sniffed,
+            Err(Error::FeedBadContentType { content_type, .. })
+                if content_type == "application/octet-stream"
+        ));
+        assert!(check_feed_body(url, Some("Text/HTML; charset=utf-8"), b"oops").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_html_error_page_reports_content_type() {
+        let app = axum::Router::new().fallback(|| async {
+            (
+                [(axum::http::header::CONTENT_TYPE, "text/html")],
+                "<html><body>Service Unavailable</body></html>",
+            )
+        });
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+        let handler =
+            GtfsHandler::from_stop_locations(stop_locations()).with_feed_base_url(&base_url);
+
+        match handler.fetch_feed_by_key("l").await {
+            Err(Error::FeedBadContentType { url, content_type }) => {
+                assert!(url.starts_with(&base_url), "{}", url);
+                assert_eq!(content_type, "text/html");
+            }
+            other => panic!("expected FeedBadContentType, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    /// An L train between L08N and L06N right now
+    fn live_feed() -> FeedMessage {
+        let now = Utc::now().timestamp();
+        feed(vec![trip_entity(
+            "063350_L..N01R",
+            "L",
+            vec![stop_time("L08N", now - 60), stop_time("L06N", now + 60)],
+        )])
+    }
+
+    #[test]
+    fn test_stations_outside_nyc_are_skipped() {
+        let station = |stop_id: &str, latitude: f64, longitude: f64| StationResponse {
+            gtfs_stop_id: stop_id.to_string(),
+            stop_name: stop_id.to_string(),
+            daytime_routes: "L".to_string(),
+            borough: "Bk".to_string(),
+            gtfs_latitude: latitude,
+            gtfs_longitude: longitude,
+        };
+
+        let tables = stop_tables(vec![
+            station("L08", 40.717304, -73.956872),
+            station("L10", 0.0, 0.0),
+        ]);
+
+        assert!(tables.locations.contains_key("L08N"));
+        assert!(!tables.locations.contains_key("L10N"));
+        assert!(!tables.names.contains_key("L10S"));
+        assert!(!tables.routes.contains_key("L10"));
+    }
+
+    #[tokio::test]
+    async fn test_get_train_positions_skips_trains_outside_nyc() {
+        let base_url = spawn_feed_server(live_feed(), Duration::ZERO, &[]).await;
+        let mut locations = stop_locations();
+        locations.insert("L06N".to_string(), (0.0, 0.0));
+        let handler = GtfsHandler::from_stop_locations(locations).with_feed_base_url(base_url);
+
+        let trains = handler
+            .get_train_positions(&Mutex::new(HashMap::new()))
+            .await;
+
+        assert!(trains.positions.is_empty(), "{:?}", trains.positions);
+    }
+
+    #[tokio::test]
+    async fn test_idle_trains_are_excluded_unless_included() {
+        let now = Utc::now().timestamp();
+        let base_url = spawn_feed_server(
+            feed(vec![
+                trip_entity(
+                    "063350_L..N01R",
+                    "L",
+                    vec![stop_time("L08N", now - 60), stop_time("L06N", now + 60)],
+                ),
+                // Same stop twice, as a train laid up at a terminal can report
+                trip_entity(
+                    "064000_L..S01R",
+                    "L",
+                    vec![stop_time("L10N", now - 60), stop_time("L10N", now + 60)],
+                ),
+            ]),
+            Duration::ZERO,
+            &[],
+        )
+        .await;
+        let handler =
+            GtfsHandler::from_stop_locations(stop_locations()).with_feed_base_url(base_url);
+        let feed_health = Mutex::new(HashMap::new());
+
+        let moving = handler.get_train_positions(&feed_health).await.positions;
+        assert!(!moving.is_empty());
+        assert!(
+            moving.iter().all(|p| p.trip_id == "063350_L..N01R"),
+            "{:?}",
+            moving
+        );
+
+        let everything = handler
+            .with_idle_trains(true)
+            .get_train_positions(&feed_health)
+            .await
+            .positions;
+        assert!(everything.iter().any(|p| p.trip_id == "064000_L..S01R"));
+    }
+
+    #[tokio::test]
+    async fn test_trip_starting_soon_is_scheduled_at_origin() {
+        let now = Utc::now().timestamp();
+        let mut origin = stop_time("L10N", now + 120);
+        origin.stop_sequence = Some(1);
+        let base_url = spawn_feed_server(
+            feed(vec![trip_entity(
+                "063350_L..N01R",
+                "L",
+                vec![origin, stop_time("L08N", now + 240)],
+            )]),
+            Duration::ZERO,
+            &[],
+        )
+        .await;
+        let handler =
+            GtfsHandler::from_stop_locations(stop_locations()).with_feed_base_url(base_url);
+
+        let trains = handler
+            .get_train_positions(&Mutex::new(HashMap::new()))
+            .await;
+
+        assert!(trains.positions.is_empty(), "{:?}", trains.positions);
+        assert_eq!(trains.scheduled.len(), SUBWAY_FEEDS.len());
+        let scheduled = &trains.scheduled[0];
+        assert_eq!(scheduled.from_stop.stop_id, "L10N");
+        assert_eq!(scheduled.progress, 0.0);
+        assert_eq!(scheduled.start_time, now + 120);
+    }
+
+    #[test]
+    fn test_mid_route_train_is_not_scheduled() {
+        let now = 1_700_000_000;
+        // Its next stop is 2 minutes out; the stops it passed were dropped
+        let next_stop = || vec![stop_time("L10N", now + 120), stop_time("L08N", now + 240)];
+        let mut departed = trip_entity("063350_L..N01R", "L", next_stop());
+        let trip = &mut departed.trip_update.as_mut().unwrap().trip;
+        trip.start_date = Some("20231114".to_string());
+        // 15:53:20 in New York is 20:53:20 UTC, before now
+        trip.start_time = Some("15:53:20".to_string());
+        let mut sequenced = trip_entity("063400_L..N01R", "L", next_stop());
+        sequenced.trip_update.as_mut().unwrap().stop_time_update[0].stop_sequence = Some(12);
+
+        for entity in [
+            trip_entity("063300_L..N01R", "L", next_stop()),
+            departed,
+            sequenced,
+        ] {
+            let trip_id = entity.id.clone();
+            let positions = positions_from_feed(
+                &feed(vec![entity]),
+                &stop_locations(),
+                &HashMap::new(),
+                now,
+                SegmentLimits::default(),
+            );
+            assert!(positions.is_empty(), "{}: {:?}", trip_id, positions);
+        }
+    }
+
+    #[test]
+    fn test_trip_start_is_new_york_time() {
+        // 2023-11-14 is standard time (UTC-5), 2023-07-04 daylight time (UTC-4)
+        assert_eq!(
+            trip_start_timestamp("20231114", "17:53:20"),
+            Some(1_700_002_400)
+        );
+        assert_eq!(
+            trip_start_timestamp("20230704", "08:00:00"),
+            Some(1_688_472_000)
+        );
+        // Trips after midnight keep the previous service day
+        assert_eq!(
+            trip_start_timestamp("20231113", "41:53:20"),
+            trip_start_timestamp("20231114", "17:53:20")
+        );
+        assert_eq!(trip_start_timestamp("2023-11-14", "17:53:20"), None);
+        assert_eq!(trip_start_timestamp("20231114", "17:53"), None);
+
+        let trip = gtfs_rt::TripDescriptor {
+            start_date: Some("20231114".to_string()),
+            start_time: Some("17:53:20".to_string()),
+            ..Default::default()
+        };
+        let first = stop_time("L10N", 1_700_002_400);
+        assert!(trip_not_started(&trip, &first, 1_700_002_399));
+        assert!(!trip_not_started(&trip, &first, 1_700_002_400));
+    }
+
+    #[tokio::test]
+    async fn test_all_feeds_failed_differs_from_no_trains() {
+        // Nothing listens on the discard port, so every feed fails
+        let handler = GtfsHandler::from_stop_locations(stop_locations())
+            .with_feed_base_url("http://127.0.0.1:9/");
+        let failed = handler
+            .get_train_positions(&Mutex::new(HashMap::new()))
+            .await;
+        assert!(failed.positions.is_empty());
+        assert_eq!(failed.feeds_failed, SUBWAY_FEEDS.len());
+        assert!(failed.all_feeds_failed());
+
+        let base_url = spawn_feed_server(feed(vec![]), Duration::ZERO, &[]).await;
+        let handler =
+            GtfsHandler::from_stop_locations(stop_locations()).with_feed_base_url(base_url);
+        let empty = handler
+            .get_train_positions(&Mutex::new(HashMap::new()))
+            .await;
+        assert!(empty.positions.is_empty());
+        assert_eq!(empty.feeds_failed, 0);
+        assert!(!empty.all_feeds_failed());
+    }
+
+    #[tokio::test]
+    async fn test_get_train_positions_fetches_feeds_concurrently() {
+        let delay = Duration::from_millis(200);
+        let base_url = spawn_feed_server(live_feed(), delay, &[]).await;
+        let handler =
+            GtfsHandler::from_stop_locations(stop_locations()).with_feed_base_url(base_url);
+        let feed_health = Mutex::new(HashMap::new());
+
+        let started = Instant::now();
+        let positions = handler.get_train_positions(&feed_health).await.positions;
+        let elapsed = started.elapsed();
+
+        assert_eq!(positions.len(), SUBWAY_FEEDS.len());
+        // Sequential polling would take at least SUBWAY_FEEDS.len() * delay
+        assert!(elapsed < delay * 3, "took {:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn test_feed_fetches_are_bounded_by_concurrency() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let most_in_flight = Arc::new(AtomicUsize::new(0));
+        let body = live_feed().encode_to_vec();
+        let app = axum::Router::new().fallback({
+            let (in_flight, most_in_flight) = (in_flight.clone(), most_in_flight.clone());
+            move || {
+                let (in_flight, most_in_flight) = (in_flight.clone(), most_in_flight.clone());
+                let body = body.clone();
+                async move {
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    most_in_flight.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    body
+                }
+            }
+        });
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+
+        let handler = GtfsHandler::from_stop_locations(stop_locations())
+            .with_feed_base_url(base_url)
+            .with_feed_concurrency(2);
+        let positions = handler
+            .get_train_positions(&Mutex::new(HashMap::new()))
+            .await
+            .positions;
+
+        assert_eq!(positions.len(), SUBWAY_FEEDS.len());
+        assert_eq!(most_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    /// A and C trains at station A27 (an A train in each direction), plus a
+    /// departed A train and a C train terminating there
+    fn departures_feed(now: i64) -> FeedMessage {
+        feed(vec![
+            trip_entity(
+                "1_A..N",
+                "A",
+                vec![stop_time("A27N", now + 60), stop_time("A24N", now + 180)],
+            ),
+            trip_entity(
+                "2_A..N",
+                "A",
+                vec![stop_time("A27N", now + 600), stop_time("A24N", now + 720)],
+            ),
+            trip_entity(
+                "3_A..S",
+                "A",
+                vec![stop_time("A27S", now + 30), stop_time("A28S", now + 150)],
+            ),
+            trip_entity(
+                "4_C..N",
+                "C",
+                vec![stop_time("A27N", now + 120), stop_time("A24N", now + 240)],
+            ),
+            trip_entity(
+                "5_A..N",
+                "A",
+                vec![stop_time("A27N", now - 30), stop_time("A24N", now + 90)],
+            ),
+            trip_entity(
+                "6_C..S",
+                "C",
+                vec![stop_time("A28S", now + 60), stop_time("A27S", now + 180)],
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_departure_board_with_two_routes() {
+        let now = 1_700_000_000;
+        let departures = departures_from_feed(&departures_feed(now), "A27", &HashMap::new(), now);
+
+        let board = departure_board(departures, 1);
+
+        let summary: Vec<(&str, Option<Direction>, Vec<&str>)> = board
+            .iter()
+            .map(|group| {
+                (
+                    group.route_id.as_str(),
+                    group.direction,
+                    group
+                        .departures
+                        .iter()
+                        .map(|d| d.trip_id.as_str())
+                        .collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("A", Some(Direction::North), vec!["1_A..N"]),
+                ("A", Some(Direction::South), vec!["3_A..S"]),
+                ("C", Some(Direction::North), vec!["4_C..N"]),
+            ]
+        );
+        assert_eq!(
+            board[0].departures[0].destination_name.as_deref(),
+            Some("A24N")
+        );
+    }
+
+    #[test]
+    fn test_departures_include_both_platforms_tagged_by_direction() {
+        let now = 1_700_000_000;
+
+        for stop_id in ["A27", "A27N", "A27S"] {
+            let departures =
+                departures_from_feed(&departures_feed(now), stop_id, &HashMap::new(), now);
+
+            let mut tagged: Vec<(&str, &str, Option<Direction>)> = departures
+                .iter()
+                .filter(|departure| departure.route_id == "A")
+                .map(|departure| {
+                    (
+                        departure.trip_id.as_str(),
+                        departure.stop_id.as_str(),
+                        departure.direction,
+                    )
+                })
+                .collect();
+            tagged.sort();
+            assert_eq!(
+                tagged,
+                [
+                    ("1_A..N", "A27N", Some(Direction::North)),
+                    ("2_A..N", "A27N", Some(Direction::North)),
+                    ("3_A..S", "A27S", Some(Direction::South)),
+                ],
+                "{}",
+                stop_id
+            );
+        }
+    }
+
+    #[test]
+    fn test_departure_board_sorts_and_limits_each_direction() {
+        let now = 1_700_000_000;
+        let departures = departures_from_feed(&departures_feed(now), "A27", &HashMap::new(), now);
+
+        let board = departure_board(departures, DEPARTURES_PER_DIRECTION);
+
+        let times: Vec<i64> = board[0].departures.iter().map(|d| d.time).collect();
+        assert_eq!(times, [now + 60, now + 600]);
+    }
+
+    #[tokio::test]
+    async fn test_get_departures_fetches_only_the_station_feeds() {
+        let now = Utc::now().timestamp();
+        let base_url = spawn_feed_server(departures_feed(now), Duration::ZERO, &[]).await;
+        let handler = GtfsHandler::from_stop_locations(HashMap::from([
+            ("A27N".to_string(), (40.672097, -73.835919)),
+            ("A27S".to_string(), (40.672097, -73.835919)),
+        ]))
+        .with_stop_routes(HashMap::from([(
+            "A27".to_string(),
+            vec!["A".to_string(), "C".to_string()],
+        )]))
+        .with_feed_base_url(base_url);
+        let feed_health = Mutex::new(HashMap::new());
+        let station = handler.station("A27N").unwrap();
+
+        let board = handler.get_departures(&station, &feed_health).await;
+
+        // Every feed path serves the same feed, so fetching more than the ace
+        // feed would repeat departures
+        assert_eq!(board.len(), 3);
+        assert_eq!(board[0].departures.len(), 2);
+        assert_eq!(feed_health.lock().keys().collect::<Vec<_>>(), ["ace"]);
+    }
+
+    #[tokio::test]
+    async fn test_get_train_positions_skips_failed_feed() {
+        let base_url =
+            spawn_feed_server(live_feed(), Duration::ZERO, &["gtfs-ace", "gtfs-g"]).await;
+        let handler =
+            GtfsHandler::from_stop_locations(stop_locations()).with_feed_base_url(base_url);
+        let feed_health = Mutex::new(HashMap::new());
+
+        let positions = handler.get_train_positions(&feed_health).await.positions;
+
+        assert_eq!(positions.len(), SUBWAY_FEEDS.len() - 2);
+        let health = feed_health.lock();
+        assert_eq!(health["ace"].consecutive_failures, 1);
+        assert_eq!(health["g"].consecutive_failures, 1);
+        assert!(health["l"].last_success.is_some());
+    }
+
+    #[test]
+    fn test_feed_health_failures_reset_on_success() {
+        let mut health = FeedHealth::default();
+        health.record_failure("timeout".to_string());
+        health.record_failure("timeout".to_string());
+        assert_eq!(health.consecutive_failures, 2);
+        assert_eq!(health.last_error.as_deref(), Some("timeout"));
 
-            // println!("\n=== FOUND POSITIONS ===");
-            // for pos in &positions {
-            //     println!(
-            //         "Train {} on route {} from {} to {}, progress: {}",
-            //         pos.trip_id,
-            //         pos.route_id,
-            //         pos.from_stop.stop_id,
-            //         pos.to_stop.stop_id,
-            //         pos.progress
-            //     );
-            // }
-
-            info!("Found {} trains in transit", positions.len());
-        }
-        Ok(positions)
+        let now = Utc::now();
+        health.record_success(now);
+        assert_eq!(health.consecutive_failures, 0);
+        assert_eq!(health.last_success, Some(now));
+        assert_eq!(health.last_error.as_deref(), Some("timeout"));
     }
 }
@@ -0,0 +1,184 @@
+//! Serializable snapshot of a GTFS-realtime feed
+//!
+//! [`FeedSnapshot`] keeps only the fields NYC Pulse reads from a feed, so JSON
+//! built from it (e.g. by the debug endpoint) doesn't change shape when the
+//! upstream `gtfs_rt` protobuf types do.
+
+use gtfs_rt::{trip_update, FeedEntity, FeedMessage, TripDescriptor};
+use serde::Serialize;
+
+/// The parts of a [`FeedMessage`] NYC Pulse uses
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FeedSnapshot {
+    /// Unix timestamp in seconds when the feed was generated, if the header has one
+    pub timestamp: Option<u64>,
+    /// Every entity in the feed, in feed order
+    pub entities: Vec<EntitySnapshot>,
+}
+
+/// One feed entity's trip and its stop time updates
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EntitySnapshot {
+    /// Entity identifier, unique within the feed
+    pub id: String,
+    /// Trip identifier (e.g. "063350_L..N01R"), from the trip update or vehicle
+    pub trip_id: Option<String>,
+    /// Route the trip runs on (e.g. "L")
+    pub route_id: Option<String>,
+    /// Predicted stops, in trip order; empty for entities without a trip update
+    pub stop_time_updates: Vec<StopTimeSnapshot>,
+}
+
+/// A predicted arrival and departure at one stop
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StopTimeSnapshot {
+    /// Directional platform (e.g. "L08N")
+    pub stop_id: Option<String>,
+    /// Predicted Unix timestamp in seconds of the arrival
+    pub arrival: Option<i64>,
+    /// Predicted Unix timestamp in seconds of the departure
+    pub departure: Option<i64>,
+}
+
+impl From<&FeedMessage> for FeedSnapshot {
+    fn from(feed: &FeedMessage) -> Self {
+        Self {
+            timestamp: feed.header.timestamp,
+            entities: feed.entity.iter().map(EntitySnapshot::from).collect(),
+        }
+    }
+}
+
+impl From<&FeedEntity> for EntitySnapshot {
+    fn from(entity: &FeedEntity) -> Self {
+        let trip: Option<&TripDescriptor> = entity
+            .trip_update
+            .as_ref()
+            .map(|update| &update.trip)
+            .or_else(|| {
+                entity
+                    .vehicle
+                    .as_ref()
+                    .and_then(|vehicle| vehicle.trip.as_ref())
+            });
+        Self {
+            id: entity.id.clone(),
+            trip_id: trip.and_then(|trip| trip.trip_id.clone()),
+            route_id: trip.and_then(|trip| trip.route_id.clone()),
+            stop_time_updates: entity
+                .trip_update
+                .iter()
+                .flat_map(|update| &update.stop_time_update)
+                .map(StopTimeSnapshot::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<&trip_update::StopTimeUpdate> for StopTimeSnapshot {
+    fn from(update: &trip_update::StopTimeUpdate) -> Self {
+        Self {
+            stop_id: update.stop_id.clone(),
+            arrival: update.arrival.as_ref().and_then(|event| event.time),
+            departure: update.departure.as_ref().and_then(|event| event.time),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gtfs_rt::{FeedHeader, TripUpdate, VehiclePosition};
+
+    fn stop_time(
+        stop_id: &str,
+        arrival: Option<i64>,
+        departure: Option<i64>,
+    ) -> trip_update::StopTimeUpdate {
+        let event = |time| trip_update::StopTimeEvent {
+            time: Some(time),
+            ..Default::default()
+        };
+        trip_update::StopTimeUpdate {
+            stop_id: Some(stop_id.to_string()),
+            arrival: arrival.map(event),
+            departure: departure.map(event),
+            ..Default::default()
+        }
+    }
+
+    fn trip(trip_id: &str, route_id: &str) -> TripDescriptor {
+        TripDescriptor {
+            trip_id: Some(trip_id.to_string()),
+            route_id: Some(route_id.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_snapshot_from_feed() {
+        let feed = FeedMessage {
+            header: FeedHeader {
+                gtfs_realtime_version: "2.0".to_string(),
+                timestamp: Some(1_700_000_000),
+                ..Default::default()
+            },
+            entity: vec![
+                FeedEntity {
+                    id: "000001L".to_string(),
+                    trip_update: Some(TripUpdate {
+                        trip: trip("063350_L..N01R", "L"),
+                        stop_time_update: vec![
+                            stop_time("L08N", None, Some(1_700_000_060)),
+                            stop_time("L06N", Some(1_700_000_180), Some(1_700_000_200)),
+                        ],
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                FeedEntity {
+                    id: "000002L".to_string(),
+                    vehicle: Some(VehiclePosition {
+                        trip: Some(trip("063350_L..N01R", "L")),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            ],
+        };
+
+        let snapshot = FeedSnapshot::from(&feed);
+        assert_eq!(snapshot.timestamp, Some(1_700_000_000));
+        assert_eq!(
+            snapshot.entities[0],
+            EntitySnapshot {
+                id: "000001L".to_string(),
+                trip_id: Some("063350_L..N01R".to_string()),
+                route_id: Some("L".to_string()),
+                stop_time_updates: vec![
+                    StopTimeSnapshot {
+                        stop_id: Some("L08N".to_string()),
+                        arrival: None,
+                        departure: Some(1_700_000_060),
+                    },
+                    StopTimeSnapshot {
+                        stop_id: Some("L06N".to_string()),
+                        arrival: Some(1_700_000_180),
+                        departure: Some(1_700_000_200),
+                    },
+                ],
+            }
+        );
+        assert_eq!(
+            snapshot.entities[1].trip_id.as_deref(),
+            Some("063350_L..N01R")
+        );
+        assert!(snapshot.entities[1].stop_time_updates.is_empty());
+
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(
+            json["entities"][0]["stop_time_updates"][1]["stop_id"],
+            "L06N"
+        );
+    }
+}
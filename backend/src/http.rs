@@ -0,0 +1,86 @@
+//! Shared HTTP client construction
+//!
+//! Every outbound request (NY Open Data, the MTA feeds) goes through a client
+//! built by [`build_http_client`], so they all send the same `User-Agent` and
+//! share timeout and connection pool settings. Some of these endpoints reject
+//! requests carrying a library's default agent.
+
+use crate::Result;
+use std::time::Duration;
+
+/// `User-Agent` sent with every request, e.g. `nyc-pulse/0.1.0`
+pub const USER_AGENT: &str = concat!("nyc-pulse/", env!("CARGO_PKG_VERSION"));
+
+/// Timeout and connection pool settings for [`build_http_client`]
+#[derive(Debug, Clone)]
+pub struct HttpClientConfig {
+    /// Limit on a whole request, from connecting to reading the body
+    pub timeout: Duration,
+    /// Limit on establishing a connection
+    pub connect_timeout: Duration,
+    /// How long an idle pooled connection is kept open
+    pub pool_idle_timeout: Duration,
+    /// Maximum idle connections kept per host
+    pub pool_max_idle_per_host: usize,
+}
+
+impl Default for HttpClientConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            connect_timeout: Duration::from_secs(5),
+            pool_idle_timeout: Duration::from_secs(90),
+            // One connection per MTA feed polled concurrently
+            pool_max_idle_per_host: 8,
+        }
+    }
+}
+
+/// Builds an HTTP client with gzip support, the NYC Pulse `User-Agent`, and
+/// the timeouts and pool limits from `config`
+///
+/// # Errors
+/// - `Error::Api` if the TLS backend can't be initialized
+pub fn build_http_client(config: &HttpClientConfig) -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .gzip(true)
+        .timeout(config.timeout)
+        .connect_timeout(config.connect_timeout)
+        .pool_idle_timeout(config.pool_idle_timeout)
+        .pool_max_idle_per_host(config.pool_max_idle_per_host)
+        .build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{header, HeaderMap};
+
+    #[tokio::test]
+    async fn test_client_sends_user_agent() {
+        let app = axum::Router::new().fallback(|headers: HeaderMap| async move {
+            headers[header::USER_AGENT].to_str().unwrap().to_string()
+        });
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+
+        let client = build_http_client(&HttpClientConfig::default()).unwrap();
+        let user_agent = client
+            .get(format!("http://{}/", addr))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+
+        assert_eq!(user_agent, USER_AGENT);
+        assert!(user_agent.starts_with("nyc-pulse/"));
+    }
+}
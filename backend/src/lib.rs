@@ -13,12 +13,28 @@
 //! # Future Features
 //!
 //!   These features are not currently implemented but provide extension points for future development.
+//!   Each is behind a cargo feature, off by default, so the subway core builds without their
+//!   dependencies:
 //!
-//!   * Bike sharing station status
-//!   * Air quality measurements
-//!   * 311 service request tracking
+//!   * Bike sharing station status (`bikes`)
+//!   * Air quality measurements (`air-quality`)
+//!   * 311 service request tracking (`service-311`)
+
+pub mod config;
+pub mod demo;
+pub mod feeds;
+pub mod gtfs;
+pub mod http;
+pub mod lines;
+pub mod logging;
+pub mod position_store;
+pub mod status_store;
 
 use chrono::{DateTime, Utc};
+use nyc_pulse_common::geohash::{self, GEOHASH_PRECISION};
+use nyc_pulse_common::geojson::{GeoJsonFeature, GeoJsonGeometry, GeoJsonProperties};
+use nyc_pulse_common::lines::route_color;
+use nyc_pulse_common::serde_helpers::rfc3339;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
@@ -29,13 +45,21 @@ pub struct SubwayStatus {
     pub line: String,
     /// Current service status (e.g., "Good Service", "Delays")
     pub status: String,
-    /// Timestamp when this status was recorded
+    /// Timestamp when this status was recorded, serialized as RFC 3339
+    #[serde(with = "rfc3339")]
     pub timestamp: DateTime<Utc>,
     /// Boolean indicating if there are currently delays
     pub delays: bool,
+    /// When `status` or `delays` last changed, serialized as RFC 3339
+    ///
+    /// Recomputed when stored (see [`status_store::store_statuses`]), so it
+    /// defaults to now when a client omits it.
+    #[serde(with = "rfc3339", default = "Utc::now")]
+    pub changed_at: DateTime<Utc>,
 }
 
 /// Represents a bike sharing station (future feature)
+#[cfg(feature = "bikes")]
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct BikeStation {
     /// Unique identifier for the station
@@ -51,10 +75,12 @@ pub struct BikeStation {
     /// Number of docks currently available
     pub docks_available: i32,
     /// Timestamp of last update
+    #[serde(with = "rfc3339")]
     pub timestamp: DateTime<Utc>,
 }
 
 /// Represents air quality measurements from a monitoring station (future feature)
+#[cfg(feature = "air-quality")]
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct AirQuality {
     /// Unique identifier for the monitoring station
@@ -64,10 +90,12 @@ pub struct AirQuality {
     /// Ozone level measurement
     pub ozone: f64,
     /// Timestamp of measurement
+    #[serde(with = "rfc3339")]
     pub timestamp: DateTime<Utc>,
 }
 
 /// Represents a 311 service request (future feature)
+#[cfg(feature = "service-311")]
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct ServiceRequest {
     /// Unique identifier for the request
@@ -77,6 +105,7 @@ pub struct ServiceRequest {
     /// Current status of the request
     pub status: String,
     /// When the request was created
+    #[serde(with = "rfc3339")]
     pub created_at: DateTime<Utc>,
     /// Optional latitude of the request location
     pub latitude: Option<f64>,
@@ -84,34 +113,145 @@ pub struct ServiceRequest {
     pub longitude: Option<f64>,
 }
 
-/// Represents the current position of a subway train
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TrainPosition {
-    /// GTFS trip identifier
+pub use nyc_pulse_common::borough::Borough;
+pub use nyc_pulse_common::service_status::ServiceStatus;
+pub use nyc_pulse_common::trains::{Direction, OccupancyStatus, StopLocation, TrainPosition};
+
+/// A train due to leave a station
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Departure {
+    /// Trip identifier from the GTFS feed
     pub trip_id: String,
-    /// Subway route identifier (e.g., "A", "1")
+    /// Route of the trip (e.g. "A")
+    pub route_id: String,
+    /// Directional platform the train leaves from (e.g. "A27N")
+    pub stop_id: String,
+    /// Direction of travel, from the platform or the trip ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<Direction>,
+    /// Predicted Unix timestamp in seconds of the departure, serialized as an integer
+    pub time: i64,
+    /// Name of the trip's last stop, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination_name: Option<String>,
+}
+
+/// The next departures of one route in one direction
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DepartureGroup {
+    /// Route shared by every departure in the group
     pub route_id: String,
-    /// The previous stop location
-    pub from_stop: StopLocation,
-    /// The next stop location
-    pub to_stop: StopLocation,
-    /// Progress between stops (0.0 to 1.0)
-    pub progress: f64,
-    /// Unix timestamp when train departed from_stop
-    pub start_time: i64,
-    /// Estimated Unix timestamp when train will arrive at to_stop
-    pub end_time: i64,
+    /// Direction shared by every departure in the group
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<Direction>,
+    /// Departures, soonest first
+    pub departures: Vec<Departure>,
 }
 
-/// Represents a subway stop location
+/// A station, identified by its parent stop ID, with the routes serving it
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct StopLocation {
-    /// GTFS stop identifier
+pub struct StopInfo {
+    /// GTFS parent stop identifier, without the `N`/`S` direction suffix
     pub stop_id: String,
-    /// Stop latitude coordinate
+    /// Human-readable station name, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_name: Option<String>,
+    /// Station latitude coordinate
     pub latitude: f64,
-    /// Stop longitude coordinate
+    /// Station longitude coordinate
     pub longitude: f64,
+    /// Routes serving the station during the day (e.g. `["A", "C", "E"]`)
+    #[serde(default)]
+    pub routes: Vec<String>,
+    /// Borough the station is in, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub borough: Option<Borough>,
+    /// Whether any train currently in transit is heading to this station
+    #[serde(default)]
+    pub train_approaching: bool,
+}
+
+impl StopInfo {
+    /// Map feature for the station, colored by its first route
+    ///
+    /// Division, accessibility and direction labels aren't in the station cache
+    /// and are left empty.
+    pub fn to_geojson_feature(&self) -> GeoJsonFeature {
+        let first_route = self.routes.first().map(String::as_str).unwrap_or_default();
+        GeoJsonFeature {
+            feature_type: "Feature".to_string(),
+            properties: GeoJsonProperties {
+                name: self
+                    .stop_name
+                    .clone()
+                    .unwrap_or_else(|| self.stop_id.clone()),
+                lines: self.routes.join(" "),
+                division: String::new(),
+                borough: self
+                    .borough
+                    .map(Borough::name)
+                    .unwrap_or_default()
+                    .to_string(),
+                ada: false,
+                ada_notes: String::new(),
+                north_direction: String::new(),
+                south_direction: String::new(),
+                color: route_color(first_route).to_string(),
+                bullet: String::new(),
+                progress: None,
+                text_color: String::new(),
+                geohash: geohash::encode(self.latitude, self.longitude, GEOHASH_PRECISION),
+            },
+            geometry: GeoJsonGeometry::point([self.longitude, self.latitude]),
+        }
+    }
+}
+
+/// Serializes the wrapped value with camelCase object keys
+///
+/// The API's native field names are snake_case; this wrapper rewrites every
+/// object key (at any depth) for JavaScript clients that expect camelCase,
+/// e.g. `from_stop.stop_id` becomes `fromStop.stopId`.
+#[derive(Debug, Clone)]
+pub struct CamelCase<T>(pub T);
+
+impl<T: Serialize> Serialize for CamelCase<T> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        let value = serde_json::to_value(&self.0).map_err(serde::ser::Error::custom)?;
+        camel_case_keys(value).serialize(serializer)
+    }
+}
+
+/// Recursively converts snake_case object keys to camelCase
+fn camel_case_keys(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => map
+            .into_iter()
+            .map(|(key, value)| (snake_to_camel(&key), camel_case_keys(value)))
+            .collect(),
+        serde_json::Value::Array(values) => values.into_iter().map(camel_case_keys).collect(),
+        other => other,
+    }
+}
+
+/// Converts a snake_case identifier to camelCase
+fn snake_to_camel(key: &str) -> String {
+    let mut camel = String::with_capacity(key.len());
+    let mut upper_next = false;
+    for c in key.chars() {
+        if c == '_' {
+            upper_next = true;
+        } else if upper_next {
+            camel.extend(c.to_uppercase());
+            upper_next = false;
+        } else {
+            camel.push(c);
+        }
+    }
+    camel
 }
 
 /// Custom error types for the application
@@ -129,6 +269,20 @@ pub enum Error {
     /// Environment/configuration errors
     #[error("Environment error: {0}")]
     Environment(String),
+    /// Stored JSON that can't be decoded
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    /// Every polled GTFS feed failed, so no train positions are available
+    #[error("All {0} GTFS feeds failed")]
+    FeedsUnavailable(usize),
+    /// A GTFS feed answered with something other than protobuf, such as an HTML error page
+    #[error("Feed {url} returned {content_type} instead of protobuf")]
+    FeedBadContentType {
+        /// URL of the feed
+        url: String,
+        /// `Content-Type` of the response, or "no content type" if it had none
+        content_type: String,
+    },
 }
 
 /// Convenience type alias for Results using our custom Error type
@@ -148,6 +302,7 @@ mod tests {
             status: "Good Service".to_string(),
             timestamp,
             delays: false,
+            changed_at: timestamp,
         };
 
         assert_eq!(status.line, "A");
@@ -156,6 +311,22 @@ mod tests {
         assert!(!status.delays);
     }
 
+    /// Only compiled with default features, so a plain `cargo test` proves the
+    /// subway core doesn't depend on any optional ingestion feature
+    #[cfg(not(any(feature = "bikes", feature = "air-quality", feature = "service-311")))]
+    #[test]
+    fn test_core_builds_without_optional_features() {
+        let status = SubwayStatus {
+            line: "L".to_string(),
+            status: "Good Service".to_string(),
+            timestamp: Utc::now(),
+            delays: false,
+            changed_at: Utc::now(),
+        };
+        assert!(serde_json::to_value(&status).is_ok());
+        assert!(!feeds::SUBWAY_FEEDS.is_empty());
+    }
+
     #[test]
     fn test_subway_status_with_delays() {
         let timestamp = Utc::now();
@@ -164,6 +335,7 @@ mod tests {
             status: "Delays".to_string(),
             timestamp,
             delays: true,
+            changed_at: timestamp,
         };
 
         assert_eq!(status.line, "7");
@@ -176,19 +348,20 @@ mod tests {
         let position = TrainPosition {
             trip_id: "123".to_string(),
             route_id: "A".to_string(),
-            from_stop: StopLocation {
-                stop_id: "A01".to_string(),
-                latitude: 40.7,
-                longitude: -73.9,
-            },
-            to_stop: StopLocation {
-                stop_id: "A02".to_string(),
-                latitude: 40.8,
-                longitude: -73.8,
-            },
+            is_known: true,
+            express: false,
+            from_stop: StopLocation::new("A01".to_string(), None, 40.7, -73.9),
+            to_stop: StopLocation::new("A02".to_string(), None, 40.8, -73.8),
             progress: 0.5,
             start_time: 1000,
             end_time: 2000,
+            direction: None,
+            destination_stop_id: None,
+            destination_name: None,
+            occupancy: None,
+            remaining_stops: Vec::new(),
+            eta_to_destination_secs: None,
+            speed_kmh: None,
         };
 
         assert_eq!(position.trip_id, "123");
@@ -202,14 +375,56 @@ mod tests {
 
     #[test]
     fn test_stop_location_creation() {
-        let stop = StopLocation {
-            stop_id: "L06".to_string(),
-            latitude: 40.7,
-            longitude: -73.9,
-        };
+        let stop = StopLocation::new("L06".to_string(), None, 40.7, -73.9);
 
         assert_eq!(stop.stop_id, "L06");
         assert_eq!(stop.latitude, 40.7);
         assert_eq!(stop.longitude, -73.9);
     }
+
+    fn sample_position() -> TrainPosition {
+        TrainPosition {
+            trip_id: "123".to_string(),
+            route_id: "A".to_string(),
+            is_known: true,
+            express: false,
+            from_stop: StopLocation::new("A01".to_string(), None, 40.7, -73.9),
+            to_stop: StopLocation::new("A02".to_string(), None, 40.8, -73.8),
+            progress: 0.5,
+            start_time: 1000,
+            end_time: 2000,
+            direction: None,
+            destination_stop_id: None,
+            destination_name: None,
+            occupancy: None,
+            remaining_stops: Vec::new(),
+            eta_to_destination_secs: None,
+            speed_kmh: None,
+        }
+    }
+
+    #[test]
+    fn test_train_position_snake_case_json() {
+        let json = serde_json::to_value(sample_position()).unwrap();
+
+        assert_eq!(json["trip_id"], "123");
+        assert_eq!(json["route_id"], "A");
+        assert_eq!(json["from_stop"]["stop_id"], "A01");
+        assert_eq!(json["start_time"], 1000);
+        assert!(json.get("tripId").is_none());
+    }
+
+    #[test]
+    fn test_train_position_camel_case_json() {
+        let json = serde_json::to_value(CamelCase(vec![sample_position()])).unwrap();
+        let position = &json[0];
+
+        assert_eq!(position["tripId"], "123");
+        assert_eq!(position["routeId"], "A");
+        assert_eq!(position["fromStop"]["stopId"], "A01");
+        assert_eq!(position["toStop"]["latitude"], 40.8);
+        assert_eq!(position["startTime"], 1000);
+        assert_eq!(position["endTime"], 2000);
+        assert!(position.get("trip_id").is_none());
+    }
 }
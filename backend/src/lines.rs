@@ -0,0 +1,110 @@
+//! Line table overrides for the collector and the server
+//!
+//! Line colors and names are built into [`nyc_pulse_common::lines`] so the
+//! frontend can share them. The `LINES_FILE` environment variable can point at
+//! a JSON array of lines, in the format [`LineInfo`] serializes to, that
+//! replace the built-in lines with the same ID at startup. That way a wrong
+//! color can be fixed without a rebuild.
+
+use crate::{Error, Result};
+pub use nyc_pulse_common::lines::{install_lines, LineInfo, LINES};
+use nyc_pulse_common::lines::{is_hex_color, merge_lines};
+use std::path::Path;
+
+/// Builds the line table from a `LINES_FILE` value
+///
+/// `None` or a blank value keeps the built-in [`LINES`]; otherwise the file's
+/// lines are merged into them (see [`merge_lines`]).
+///
+/// # Errors
+/// - `Error::Environment` if the file can't be read or isn't a JSON array of
+///   lines, or naming every line whose color isn't a `#RRGGBB` hex string
+pub fn load_lines_file(path: Option<&str>) -> Result<Vec<LineInfo>> {
+    match path.map(str::trim).filter(|path| !path.is_empty()) {
+        Some(path) => read_lines_file(Path::new(path)),
+        None => Ok(LINES.to_vec()),
+    }
+}
+
+/// Reads and validates the line overrides in `path`, merged into [`LINES`]
+fn read_lines_file(path: &Path) -> Result<Vec<LineInfo>> {
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        Error::Environment(format!(
+            "LINES_FILE {} can't be read: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    // Lines borrow their strings, so the file is kept for the rest of the process
+    let contents: &'static str = Box::leak(contents.into_boxed_str());
+    let overrides: Vec<LineInfo> = serde_json::from_str(contents).map_err(|e| {
+        Error::Environment(format!(
+            "LINES_FILE {} isn't a JSON array of lines: {}",
+            path.display(),
+            e
+        ))
+    })?;
+
+    let invalid: Vec<String> = overrides
+        .iter()
+        .filter(|line| !is_hex_color(line.color))
+        .map(|line| format!("{} ({:?})", line.id, line.color))
+        .collect();
+    if !invalid.is_empty() {
+        return Err(Error::Environment(format!(
+            "LINES_FILE {} has colors that aren't #RRGGBB hex: {}",
+            path.display(),
+            invalid.join(", ")
+        )));
+    }
+    Ok(merge_lines(&overrides))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture_path(name: &str) -> String {
+        format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    #[test]
+    fn test_lines_file_overrides_color() {
+        let table = load_lines_file(Some(&fixture_path("lines.json"))).unwrap();
+
+        let l_train = table.iter().find(|line| line.id == "L").unwrap();
+        assert_eq!(l_train.color, "#123456");
+        assert_eq!(l_train.name, "14 St-Canarsie Local");
+        assert_eq!(table.len(), LINES.len());
+        let a_train = table.iter().find(|line| line.id == "A").unwrap();
+        assert_eq!(a_train.color, "#0039A6");
+    }
+
+    #[test]
+    fn test_missing_lines_file_keeps_built_in_lines() {
+        assert_eq!(load_lines_file(None).unwrap(), LINES);
+        assert_eq!(load_lines_file(Some(" ")).unwrap(), LINES);
+    }
+
+    #[test]
+    fn test_invalid_lines_file_names_the_problem() {
+        let bad_color = load_lines_file(Some(&fixture_path("lines-bad-color.json")))
+            .unwrap_err()
+            .to_string();
+        assert!(bad_color.contains("N (\"yellow\")"), "{}", bad_color);
+
+        let not_lines = load_lines_file(Some(&fixture_path("stations.json")))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            not_lines.contains("isn't a JSON array of lines"),
+            "{}",
+            not_lines
+        );
+
+        let missing = load_lines_file(Some("no-such-lines.json"))
+            .unwrap_err()
+            .to_string();
+        assert!(missing.contains("can't be read"), "{}", missing);
+    }
+}
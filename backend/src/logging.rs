@@ -0,0 +1,69 @@
+//! Log output shared by the server and the collector
+//!
+//! `LOG_FORMAT` picks between human-readable output for development (`pretty`,
+//! the default) and one JSON object per line for a log aggregator (`json`).
+//! `RUST_LOG` filters either format, defaulting to `info`.
+
+use crate::{Error, Result};
+use tracing_subscriber::EnvFilter;
+
+/// How log lines are written to stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Readable, single-line text
+    #[default]
+    Pretty,
+    /// One JSON object per line
+    Json,
+}
+
+/// Parses a `LOG_FORMAT` value
+///
+/// Values are case-insensitive and may be surrounded by whitespace. `None` or a
+/// blank value selects [`LogFormat::Pretty`].
+///
+/// # Errors
+/// - `Error::Environment` if the value is neither `pretty` nor `json`
+pub fn parse_log_format(value: Option<&str>) -> Result<LogFormat> {
+    match value.unwrap_or_default().trim().to_lowercase().as_str() {
+        "" | "pretty" => Ok(LogFormat::Pretty),
+        "json" => Ok(LogFormat::Json),
+        other => Err(Error::Environment(format!(
+            "Unknown LOG_FORMAT: {} (expected pretty or json)",
+            other
+        ))),
+    }
+}
+
+/// Installs the global tracing subscriber in `format`
+///
+/// `log` records, such as those from the GTFS handler, are captured too.
+pub fn init(format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match format {
+        LogFormat::Pretty => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log_format_defaults_to_pretty() {
+        assert_eq!(parse_log_format(None).unwrap(), LogFormat::Pretty);
+        assert_eq!(parse_log_format(Some(" ")).unwrap(), LogFormat::Pretty);
+        assert_eq!(LogFormat::default(), LogFormat::Pretty);
+    }
+
+    #[test]
+    fn test_parse_log_format_values() {
+        assert_eq!(parse_log_format(Some("pretty")).unwrap(), LogFormat::Pretty);
+        assert_eq!(parse_log_format(Some(" JSON ")).unwrap(), LogFormat::Json);
+
+        let message = parse_log_format(Some("xml")).unwrap_err().to_string();
+        assert!(message.contains("xml"), "{}", message);
+    }
+}
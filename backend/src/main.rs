@@ -16,17 +16,107 @@
 //! for processing real-time transit feeds. These are shared across request handlers via
 //! the application state.
 //!
+//! Station locations come from NY Open Data, or from the `STATIONS_FILE` dataset
+//! (default `stops.json`) when that request fails or `STATIONS_SOURCE=file` is set.
+//!
 //! # API Endpoints
 //! - `GET /api/subway/status` - Returns current status for all subway lines
-//! - `GET /api/trains` - Returns real-time positions of all trains
+//!   (`?delays=true` for delayed lines only, `?sort=priority` for delayed and
+//!   busy lines first)
+//! - `GET /api/subway/status/grouped` - Returns the same statuses as `irt`, `bmt`
+//!   and `ind` arrays, busiest line first
+//! - `GET /api/subway/status/changes` - Returns the latest status of each line whose
+//!   status changed after `?since=` (RFC 3339 or Unix seconds)
+//! - `GET /api/subway/status/latest-timestamp` - Returns the time of the newest stored
+//!   status, or `404` if there is none
+//! - `GET /api/subway/history` - Returns past statuses, newest first, filtered by
+//!   `?line=`, `?since=` (RFC 3339 or Unix seconds) and `?limit=`
+//! - `GET /api/subway/history.csv` - The same history as streamed CSV
+//! - `GET /api/subway/reliability` - Returns each line's percentage of samples without
+//!   delays over the last `?days=` (default 7)
+//! - `POST /api/subway/status` - Stores statuses pushed by external collectors
+//!   (requires `Authorization: Bearer <INGEST_TOKEN>`)
+//! - `GET /api/trains` - Returns real-time positions of all trains (`?case=camel` for camelCase keys,
+//!   `?include_scheduled=true` to add trains about to leave their origin), or `503` when every feed failed
+//! - `GET /api/trains/count` - Returns how many trains are running, in total and per route
+//! - `GET /api/trains/latest` - Returns the newest train positions stored by the collector
+//!   with their `recorded_at`, without fetching the live feeds
+//! - `GET /api/trains/replay` - Returns the positions stored by the collector at or
+//!   just before `?at=` (RFC 3339 or Unix seconds), within the last hour
+//! - `GET /api/trains.geojson` - Returns the same positions as a GeoJSON `FeatureCollection`
+//! - `GET /api/trains.ndjson` - Streams the same positions as `/api/trains`, one JSON
+//!   object per line (`application/x-ndjson`)
+//! - `GET /api/feeds/status` - Returns health of each GTFS feed
+//! - `GET /api/stations` - Returns every known stop with its coordinates and name
+//!   (`?borough=Bk` keeps one borough's stops)
+//! - `GET /api/stops.geojson` - Returns every station as a GeoJSON `FeatureCollection`
+//!   (`?routes=L,G` keeps stations served by any of those routes)
+//! - `GET /ws/status` - WebSocket sending a snapshot of every line's status, then
+//!   each line whose status changes
+//! - `GET /api/stations/:stop_id` - Returns one station, by parent or directional stop ID
+//! - `GET /api/stations/:stop_id/departures` - Returns the station's next departures,
+//!   grouped by route and direction
+//! - `GET /api/debug/feed/:key` - Returns one GTFS feed (e.g. `ace`) as a pretty-printed
+//!   JSON snapshot of its trips; only served when `DEBUG_ENDPOINTS=1`
+//!
+//! The live train endpoints (`/api/trains`, its count and GeoJSON, and a station's
+//! `train_approaching`) read positions that a background task refetches every
+//! second, so the MTA feeds are fetched at the same rate however many clients poll.
+//!
+//! With `DEMO_MODE=1` the server needs neither Postgres nor the MTA: it connects
+//! to nothing, and the status and live train endpoints serve the fixed data in
+//! [`demo`](nyc_pulse_backend::demo), for local development and screenshots.
+//! Endpoints that need the database, such as history, reliability, stored
+//! train snapshots and status ingestion, answer `503` right away.
+//!
+//! Responses larger than a few dozen bytes are gzip or brotli compressed when the
+//! client's `Accept-Encoding` allows it.
+//!
+//! Every response carries an `X-Request-Id` header, echoing the client's if it
+//! sent one; log lines written while handling a request are tagged with it.
+//! Log verbosity follows `RUST_LOG` (default `info`); `LOG_FORMAT=json` writes one
+//! JSON object per line instead of readable text.
+//!
+//! All endpoints are rate limited per client IP (`RATE_LIMIT_PER_MIN`, default 120);
+//! clients over the limit receive `429 Too Many Requests` with a `Retry-After` header.
 
-mod gtfs;
+mod rate_limit;
+mod request_id;
+mod status_ws;
+mod train_cache;
 
-use crate::gtfs::GtfsHandler;
-use axum::{extract::State, routing::get, Json, Router};
+use crate::rate_limit::RateLimiter;
+use crate::status_ws::StatusHub;
+use crate::train_cache::TrainCache;
+use axum::{
+    body::{Bytes, StreamBody},
+    extract::{FromRef, Path, Query, State},
+    http::{header, HeaderMap, Request, StatusCode, Uri},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use chrono::TimeZone;
 use dotenv::dotenv;
+use futures::{StreamExt, TryStreamExt};
 use nyc_pulse_backend as backend;
+use nyc_pulse_backend::config::{Config, DEFAULT_QUERY_TIMEOUT_SECS};
+use nyc_pulse_backend::demo;
+use nyc_pulse_backend::gtfs::{self, FeedHealth, GtfsHandler};
+use nyc_pulse_backend::position_store;
+use nyc_pulse_backend::status_store::{self, HistoryFilter, StatusRetention};
+use nyc_pulse_common::borough::ParseBoroughError;
+use nyc_pulse_common::geojson::GeoJsonCollection;
+use nyc_pulse_common::lines;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tower_http::compression::CompressionLayer;
 use tower_http::cors::CorsLayer;
 
 /// Shared application state available to all request handlers
@@ -36,34 +126,509 @@ struct AppState {
     db: PgPool,
     /// Handler for GTFS real-time data
     gtfs: GtfsHandler,
+    /// Health of each polled GTFS feed, keyed by feed key
+    feed_health: Arc<Mutex<HashMap<String, FeedHealth>>>,
+    /// Per-client request rate limiter
+    rate_limiter: RateLimiter,
+    /// Bearer token required to push statuses; ingestion is disabled when `None`
+    ingest_token: Option<String>,
+    /// How ingested statuses are retained, matching the collector's mode
+    status_retention: StatusRetention,
+    /// Last-known line statuses and the channel announcing changes
+    status_hub: StatusHub,
+    /// Live train positions, refreshed in the background
+    trains: TrainCache,
+    /// Whether `/api/debug` routes are served
+    debug_endpoints: bool,
+    /// Longest a request's database query may run before it answers 503
+    query_timeout: Duration,
+    /// Whether statuses and trains come from [`demo::demo_data`] instead of the
+    /// database and the GTFS feeds
+    demo_mode: bool,
+}
+
+impl AppState {
+    /// Creates application state with a healthy, never-polled entry for every polled feed
+    fn new(
+        db: PgPool,
+        gtfs: GtfsHandler,
+        rate_limiter: RateLimiter,
+        ingest_token: Option<String>,
+        status_retention: StatusRetention,
+    ) -> Self {
+        let feed_health = gtfs
+            .feeds()
+            .iter()
+            .map(|feed| (feed.key.to_string(), FeedHealth::default()))
+            .collect();
+
+        Self {
+            db,
+            gtfs,
+            feed_health: Arc::new(Mutex::new(feed_health)),
+            rate_limiter,
+            ingest_token,
+            status_retention,
+            status_hub: StatusHub::new(),
+            trains: TrainCache::new(),
+            debug_endpoints: false,
+            query_timeout: Duration::from_secs(DEFAULT_QUERY_TIMEOUT_SECS),
+            demo_mode: false,
+        }
+    }
+
+    /// The database pool, for handlers reading or writing stored data
+    ///
+    /// # Errors
+    /// - `503 Service Unavailable` in demo mode, which has no database
+    fn database(&self) -> Result<&PgPool, ApiError> {
+        if self.demo_mode {
+            return Err(ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Unavailable in demo mode",
+            ));
+        }
+        Ok(&self.db)
+    }
+
+    /// Current train positions, canned in demo mode and cached otherwise
+    async fn train_positions(&self) -> gtfs::TrainPositions {
+        if self.demo_mode {
+            return demo::demo_data().train_positions();
+        }
+        self.trains.get(&self.gtfs, &self.feed_health).await
+    }
+}
+
+impl FromRef<AppState> for StatusHub {
+    fn from_ref(state: &AppState) -> Self {
+        state.status_hub.clone()
+    }
+}
+
+/// Error response carrying a status code and a JSON `{"error": ...}` body
+#[derive(Debug)]
+struct ApiError {
+    /// HTTP status of the response
+    status: StatusCode,
+    /// Human-readable description of the problem
+    message: String,
+}
+
+impl ApiError {
+    /// Creates an error response with the given status and message
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (
+            self.status,
+            Json(serde_json::json!({ "error": self.message })),
+        )
+            .into_response()
+    }
+}
+
+impl From<backend::Error> for ApiError {
+    fn from(e: backend::Error) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    }
+}
+
+/// Parses a timestamp query parameter, in RFC 3339 or as Unix seconds
+///
+/// Every endpoint taking a time (`since`, `at`) parses it here, so they all
+/// accept the same formats and fail the same way.
+///
+/// # Errors
+/// - `400 Bad Request` naming the value if it's neither format
+fn parse_ts(value: &str) -> Result<chrono::DateTime<chrono::Utc>, ApiError> {
+    let value = value.trim();
+    let parsed = match value.parse::<i64>() {
+        Ok(secs) => chrono::Utc.timestamp_opt(secs, 0).single(),
+        Err(_) => chrono::DateTime::parse_from_rfc3339(value)
+            .ok()
+            .map(|time| time.with_timezone(&chrono::Utc)),
+    };
+    parsed.ok_or_else(|| {
+        ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Invalid time {:?}: expected RFC 3339 or Unix seconds",
+                value
+            ),
+        )
+    })
+}
+
+/// Runs a request's database `query`, giving up once it has run for `timeout`
+///
+/// Dropping a slow query releases its connection, so one slow query can't hold
+/// up every other request waiting on the pool.
+///
+/// # Errors
+/// - `503 Service Unavailable` if `timeout` expires first
+async fn with_query_timeout<T>(
+    timeout: Duration,
+    query: impl std::future::Future<Output = T>,
+) -> Result<T, ApiError> {
+    tokio::time::timeout(timeout, query).await.map_err(|_| {
+        ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("Database query timed out after {:?}", timeout),
+        )
+    })
+}
+
+/// Ordering of the subway status endpoint
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum StatusSort {
+    /// Alphabetical by line
+    #[default]
+    Line,
+    /// Delayed lines first, then by ridership (see [`status_store::sort_by_priority`])
+    Priority,
+}
+
+/// Query parameters for the subway status endpoint
+#[derive(Debug, Default, Deserialize)]
+struct StatusQuery {
+    /// Only return lines whose latest status has delays
+    #[serde(default)]
+    delays: bool,
+    /// Response order, alphabetical unless `sort=priority`
+    #[serde(default)]
+    sort: StatusSort,
 }
 
 /// Handler for fetching current subway line status
 ///
-/// Returns the most recent status for each subway line from the database.
-/// Status includes service condition and any delays.
+/// Returns the most recent status for each subway line from the database, or
+/// the canned statuses in demo mode.
+/// Status includes service condition and any delays. `?delays=true` keeps
+/// only the lines currently delayed, and `?sort=priority` lists delayed and
+/// busy lines first instead of sorting by line.
 ///
 /// # Returns
 /// - JSON array of [`SubwayStatus`] objects, one per line
-async fn get_subway_status(State(state): State<AppState>) -> Json<Vec<backend::SubwayStatus>> {
-    let statuses = sqlx::query_as!(
-        backend::SubwayStatus,
-        r#"
-        WITH latest_statuses AS (
-            SELECT DISTINCT ON (line) *
-            FROM subway_status
-            ORDER BY line, timestamp DESC
-        )
-        SELECT line, status, timestamp, delays
-        FROM latest_statuses
-        ORDER BY line ASC
-        "#
+/// - `503 Service Unavailable` if the query takes longer than `QUERY_TIMEOUT_SECS`
+/// - `500 Internal Server Error` if the query fails
+async fn get_subway_status(
+    State(state): State<AppState>,
+    Query(query): Query<StatusQuery>,
+) -> Result<Json<Vec<backend::SubwayStatus>>, ApiError> {
+    let mut statuses = if state.demo_mode {
+        demo::demo_data().statuses(query.delays)
+    } else {
+        with_query_timeout(
+            state.query_timeout,
+            status_store::latest_statuses(&state.db, query.delays),
+        )
+        .await??
+    };
+    if query.sort == StatusSort::Priority {
+        status_store::sort_by_priority(&mut statuses);
+    }
+    Ok(Json(statuses))
+}
+
+/// Response of the latest status timestamp endpoint
+#[derive(Debug, Serialize)]
+struct LatestTimestamp {
+    /// Time of the newest stored status
+    #[serde(with = "nyc_pulse_common::serde_helpers::rfc3339")]
+    timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+/// Handler for the time of the newest stored status
+///
+/// Lets a monitoring job notice a stopped collector by comparing it to now.
+///
+/// # Returns
+/// - JSON [`LatestTimestamp`]
+/// - `404 Not Found` if no status has been stored yet
+async fn get_subway_status_latest_timestamp(
+    State(state): State<AppState>,
+) -> Result<Json<LatestTimestamp>, ApiError> {
+    let timestamp = status_store::latest_timestamp(state.database()?)
+        .await?
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "No statuses stored yet"))?;
+    Ok(Json(LatestTimestamp { timestamp }))
+}
+
+/// Query parameters for the status changes endpoint
+#[derive(Debug, Deserialize)]
+struct ChangesQuery {
+    /// Only lines whose status changed after this time (see [`parse_ts`])
+    since: String,
+}
+
+/// Handler for the lines whose status changed since a given time
+///
+/// # Returns
+/// - JSON array of the latest [`SubwayStatus`] of each line with a `changed_at`
+///   after `?since=`, empty if nothing changed
+/// - `400 Bad Request` if `?since=` isn't a time (see [`parse_ts`])
+async fn get_subway_status_changes(
+    State(state): State<AppState>,
+    Query(query): Query<ChangesQuery>,
+) -> Result<Json<Vec<backend::SubwayStatus>>, ApiError> {
+    let db = state.database()?;
+    let since = parse_ts(&query.since)?;
+    Ok(Json(status_store::statuses_changed_since(db, since).await?))
+}
+
+/// Handler for the latest statuses grouped into the IRT, BMT and IND grid
+///
+/// # Returns
+/// - JSON object with `irt`, `bmt` and `ind` arrays (see [`status_store::DivisionedStatus`])
+async fn get_subway_status_grouped(
+    State(state): State<AppState>,
+) -> Result<Json<status_store::DivisionedStatus>, ApiError> {
+    let statuses = status_store::latest_statuses(state.database()?, false).await?;
+    Ok(Json(status_store::DivisionedStatus::from_statuses(
+        statuses,
+    )))
+}
+
+/// Handler for scoring each line's recent reliability
+///
+/// `?days=` sets the window, defaulting to a week and clamped to
+/// [`status_store::MAX_RELIABILITY_DAYS`].
+///
+/// # Returns
+/// - JSON array of [`LineReliability`](status_store::LineReliability) ordered by line
+async fn get_subway_reliability(
+    State(state): State<AppState>,
+    Query(window): Query<status_store::ReliabilityWindow>,
+) -> Result<Json<Vec<status_store::LineReliability>>, ApiError> {
+    Ok(Json(
+        status_store::line_reliability(state.database()?, state.status_retention, window).await?,
+    ))
+}
+
+/// Query parameters for the status history endpoints
+#[derive(Debug, Default, Deserialize)]
+struct HistoryQuery {
+    /// Only statuses for this line
+    line: Option<String>,
+    /// Only statuses recorded at or after this time (see [`parse_ts`])
+    since: Option<String>,
+    /// Maximum number of rows (see [`HistoryFilter::effective_limit`])
+    limit: Option<i64>,
+}
+
+impl HistoryQuery {
+    /// The [`HistoryFilter`] these parameters describe
+    ///
+    /// # Errors
+    /// - `400 Bad Request` if `since` isn't a time
+    fn into_filter(self) -> Result<HistoryFilter, ApiError> {
+        Ok(HistoryFilter {
+            line: self.line,
+            since: self.since.as_deref().map(parse_ts).transpose()?,
+            limit: self.limit,
+        })
+    }
+}
+
+/// Handler for reading status history as JSON
+///
+/// # Returns
+/// - JSON array of [`SubwayStatus`] objects matching the [`HistoryQuery`], newest first
+/// - `400 Bad Request` if `?since=` isn't a time (see [`parse_ts`])
+async fn get_subway_history(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<backend::SubwayStatus>>, ApiError> {
+    let db = state.database()?.clone();
+    let filter = query.into_filter()?;
+    let statuses = status_store::stream_history(db, state.status_retention, filter)
+        .try_collect()
+        .await?;
+    Ok(Json(statuses))
+}
+
+/// Header row of the CSV history export
+const HISTORY_CSV_HEADER: &[u8] = b"line,status,timestamp,delays\n";
+
+/// Encodes one status as a CSV record with an RFC 3339 timestamp
+fn history_csv_record(status: &backend::SubwayStatus) -> Vec<u8> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let timestamp = status
+        .timestamp
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    writer
+        .write_record([
+            status.line.as_str(),
+            status.status.as_str(),
+            timestamp.as_str(),
+            if status.delays { "true" } else { "false" },
+        ])
+        .expect("writing to a Vec cannot fail");
+    writer.into_inner().expect("writing to a Vec cannot fail")
+}
+
+/// Handler for exporting status history as CSV
+///
+/// Accepts the same filters as `GET /api/subway/history`. Rows are streamed as
+/// they are read, so the response starts before the query finishes.
+///
+/// # Returns
+/// - `text/csv` body with a `line,status,timestamp,delays` header row
+/// - `400 Bad Request` if `?since=` isn't a time (see [`parse_ts`])
+async fn get_subway_history_csv(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Response, ApiError> {
+    let db = state.database()?.clone();
+    let filter = query.into_filter()?;
+    let rows = status_store::stream_history(db, state.status_retention, filter)
+        .map_ok(|status| Bytes::from(history_csv_record(&status)));
+    let body =
+        futures::stream::once(async { Ok(Bytes::from_static(HISTORY_CSV_HEADER)) }).chain(rows);
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"subway_history.csv\"",
+            ),
+        ],
+        StreamBody::new(body),
     )
-    .fetch_all(&state.db)
-    .await
-    .unwrap_or_default();
+        .into_response())
+}
+
+/// Response body of the status ingestion endpoint
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct IngestResponse {
+    /// Number of status rows stored (see [`status_store::store_statuses`])
+    inserted: usize,
+}
+
+/// Compares two tokens in time independent of where they first differ
+fn tokens_match(provided: &str, expected: &str) -> bool {
+    provided.len() == expected.len()
+        && provided
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Handler for storing statuses pushed by external collectors
+///
+/// Requests must carry `Authorization: Bearer <INGEST_TOKEN>`. Every status is
+/// checked against the known lines before any row is written, and all rows are
+/// stored in a single transaction according to `STATUS_RETENTION`.
+///
+/// # Returns
+/// - JSON [`IngestResponse`] with the number of rows inserted
+///
+/// # Errors
+/// - `503 Service Unavailable` in demo mode
+/// - `401 Unauthorized` if the token is missing, wrong, or ingestion is disabled
+/// - `400 Bad Request` naming any unknown line identifiers
+/// - `500 Internal Server Error` if the insert fails
+async fn post_subway_status(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(statuses): Json<Vec<backend::SubwayStatus>>,
+) -> Result<Json<IngestResponse>, ApiError> {
+    let db = state.database()?;
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let authorized = match (provided, state.ingest_token.as_deref()) {
+        (Some(provided), Some(expected)) => tokens_match(provided, expected),
+        _ => false,
+    };
+    if !authorized {
+        return Err(ApiError::new(
+            StatusCode::UNAUTHORIZED,
+            "Missing or invalid bearer token",
+        ));
+    }
+
+    let mut unknown: Vec<&str> = statuses
+        .iter()
+        .map(|status| status.line.as_str())
+        .filter(|line| lines::line_info(line).is_none())
+        .collect();
+    if !unknown.is_empty() {
+        unknown.sort_unstable();
+        unknown.dedup();
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("Unknown line identifier(s): {}", unknown.join(", ")),
+        ));
+    }
 
-    Json(statuses)
+    if statuses.is_empty() {
+        return Ok(Json(IngestResponse { inserted: 0 }));
+    }
+
+    let inserted = status_store::store_statuses(db, &statuses, state.status_retention).await?;
+
+    Ok(Json(IngestResponse { inserted }))
+}
+
+/// Key casing for JSON responses
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum KeyCase {
+    /// Native snake_case field names
+    #[default]
+    Snake,
+    /// camelCase field names for JavaScript clients
+    Camel,
+}
+
+/// Query parameters for the train positions endpoint
+#[derive(Debug, Default, Deserialize)]
+struct TrainQuery {
+    /// Key casing of the response, snake_case unless `case=camel`
+    #[serde(default)]
+    case: KeyCase,
+    /// Also list trains about to leave their origin, with `include_scheduled=true`
+    #[serde(default)]
+    include_scheduled: bool,
+}
+
+/// Positions listed for `query`, from the cached `trains`
+///
+/// # Returns
+/// - Trains in transit, followed by those waiting at their origin when
+///   `?include_scheduled=true`
+///
+/// # Errors
+/// - `503 Service Unavailable` if every feed failed, so clients can tell live
+///   data being unavailable apart from no trains running
+fn listed_positions(
+    trains: gtfs::TrainPositions,
+    query: &TrainQuery,
+) -> Result<Vec<backend::TrainPosition>, ApiError> {
+    if trains.all_feeds_failed() {
+        return Err(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("All {} GTFS feeds failed", trains.feeds_total),
+        ));
+    }
+    let mut positions = trains.positions;
+    if query.include_scheduled {
+        positions.extend(trains.scheduled);
+    }
+    Ok(positions)
 }
 
 /// Handler for fetching real-time train positions
@@ -71,48 +636,1742 @@ async fn get_subway_status(State(state): State<AppState>) -> Json<Vec<backend::S
 /// Retrieves current positions of all trains from GTFS feeds via the GTFS handler.
 ///
 /// # Returns
-/// - JSON array of [`TrainPosition`] objects representing current train locations
-async fn get_train_positions(State(state): State<AppState>) -> Json<Vec<backend::TrainPosition>> {
-    let positions = state.gtfs.get_train_positions().await.unwrap_or_default();
-    Json(positions)
+/// - JSON array of [`TrainPosition`] objects representing current train locations,
+///   with camelCase keys when requested via `?case=camel`, followed by trains
+///   waiting at their origin at progress 0.0 with `?include_scheduled=true`
+/// - `503 Service Unavailable` if every feed failed (see [`listed_positions`])
+async fn get_train_positions(
+    State(state): State<AppState>,
+    Query(query): Query<TrainQuery>,
+) -> Result<Response, ApiError> {
+    let trains = state.train_positions().await;
+    let positions = listed_positions(trains, &query)?;
+
+    Ok(match query.case {
+        KeyCase::Snake => Json(positions).into_response(),
+        KeyCase::Camel => Json(backend::CamelCase(positions)).into_response(),
+    })
+}
+
+/// Encodes one position as a line of newline-delimited JSON in `case`
+fn ndjson_line(position: &backend::TrainPosition, case: KeyCase) -> Bytes {
+    let mut line = match case {
+        KeyCase::Snake => serde_json::to_vec(position),
+        KeyCase::Camel => serde_json::to_vec(&backend::CamelCase(position)),
+    }
+    .expect("train positions always serialize");
+    line.push(b'\n');
+    Bytes::from(line)
+}
+
+/// Handler for streaming train positions as newline-delimited JSON
+///
+/// Takes the same query parameters as `GET /api/trains`, but writes one
+/// position per line as the body is streamed, so clients can handle trains
+/// without holding the whole array.
+///
+/// # Returns
+/// - `application/x-ndjson` body with one [`TrainPosition`] object per line
+/// - `503 Service Unavailable` if every feed failed (see [`listed_positions`])
+async fn get_train_positions_ndjson(
+    State(state): State<AppState>,
+    Query(query): Query<TrainQuery>,
+) -> Result<Response, ApiError> {
+    let trains = state.train_positions().await;
+    let positions = listed_positions(trains, &query)?;
+    let case = query.case;
+    let body = futures::stream::iter(positions)
+        .map(move |position| Ok::<_, std::convert::Infallible>(ndjson_line(&position, case)));
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        StreamBody::new(body),
+    )
+        .into_response())
+}
+
+/// Handler for the newest train positions stored by the collector
+///
+/// Reads the database only, so it keeps answering, with slightly stale
+/// positions, while the live GTFS feeds are down.
+///
+/// # Returns
+/// - JSON [`PositionSnapshot`](position_store::PositionSnapshot) with `recorded_at` and `positions`
+/// - `404 Not Found` if no positions have been stored
+async fn get_latest_train_positions(
+    State(state): State<AppState>,
+) -> Result<Json<position_store::PositionSnapshot>, ApiError> {
+    let snapshot = position_store::latest_positions(state.database()?)
+        .await?
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "No train positions stored yet"))?;
+    Ok(Json(snapshot))
+}
+
+/// Query parameters for the train replay endpoint
+#[derive(Debug, Deserialize)]
+struct ReplayQuery {
+    /// Time to replay (see [`parse_ts`])
+    at: String,
+}
+
+/// Handler for the train positions stored by the collector at a past time
+///
+/// Serves the newest snapshot recorded at or before `?at=`, which the
+/// frontend's replay slider shows as-is. Snapshots are kept for
+/// [`position_store::REPLAY_WINDOW_SECS`].
+///
+/// # Returns
+/// - JSON array of [`TrainPosition`]
+/// - `400 Bad Request` if `?at=` isn't a time (see [`parse_ts`])
+/// - `404 Not Found` if no snapshot was stored at or before `?at=`
+async fn get_train_replay(
+    State(state): State<AppState>,
+    Query(query): Query<ReplayQuery>,
+) -> Result<Json<Vec<backend::TrainPosition>>, ApiError> {
+    let db = state.database()?;
+    let at = parse_ts(&query.at)?;
+    let snapshot = position_store::positions_at(db, at).await?.ok_or_else(|| {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("No train positions stored at or before {}", at.to_rfc3339()),
+        )
+    })?;
+    Ok(Json(snapshot.positions))
+}
+
+/// Number of trains in transit, in total and per route
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct TrainCount {
+    /// Total trains in transit
+    count: usize,
+    /// Trains in transit keyed by route ID
+    by_route: BTreeMap<String, usize>,
+}
+
+impl TrainCount {
+    /// Counts `positions`, grouped by route ID
+    fn from_positions(positions: &[backend::TrainPosition]) -> Self {
+        let mut by_route = BTreeMap::new();
+        for position in positions {
+            *by_route.entry(position.route_id.clone()).or_insert(0) += 1;
+        }
+        Self {
+            count: positions.len(),
+            by_route,
+        }
+    }
+}
+
+/// Handler for counting trains in transit
+///
+/// # Returns
+/// - JSON [`TrainCount`] of the cached train positions
+async fn get_train_count(State(state): State<AppState>) -> Json<TrainCount> {
+    let trains = state.train_positions().await;
+    Json(TrainCount::from_positions(&trains.positions))
+}
+
+/// Handler for fetching train positions as map features
+///
+/// # Returns
+/// - GeoJSON `FeatureCollection` with one point per train, colored by route
+async fn get_train_geojson(State(state): State<AppState>) -> Json<GeoJsonCollection> {
+    let positions = state.train_positions().await.positions;
+
+    Json(GeoJsonCollection::from_features(
+        positions
+            .iter()
+            .map(backend::TrainPosition::to_geojson_feature)
+            .collect(),
+    ))
+}
+
+/// Query parameters for the station map layer
+#[derive(Debug, Default, Deserialize)]
+struct StopsGeoJsonQuery {
+    /// Comma-separated routes (e.g. `L,G`); stations served by any are kept
+    routes: Option<String>,
+}
+
+/// Handler for fetching stations as map features
+///
+/// `?routes=` keeps stations served by any of the listed routes, ignoring case.
+///
+/// # Returns
+/// - GeoJSON `FeatureCollection` with one point per station, colored by its first route
+async fn get_stops_geojson(
+    State(state): State<AppState>,
+    Query(query): Query<StopsGeoJsonQuery>,
+) -> Json<GeoJsonCollection> {
+    let routes: Option<Vec<String>> = query.routes.map(|routes| {
+        routes
+            .split(',')
+            .map(|route| route.trim().to_uppercase())
+            .filter(|route| !route.is_empty())
+            .collect()
+    });
+
+    Json(GeoJsonCollection::from_features(
+        state
+            .gtfs
+            .stations()
+            .iter()
+            .filter(|station| match &routes {
+                Some(routes) => station
+                    .routes
+                    .iter()
+                    .any(|route| routes.contains(&route.to_uppercase())),
+                None => true,
+            })
+            .map(backend::StopInfo::to_geojson_feature)
+            .collect(),
+    ))
+}
+
+/// Handler for reporting GTFS feed health
+///
+/// Each feed's entry is updated whenever train positions are fetched.
+///
+/// # Returns
+/// - JSON object mapping feed key to its [`FeedHealth`]
+async fn get_feed_status(State(state): State<AppState>) -> Json<HashMap<String, FeedHealth>> {
+    Json(state.feed_health.lock().clone())
+}
+
+/// Query parameters for the stations endpoint
+#[derive(Debug, Default, Deserialize)]
+struct StationsQuery {
+    /// Borough name or MTA abbreviation (e.g. `Bk`) to keep stops from
+    borough: Option<String>,
+}
+
+/// Whether an `If-None-Match` header value matches `etag`
+///
+/// The value may list several tags or be `*`; weak tags compare equal to their
+/// strong form.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag
+    })
+}
+
+/// Handler for listing known stops
+///
+/// `?borough=` keeps only stops in that borough, given as a full name or MTA
+/// abbreviation.
+///
+/// Responses carry an `ETag`. A filtered list is tagged with the full list's
+/// tag plus the borough, since it follows from both. A request whose
+/// `If-None-Match` matches gets `304 Not Modified` without a body.
+///
+/// # Returns
+/// - JSON array of [`StopLocation`] objects from the station cache, sorted by stop ID
+/// - `304 Not Modified` if the client already has this list
+/// - `400 Bad Request` if the borough is unknown
+async fn get_stations(
+    State(state): State<AppState>,
+    Query(query): Query<StationsQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let borough = query
+        .borough
+        .map(|borough| borough.parse::<backend::Borough>())
+        .transpose()
+        .map_err(|e: ParseBoroughError| ApiError::new(StatusCode::BAD_REQUEST, e.to_string()))?;
+    let stations_etag = state.gtfs.stations_etag();
+    let etag = match borough {
+        Some(borough) => format!(
+            "{}-{}\"",
+            stations_etag.trim_end_matches('"'),
+            borough.abbreviation()
+        ),
+        None => stations_etag,
+    };
+
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| etag_matches(value, &etag));
+    if not_modified {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+
+    let stops: Vec<backend::StopLocation> = match borough {
+        Some(borough) => state
+            .gtfs
+            .stops()
+            .into_iter()
+            .filter(|stop| state.gtfs.borough(&stop.stop_id) == Some(borough))
+            .collect(),
+        None => state.gtfs.stops(),
+    };
+    Ok(([(header::ETAG, etag)], Json(stops)).into_response())
+}
+
+/// Handler for looking up a single station
+///
+/// Accepts a parent (`L08`) or directional (`L08N`) stop ID. Current train
+/// positions are fetched to report whether a train is approaching.
+///
+/// # Returns
+/// - JSON [`StopInfo`](backend::StopInfo) keyed by the parent stop ID
+/// - `404 Not Found` if the station is unknown
+async fn get_station(
+    State(state): State<AppState>,
+    Path(stop_id): Path<String>,
+) -> Result<Json<backend::StopInfo>, ApiError> {
+    let mut station = state.gtfs.station(&stop_id).ok_or_else(|| {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("Unknown stop ID: {}", stop_id),
+        )
+    })?;
+
+    let positions = state.train_positions().await.positions;
+    station.train_approaching = gtfs::train_approaching(&positions, &station.stop_id);
+
+    Ok(Json(station))
+}
+
+/// Handler for a station's departure board
+///
+/// Accepts a parent or directional stop ID, like [`get_station`]. Only feeds
+/// carrying the station's routes are fetched.
+///
+/// # Returns
+/// - JSON array of [`DepartureGroup`](backend::DepartureGroup)s, one per route
+///   and direction, each with its next few departures soonest first
+/// - `404 Not Found` if the station is unknown
+async fn get_station_departures(
+    State(state): State<AppState>,
+    Path(stop_id): Path<String>,
+) -> Result<Json<Vec<backend::DepartureGroup>>, ApiError> {
+    let station = state.gtfs.station(&stop_id).ok_or_else(|| {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("Unknown stop ID: {}", stop_id),
+        )
+    })?;
+
+    Ok(Json(
+        state
+            .gtfs
+            .get_departures(&station, &state.feed_health)
+            .await,
+    ))
+}
+
+/// The GTFS-realtime feed carrying a route
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct RouteFeed {
+    /// Route ID as requested
+    route_id: String,
+    /// Feed key, as used in `FEEDS` (e.g. "ace")
+    key: String,
+    /// Full URL of the feed on the MTA API
+    url: String,
 }
 
+/// Handler reporting which feed carries a route, for clients fetching feeds themselves
+///
+/// # Returns
+/// - JSON [`RouteFeed`] with the feed's key and URL
+/// - `404 Not Found` if no feed carries the route
+async fn get_route_feed(Path(route_id): Path<String>) -> Result<Json<RouteFeed>, ApiError> {
+    let feed = backend::feeds::feed_for_route(&route_id).ok_or_else(|| {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("Unknown route: {}", route_id),
+        )
+    })?;
+    Ok(Json(RouteFeed {
+        key: feed.key.to_string(),
+        url: feed.url(),
+        route_id,
+    }))
+}
+
+/// Handler returning one decoded GTFS feed for debugging, only routed with `DEBUG_ENDPOINTS=1`
+///
+/// # Returns
+/// - The feed's [`FeedSnapshot`](gtfs::snapshot::FeedSnapshot) as pretty-printed JSON
+/// - `404 Not Found` if no feed has the key
+/// - `502 Bad Gateway` if the feed can't be fetched or decoded
+async fn get_debug_feed(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<Response, ApiError> {
+    let feed = state
+        .gtfs
+        .fetch_feed_by_key(&key)
+        .await
+        .map_err(|e| ApiError::new(StatusCode::BAD_GATEWAY, e.to_string()))?
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("Unknown feed: {}", key)))?;
+    let json = serde_json::to_string_pretty(&gtfs::snapshot::FeedSnapshot::from(&feed))
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(([(header::CONTENT_TYPE, "application/json")], json).into_response())
+}
+
+/// Fallback handler for paths that match no route
+///
+/// # Returns
+/// - `404 Not Found` with `{"error": "not found", "path": ...}`
+async fn not_found(uri: Uri) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(serde_json::json!({ "error": "not found", "path": uri.path() })),
+    )
+        .into_response()
+}
+
+/// Middleware giving axum's empty `405 Method Not Allowed` responses a JSON body
+///
+/// The `Allow` header set by the router is kept.
+async fn method_not_allowed<B>(request: Request<B>, next: Next<B>) -> Response {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let response = next.run(request).await;
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+        return response;
+    }
+
+    let (mut parts, _) = response.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let body = Json(serde_json::json!({
+        "error": "method not allowed",
+        "method": method.as_str(),
+        "path": path,
+    }));
+    (parts, body).into_response()
+}
+
+/// Builds the API router with rate limiting, compression and CORS applied to every route
+///
+/// The compression layer's default predicate leaves bodies under 32 bytes and
+/// already-encoded responses alone. Debug routes are only added when
+/// `DEBUG_ENDPOINTS` enables them.
+fn app(state: AppState) -> Router {
+    let mut router = Router::new()
+        .route(
+            "/api/subway/status",
+            get(get_subway_status).post(post_subway_status),
+        )
+        .route("/api/subway/status/grouped", get(get_subway_status_grouped))
+        .route("/api/subway/status/changes", get(get_subway_status_changes))
+        .route(
+            "/api/subway/status/latest-timestamp",
+            get(get_subway_status_latest_timestamp),
+        )
+        .route("/api/subway/history", get(get_subway_history))
+        .route("/api/subway/reliability", get(get_subway_reliability))
+        .route("/api/subway/history.csv", get(get_subway_history_csv))
+        .route("/api/trains", get(get_train_positions))
+        .route("/api/trains/count", get(get_train_count))
+        .route("/api/trains/latest", get(get_latest_train_positions))
+        .route("/api/trains/replay", get(get_train_replay))
+        .route("/api/trains.geojson", get(get_train_geojson))
+        .route("/api/trains.ndjson", get(get_train_positions_ndjson))
+        .route("/api/stops.geojson", get(get_stops_geojson))
+        .route("/api/feeds/status", get(get_feed_status))
+        .route("/api/routes/:route_id/feed", get(get_route_feed))
+        .route("/api/stations", get(get_stations))
+        .route("/api/stations/:stop_id", get(get_station))
+        .route(
+            "/api/stations/:stop_id/departures",
+            get(get_station_departures),
+        )
+        .route("/ws/status", get(status_ws::ws_status));
+    if state.debug_endpoints {
+        router = router.route("/api/debug/feed/:key", get(get_debug_feed));
+    }
+
+    router
+        .fallback(not_found)
+        .layer(middleware::from_fn(method_not_allowed))
+        .layer(middleware::from_fn_with_state(
+            state.rate_limiter.clone(),
+            rate_limit::rate_limit,
+        ))
+        .layer(CompressionLayer::new())
+        .layer(CorsLayer::permissive())
+        .layer(middleware::from_fn(request_id::request_id))
+        .with_state(state)
+}
+
+/// Placeholder database URL in demo mode, which never connects
+const DEMO_DATABASE_URL: &str = "postgres://localhost/nyc_pulse";
+
 /// Main entry point for the NYC Pulse backend server
 ///
 /// Sets up the database connection, GTFS handler, and web server with API routes.
-/// The server runs on port 3000 and accepts connections from any origin via CORS.
+/// The server listens on `BIND_ADDR`:`PORT` (default `0.0.0.0:3000`) and accepts
+/// connections from any origin via CORS.
+///
+/// Exits with a summary of every problem if the environment is invalid (see
+/// [`Config::from_env`]). Status ingestion stays disabled unless `INGEST_TOKEN`
+/// is set, and lines from `LINES_FILE` replace the built-in colors and names.
+/// With `DEMO_MODE=1` it connects to nothing and serves [`demo::demo_data`].
 ///
 /// # Errors
 /// Returns an error if:
 /// - Database connection fails
 /// - GTFS handler initialization fails
+/// - The status tables can't be created
 /// - Server fails to start
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenv().ok();
+    let config = Config::from_env().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    backend::logging::init(config.log_format);
+    backend::lines::install_lines(config.lines.clone());
+
+    let (db, gtfs) = if config.demo_mode {
+        // Never connected; the status and train handlers answer from demo data
+        let db = PgPool::connect_lazy(DEMO_DATABASE_URL)?;
+        (db, GtfsHandler::from_stop_locations(HashMap::new()))
+    } else {
+        let db = PgPool::connect(&config.database_url)
+            .await
+            .expect("Failed to connect to database");
+        status_store::ensure_schema(&db, config.status_retention).await?;
+        let gtfs = GtfsHandler::new(config.feeds, config.station_source)
+            .await?
+            .with_idle_trains(config.include_idle_trains)
+            .with_feed_concurrency(config.feed_concurrency);
+        (db, gtfs)
+    };
+
+    let mut state = AppState::new(
+        db,
+        gtfs,
+        RateLimiter::new(config.rate_limit_per_min),
+        config.ingest_token,
+        config.status_retention,
+    );
+    state.debug_endpoints = config.debug_endpoints;
+    state.query_timeout = config.query_timeout;
+    state.demo_mode = config.demo_mode;
+
+    if config.demo_mode {
+        tracing::info!("Demo mode: serving canned statuses and train positions");
+    } else {
+        tokio::spawn(status_ws::poll_statuses(
+            state.db.clone(),
+            state.status_hub.clone(),
+        ));
+        tokio::spawn(train_cache::refresh_trains(
+            state.gtfs.clone(),
+            state.feed_health.clone(),
+            state.trains.clone(),
+        ));
+    }
+
+    let server = axum::Server::try_bind(&config.bind_addr)?
+        .serve(app(state).into_make_service_with_connect_info::<SocketAddr>());
+    println!("Server running on http://{}", server.local_addr());
+    server.await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use nyc_pulse_backend::config::DEFAULT_RATE_LIMIT_PER_MIN;
+    use sqlx::postgres::PgPoolOptions;
+    use tower::ServiceExt;
+
+    fn test_state() -> AppState {
+        let db = PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/nyc_pulse")
+            .unwrap();
+        AppState::new(
+            db,
+            GtfsHandler::from_stop_locations(HashMap::new()),
+            RateLimiter::new(DEFAULT_RATE_LIMIT_PER_MIN),
+            Some(TEST_INGEST_TOKEN.to_string()),
+            StatusRetention::History,
+        )
+    }
+
+    const TEST_INGEST_TOKEN: &str = "test-token";
+
+    fn ingest_request(token: Option<&str>, body: serde_json::Value) -> Request<Body> {
+        let mut builder = Request::builder()
+            .method("POST")
+            .uri("/api/subway/status")
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(token) = token {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        builder.body(Body::from(body.to_string())).unwrap()
+    }
+
+    fn status_json(line: &str) -> serde_json::Value {
+        serde_json::json!({
+            "line": line,
+            "status": "Delays",
+            "timestamp": "2024-01-01T12:00:00Z",
+            "delays": true,
+        })
+    }
+
+    fn get_request(uri: &str) -> Request<Body> {
+        Request::builder().uri(uri).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_feed_status_reports_failed_feed() {
+        let state = test_state();
+        let failed_key = "ace";
+        state
+            .feed_health
+            .lock()
+            .get_mut(failed_key)
+            .unwrap()
+            .record_failure("connection refused".to_string());
 
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let Json(status) = get_feed_status(State(state)).await;
 
-    let db = PgPool::connect(&database_url)
+        assert_eq!(status.len(), backend::feeds::SUBWAY_FEEDS.len());
+        let failed = &status[failed_key];
+        assert_eq!(failed.consecutive_failures, 1);
+        assert_eq!(failed.last_error.as_deref(), Some("connection refused"));
+        assert_eq!(status["l"], FeedHealth::default());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_rejects_past_limit() {
+        let mut state = test_state();
+        state.rate_limiter = RateLimiter::new(5);
+        let app = app(state);
+
+        for _ in 0..5 {
+            let response = app
+                .clone()
+                .oneshot(get_request("/api/feeds/status"))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let response = app.oneshot(get_request("/api/feeds/status")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = response.headers()[header::RETRY_AFTER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=12).contains(&retry_after));
+    }
+
+    #[tokio::test]
+    async fn test_large_stations_response_is_gzipped() {
+        let stop_locations = (0..500)
+            .map(|i| (format!("{:03}N", i), (40.7, -73.9)))
+            .collect();
+        let mut state = test_state();
+        state.gtfs = GtfsHandler::from_stop_locations(stop_locations);
+
+        let request = Request::builder()
+            .uri("/api/stations")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let response = app(state).oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+    }
+
+    #[tokio::test]
+    async fn test_tiny_response_is_not_compressed() {
+        let request = Request::builder()
+            .uri("/api/stations")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let response = app(test_state()).oneshot(request).await.unwrap();
+
+        assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"[]");
+    }
+
+    #[tokio::test]
+    async fn test_ingest_rejects_missing_or_wrong_token() {
+        let app = app(test_state());
+        let body = serde_json::json!([status_json("A")]);
+
+        for token in [None, Some("wrong-token")] {
+            let response = app
+                .clone()
+                .oneshot(ingest_request(token, body.clone()))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ingest_disabled_without_token() {
+        let mut state = test_state();
+        state.ingest_token = None;
+
+        let response = app(state)
+            .oneshot(ingest_request(
+                Some(TEST_INGEST_TOKEN),
+                serde_json::json!([]),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_ingest_accepts_valid_token() {
+        let response = app(test_state())
+            .oneshot(ingest_request(
+                Some(TEST_INGEST_TOKEN),
+                serde_json::json!([]),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let parsed: IngestResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(parsed, IngestResponse { inserted: 0 });
+    }
+
+    #[tokio::test]
+    async fn test_ingest_rejects_unknown_lines() {
+        let body = serde_json::json!([status_json("A"), status_json("X"), status_json("9")]);
+        let response = app(test_state())
+            .oneshot(ingest_request(Some(TEST_INGEST_TOKEN), body))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error["error"], "Unknown line identifier(s): 9, X");
+    }
+
+    /// Creates the schema in a fresh `#[sqlx::test]` database
+    async fn apply_schema(db: &PgPool) {
+        sqlx::Executor::execute(db, include_str!("../schema.sql"))
+            .await
+            .unwrap();
+    }
+
+    fn db_state(db: PgPool) -> AppState {
+        AppState::new(
+            db,
+            GtfsHandler::from_stop_locations(HashMap::new()),
+            RateLimiter::new(DEFAULT_RATE_LIMIT_PER_MIN),
+            Some(TEST_INGEST_TOKEN.to_string()),
+            StatusRetention::History,
+        )
+    }
+
+    async fn get_json(app: &Router, uri: &str) -> serde_json::Value {
+        let response = app.clone().oneshot(get_request(uri)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[test]
+    fn test_parse_ts_accepts_rfc3339_and_unix_seconds() {
+        let noon = chrono::Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        assert_eq!(parse_ts("2024-01-01T12:00:00Z").unwrap(), noon);
+        assert_eq!(parse_ts("2024-01-01T07:00:00-05:00").unwrap(), noon);
+        assert_eq!(parse_ts(" 1704110400 ").unwrap(), noon);
+        assert_eq!(parse_ts("0").unwrap().timestamp(), 0);
+    }
+
+    #[test]
+    fn test_parse_ts_rejects_garbage() {
+        for value in [
+            "",
+            "yesterday",
+            "2024-01-01",
+            "1704110400.5",
+            "99999999999999999",
+        ] {
+            let error = parse_ts(value).unwrap_err();
+            assert_eq!(error.status, StatusCode::BAD_REQUEST, "{:?}", value);
+            assert!(error.message.contains("RFC 3339"), "{}", error.message);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bad_since_is_a_json_400() {
+        let app = app(test_state());
+        for uri in [
+            "/api/subway/status/changes?since=yesterday",
+            "/api/subway/history?since=yesterday",
+            "/api/subway/history.csv?since=yesterday",
+            "/api/trains/replay?at=yesterday",
+        ] {
+            let response = app.clone().oneshot(get_request(uri)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert!(body["error"].as_str().unwrap().contains("yesterday"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_query_times_out() {
+        let slow_query = tokio::time::sleep(Duration::from_secs(60));
+        let error = with_query_timeout(Duration::from_millis(10), slow_query)
+            .await
+            .unwrap_err();
+        assert_eq!(error.status, StatusCode::SERVICE_UNAVAILABLE);
+
+        let fast_query = async { 7 };
+        assert_eq!(
+            with_query_timeout(Duration::from_secs(1), fast_query)
+                .await
+                .unwrap(),
+            7
+        );
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_subway_status_times_out_on_locked_table(db: PgPool) {
+        apply_schema(&db).await;
+        // Hold a lock so the status query waits until the transaction ends
+        let mut lock = db.begin().await.unwrap();
+        sqlx::query("LOCK TABLE subway_status IN ACCESS EXCLUSIVE MODE")
+            .execute(&mut *lock)
+            .await
+            .unwrap();
+        let mut state = db_state(db);
+        state.query_timeout = Duration::from_millis(200);
+
+        let response = app(state)
+            .oneshot(get_request("/api/subway/status"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        lock.rollback().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_subway_status_database_error_is_a_500() {
+        let mut state = test_state();
+        // Nothing listens on port 9, so the query fails before the timeout
+        state.db = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(100))
+            .connect_lazy("postgres://127.0.0.1:9/nyc_pulse")
+            .unwrap();
+        state.query_timeout = Duration::from_secs(5);
+
+        let response = app(state)
+            .oneshot(get_request("/api/subway/status"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(body["error"].is_string());
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_subway_status_returns_latest_per_line(db: PgPool) {
+        apply_schema(&db).await;
+        sqlx::query(
+            r#"
+            INSERT INTO subway_status (line, status, timestamp, delays) VALUES
+                ('L', 'Delays', '2024-01-01T12:00:00Z', true),
+                ('L', 'Good Service', '2024-01-01T12:05:00Z', false),
+                ('A', 'Delays', '2024-01-01T12:01:00Z', true)
+            "#,
+        )
+        .execute(&db)
         .await
-        .expect("Failed to connect to database");
+        .unwrap();
 
-    let state = AppState {
-        db,
-        gtfs: GtfsHandler::new().await?,
-    };
+        let statuses = get_json(&app(db_state(db)), "/api/subway/status").await;
 
-    let app = Router::new()
-        .route("/api/subway/status", get(get_subway_status))
-        .route("/api/trains", get(get_train_positions))
-        .layer(CorsLayer::permissive())
-        .with_state(state);
+        assert_eq!(
+            statuses,
+            serde_json::json!([
+                {
+                    "line": "A",
+                    "status": "Delays",
+                    "timestamp": "2024-01-01T12:01:00Z",
+                    "delays": true,
+                    // Rows without a transition time fall back to their timestamp
+                    "changed_at": "2024-01-01T12:01:00Z",
+                },
+                {
+                    "line": "L",
+                    "status": "Good Service",
+                    "timestamp": "2024-01-01T12:05:00Z",
+                    "delays": false,
+                    "changed_at": "2024-01-01T12:05:00Z",
+                },
+            ])
+        );
+    }
 
-    println!("Server running on http://localhost:3000");
-    axum::Server::bind(&"0.0.0.0:3000".parse().unwrap())
-        .serve(app.into_make_service())
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_subway_status_grouped_by_division(db: PgPool) {
+        apply_schema(&db).await;
+        sqlx::query(
+            r#"
+            INSERT INTO subway_status (line, status, timestamp, delays) VALUES
+                ('3', 'Good Service', '2024-01-01T12:00:00Z', false),
+                ('1', 'Delays', '2024-01-01T12:00:00Z', true),
+                ('2', 'Good Service', '2024-01-01T12:00:00Z', false),
+                ('L', 'Good Service', '2024-01-01T12:00:00Z', false)
+            "#,
+        )
+        .execute(&db)
         .await
         .unwrap();
 
-    Ok(())
+        let grouped = get_json(&app(db_state(db)), "/api/subway/status/grouped").await;
+
+        let lines = |division: &str| -> Vec<String> {
+            grouped[division]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|status| status["line"].as_str().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(lines("irt"), ["1", "2", "3"]);
+        assert_eq!(lines("bmt"), ["L"]);
+        assert!(lines("ind").is_empty());
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_latest_train_positions_from_db(db: PgPool) {
+        apply_schema(&db).await;
+        // Feeds at 127.0.0.1:9 all fail; the stored snapshot is served anyway
+        let mut state = db_state(db.clone());
+        state.gtfs = state.gtfs.with_feed_base_url("http://127.0.0.1:9/");
+        let app = app(state);
+
+        let response = app
+            .clone()
+            .oneshot(get_request("/api/trains/latest"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let snapshot = position_store::PositionSnapshot {
+            recorded_at: "2024-01-01T12:00:00Z".parse().unwrap(),
+            positions: vec![route_position("063350_L..N01R", "L")],
+        };
+        position_store::store_positions(&db, &snapshot)
+            .await
+            .unwrap();
+
+        let latest = get_json(&app, "/api/trains/latest").await;
+        assert_eq!(latest["recorded_at"], "2024-01-01T12:00:00Z");
+        assert_eq!(latest["positions"][0]["trip_id"], "063350_L..N01R");
+        assert_eq!(latest["positions"].as_array().unwrap().len(), 1);
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_latest_timestamp_endpoint(db: PgPool) {
+        apply_schema(&db).await;
+        let app = app(db_state(db.clone()));
+
+        let response = app
+            .clone()
+            .oneshot(get_request("/api/subway/status/latest-timestamp"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        sqlx::query(
+            r#"
+            INSERT INTO subway_status (line, status, timestamp, delays) VALUES
+                ('L', 'Good Service', '2024-01-01T12:05:00Z', false),
+                ('A', 'Delays', '2024-01-01T12:01:00Z', true)
+            "#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let latest = get_json(&app, "/api/subway/status/latest-timestamp").await;
+        assert_eq!(
+            latest,
+            serde_json::json!({ "timestamp": "2024-01-01T12:05:00Z" })
+        );
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_subway_status_delays_only(db: PgPool) {
+        apply_schema(&db).await;
+        sqlx::query(
+            r#"
+            INSERT INTO subway_status (line, status, timestamp, delays) VALUES
+                ('L', 'Delays', '2024-01-01T12:00:00Z', true),
+                ('L', 'Good Service', '2024-01-01T12:05:00Z', false),
+                ('A', 'Delays', '2024-01-01T12:01:00Z', true),
+                ('G', 'Good Service', '2024-01-01T12:00:00Z', false),
+                ('7', 'Delays', '2024-01-01T12:02:00Z', true)
+            "#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        let app = app(db_state(db));
+
+        let delayed = get_json(&app, "/api/subway/status?delays=true").await;
+        let lines: Vec<&str> = delayed
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|status| status["line"].as_str().unwrap())
+            .collect();
+        assert_eq!(lines, ["7", "A"]);
+
+        let all = get_json(&app, "/api/subway/status").await;
+        assert_eq!(all.as_array().unwrap().len(), 4);
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_history_csv_applies_filters(db: PgPool) {
+        apply_schema(&db).await;
+        sqlx::query(
+            r#"
+            INSERT INTO subway_status (line, status, timestamp, delays) VALUES
+                ('L', 'Delays', '2024-01-01T12:00:00Z', true),
+                ('L', 'Good Service', '2024-01-01T12:05:00Z', false),
+                ('A', 'Delays', '2024-01-01T12:01:00Z', true)
+            "#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        let response = app(db_state(db))
+            .oneshot(get_request(
+                "/api/subway/history.csv?line=L&since=2024-01-01T12:01:00Z",
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/csv; charset=utf-8"
+        );
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            std::str::from_utf8(&body).unwrap(),
+            "line,status,timestamp,delays\nL,Good Service,2024-01-01T12:05:00Z,false\n"
+        );
+    }
+
+    #[test]
+    fn test_history_csv_record_quotes_fields() {
+        let timestamp = chrono::DateTime::parse_from_rfc3339("2024-01-01T12:00:00.5Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        let status = backend::SubwayStatus {
+            line: "A".to_string(),
+            status: "Delays, signal problems".to_string(),
+            timestamp,
+            delays: true,
+            changed_at: timestamp,
+        };
+
+        assert_eq!(
+            history_csv_record(&status),
+            b"A,\"Delays, signal problems\",2024-01-01T12:00:00Z,true\n"
+        );
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_ingested_status_is_served(db: PgPool) {
+        apply_schema(&db).await;
+        let app = app(db_state(db));
+
+        let response = app
+            .clone()
+            .oneshot(ingest_request(
+                Some(TEST_INGEST_TOKEN),
+                serde_json::json!([status_json("G")]),
+            ))
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let parsed: IngestResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(parsed, IngestResponse { inserted: 1 });
+
+        // A line seen for the first time changed when it was recorded
+        let mut expected = status_json("G");
+        expected["changed_at"] = expected["timestamp"].clone();
+        let statuses = get_json(&app, "/api/subway/status").await;
+        assert_eq!(statuses, serde_json::json!([expected]));
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_ingesting_unchanged_status_inserts_nothing(db: PgPool) {
+        apply_schema(&db).await;
+        let app = app(db_state(db));
+
+        for expected in [1, 0] {
+            let response = app
+                .clone()
+                .oneshot(ingest_request(
+                    Some(TEST_INGEST_TOKEN),
+                    serde_json::json!([status_json("G")]),
+                ))
+                .await
+                .unwrap();
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let parsed: IngestResponse = serde_json::from_slice(&body).unwrap();
+            assert_eq!(parsed, IngestResponse { inserted: expected });
+        }
+    }
+
+    /// State whose stations come from the test fixture and whose feeds all fail fast
+    fn fixture_station_state() -> AppState {
+        let path =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/stations.json");
+        let mut state = test_state();
+        state.gtfs = GtfsHandler::from_stations_file(&path)
+            .unwrap()
+            .with_feed_base_url("http://127.0.0.1:9/");
+        state
+    }
+
+    #[tokio::test]
+    async fn test_station_by_parent_or_directional_id() {
+        let app = app(fixture_station_state());
+
+        for uri in ["/api/stations/L08", "/api/stations/L08S"] {
+            let station = get_json(&app, uri).await;
+            assert_eq!(station["stop_id"], "L08", "{}", uri);
+            assert_eq!(station["stop_name"], "Bedford Av");
+            assert_eq!(station["routes"], serde_json::json!(["L"]));
+            assert_eq!(station["borough"], "Brooklyn");
+            assert_eq!(station["train_approaching"], false);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stations_filtered_by_borough() {
+        let app = app(fixture_station_state());
+
+        for borough in ["Bk", "brooklyn"] {
+            let stops = get_json(&app, &format!("/api/stations?borough={}", borough)).await;
+            let ids: Vec<&str> = stops
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|stop| stop["stop_id"].as_str().unwrap())
+                .collect();
+            assert!(ids.contains(&"L08N"), "{}", borough);
+            assert!(!ids.contains(&"101N"), "{}", borough);
+        }
+
+        let response = app
+            .oneshot(get_request("/api/stations?borough=Jersey"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_stations_etag_returns_not_modified() {
+        let app = app(fixture_station_state());
+
+        for uri in ["/api/stations", "/api/stations?borough=Bk"] {
+            let response = app.clone().oneshot(get_request(uri)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let etag = response.headers()[header::ETAG].clone();
+
+            let request = Request::builder()
+                .uri(uri)
+                .header(header::IF_NONE_MATCH, etag.clone())
+                .body(Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_MODIFIED, "{}", uri);
+            assert_eq!(response.headers()[header::ETAG], etag);
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            assert!(body.is_empty());
+        }
+
+        let request = Request::builder()
+            .uri("/api/stations")
+            .header(header::IF_NONE_MATCH, "\"stale\"")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_etag_matches() {
+        assert!(etag_matches("\"abc\"", "\"abc\""));
+        assert!(etag_matches("\"x\", W/\"abc\"", "\"abc\""));
+        assert!(etag_matches("*", "\"abc\""));
+        assert!(!etag_matches("\"abcd\"", "\"abc\""));
+    }
+
+    /// Serves `feed` at every path and returns the server's base URL
+    fn spawn_feed_server(feed: gtfs_rt::FeedMessage) -> String {
+        let body = prost::Message::encode_to_vec(&feed);
+        let app = Router::new().fallback(move || {
+            let body = body.clone();
+            async move { body }
+        });
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+        format!("http://{}/", addr)
+    }
+
+    #[tokio::test]
+    async fn test_debug_feed_returns_decoded_json() {
+        let feed = gtfs_rt::FeedMessage {
+            header: gtfs_rt::FeedHeader {
+                gtfs_realtime_version: "2.0".to_string(),
+                timestamp: Some(1_700_000_000),
+                ..Default::default()
+            },
+            entity: vec![gtfs_rt::FeedEntity {
+                id: "000001A".to_string(),
+                ..Default::default()
+            }],
+        };
+        let mut state = test_state();
+        state.gtfs = GtfsHandler::from_stop_locations(HashMap::new())
+            .with_feed_base_url(spawn_feed_server(feed));
+
+        // Off unless DEBUG_ENDPOINTS enables it
+        let response = app(state.clone())
+            .oneshot(get_request("/api/debug/feed/ace"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        state.debug_endpoints = true;
+        let app = app(state);
+        let response = app
+            .clone()
+            .oneshot(get_request("/api/debug/feed/ace"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.contains("\n  "), "not pretty-printed: {}", body);
+        let json: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(json["timestamp"], 1_700_000_000);
+        assert_eq!(json["entities"][0]["id"], "000001A");
+
+        let response = app
+            .oneshot(get_request("/api/debug/feed/xyz"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_trains_unavailable_when_every_feed_fails() {
+        let response = app(fixture_station_state())
+            .oneshot(get_request("/api/trains"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // Feeds that answer with no trains are an empty 200, not an outage
+        let mut state = test_state();
+        state.gtfs = GtfsHandler::from_stop_locations(HashMap::new())
+            .with_feed_base_url(spawn_feed_server(gtfs_rt::FeedMessage::default()));
+        let trains = get_json(&app(state), "/api/trains").await;
+        assert_eq!(trains, serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_train_requests_share_cached_fetch() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        let body = prost::Message::encode_to_vec(&gtfs_rt::FeedMessage::default());
+        let counted = requests.clone();
+        let feeds = Router::new().fallback(move || {
+            counted.fetch_add(1, Ordering::SeqCst);
+            let body = body.clone();
+            async move { body }
+        });
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(feeds.into_make_service()),
+        );
+
+        let mut state = test_state();
+        state.gtfs = GtfsHandler::from_stop_locations(HashMap::new())
+            .with_feed_base_url(format!("http://{}/", addr));
+        let feed_count = state.gtfs.feeds().len();
+        let app = app(state);
+
+        // Requests racing on the empty cache wait for a single refill
+        let responses = futures::future::join_all(
+            (0..10).map(|_| app.clone().oneshot(get_request("/api/trains"))),
+        )
+        .await;
+        for response in responses {
+            assert_eq!(response.unwrap().status(), StatusCode::OK);
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), feed_count);
+
+        for uri in ["/api/trains", "/api/trains", "/api/trains/count"] {
+            get_json(&app, uri).await;
+        }
+        // Later requests are served from the cache
+        assert_eq!(requests.load(Ordering::SeqCst), feed_count);
+    }
+
+    #[tokio::test]
+    async fn test_train_geojson_is_a_feature_collection() {
+        let collection = get_json(&app(fixture_station_state()), "/api/trains.geojson").await;
+
+        assert_eq!(
+            collection,
+            serde_json::json!({ "type": "FeatureCollection", "features": [] })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stops_geojson_filtered_by_routes() {
+        let app = app(fixture_station_state());
+
+        let all = get_json(&app, "/api/stops.geojson").await;
+        assert_eq!(all["type"], "FeatureCollection");
+        let all = all["features"].as_array().unwrap().len();
+
+        let collection = get_json(&app, "/api/stops.geojson?routes=l").await;
+        let features = collection["features"].as_array().unwrap();
+        assert_eq!(features.len(), 24);
+        assert!(features.len() < all);
+        for feature in features {
+            assert_eq!(feature["properties"]["lines"], "L", "{}", feature);
+            assert_eq!(feature["properties"]["color"], lines::route_color("L"));
+            assert_eq!(feature["geometry"]["type"], "Point");
+        }
+        let bedford = features
+            .iter()
+            .find(|feature| feature["properties"]["name"] == "Bedford Av")
+            .unwrap();
+        assert_eq!(bedford["properties"]["borough"], "Brooklyn");
+        assert_eq!(
+            bedford["properties"]["geohash"],
+            nyc_pulse_common::geohash::encode(
+                40.719533,
+                -73.980681,
+                nyc_pulse_common::geohash::GEOHASH_PRECISION
+            )
+        );
+        assert_eq!(
+            bedford["geometry"]["coordinates"],
+            serde_json::json!([-73.980681, 40.719533])
+        );
+    }
+
+    fn route_position(trip_id: &str, route_id: &str) -> backend::TrainPosition {
+        let stop = backend::StopLocation::new("L08N".to_string(), None, 40.717304, -73.956872);
+        backend::TrainPosition {
+            trip_id: trip_id.to_string(),
+            route_id: route_id.to_string(),
+            is_known: true,
+            express: false,
+            from_stop: stop.clone(),
+            to_stop: stop,
+            progress: 0.5,
+            start_time: 0,
+            end_time: 60,
+            direction: None,
+            destination_stop_id: None,
+            destination_name: None,
+            occupancy: None,
+            remaining_stops: Vec::new(),
+            eta_to_destination_secs: None,
+            speed_kmh: None,
+        }
+    }
+
+    #[test]
+    fn test_train_count_by_route_sums_to_total() {
+        let positions: Vec<_> = [("1", "A"), ("2", "A"), ("3", "L"), ("4", "GS"), ("5", "A")]
+            .iter()
+            .map(|(trip, route)| route_position(trip, route))
+            .collect();
+
+        let count = TrainCount::from_positions(&positions);
+
+        assert_eq!(count.count, 5);
+        assert_eq!(count.by_route["A"], 3);
+        assert_eq!(count.by_route["L"], 1);
+        assert_eq!(count.by_route.values().sum::<usize>(), count.count);
+    }
+
+    #[tokio::test]
+    async fn test_scheduled_trains_only_listed_on_request() {
+        let state = fixture_station_state();
+        let mut scheduled = route_position("2", "L");
+        scheduled.progress = 0.0;
+        scheduled.start_time = chrono::Utc::now().timestamp() + 120;
+        state.trains.store(gtfs::TrainPositions {
+            positions: vec![route_position("1", "L")],
+            scheduled: vec![scheduled],
+            feeds_total: 1,
+            feeds_failed: 0,
+        });
+        let app = app(state);
+
+        let trains = get_json(&app, "/api/trains").await;
+        assert_eq!(trains.as_array().unwrap().len(), 1);
+
+        let trains = get_json(&app, "/api/trains?include_scheduled=true").await;
+        let trip_ids: Vec<_> = trains
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|train| train["trip_id"].as_str().unwrap())
+            .collect();
+        assert_eq!(trip_ids, ["1", "2"]);
+    }
+
+    #[tokio::test]
+    async fn test_train_positions_stream_as_ndjson() {
+        let state = fixture_station_state();
+        state.trains.store(gtfs::TrainPositions {
+            positions: vec![route_position("1", "L"), route_position("2", "G")],
+            scheduled: vec![route_position("3", "L")],
+            feeds_total: 1,
+            feeds_failed: 0,
+        });
+        let app = app(state);
+
+        let response = app
+            .clone()
+            .oneshot(get_request("/api/trains.ndjson"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/x-ndjson"
+        );
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.ends_with('\n'), "{:?}", body);
+        let positions: Vec<backend::TrainPosition> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let routes: Vec<_> = positions
+            .iter()
+            .map(|position| (position.trip_id.as_str(), position.route_id.as_str()))
+            .collect();
+        assert_eq!(routes, [("1", "L"), ("2", "G")]);
+
+        let response = app
+            .oneshot(get_request(
+                "/api/trains.ndjson?include_scheduled=true&case=camel",
+            ))
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let trip_ids: Vec<String> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let position: serde_json::Value = serde_json::from_str(line).unwrap();
+                position["tripId"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(trip_ids, ["1", "2", "3"]);
+    }
+
+    #[tokio::test]
+    async fn test_demo_mode_serves_fixture_data() {
+        let mut state = test_state();
+        // Nothing listens on port 9, so reaching the database would fail
+        state.db = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(100))
+            .connect_lazy("postgres://127.0.0.1:9/nyc_pulse")
+            .unwrap();
+        state.demo_mode = true;
+        let app = app(state);
+        let demo = demo::demo_data();
+
+        let statuses = get_json(&app, "/api/subway/status").await;
+        assert_eq!(statuses, serde_json::to_value(&demo.statuses).unwrap());
+        let delayed = get_json(&app, "/api/subway/status?delays=true").await;
+        assert_eq!(delayed, serde_json::to_value(demo.statuses(true)).unwrap());
+
+        let trains = get_json(&app, "/api/trains").await;
+        assert_eq!(trains, serde_json::to_value(&demo.trains).unwrap());
+        let count = get_json(&app, "/api/trains/count").await;
+        assert_eq!(count["count"], demo.trains.len());
+    }
+
+    #[tokio::test]
+    async fn test_demo_mode_database_endpoints_are_unavailable() {
+        let mut state = test_state();
+        // Nothing listens on port 9, so reaching the database would fail
+        state.db = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(100))
+            .connect_lazy("postgres://127.0.0.1:9/nyc_pulse")
+            .unwrap();
+        state.demo_mode = true;
+        let app = app(state);
+
+        let mut requests: Vec<Request<Body>> = [
+            "/api/subway/status/grouped",
+            "/api/subway/status/changes?since=0",
+            "/api/subway/status/latest-timestamp",
+            "/api/subway/history",
+            "/api/subway/history.csv",
+            "/api/subway/reliability",
+            "/api/trains/latest",
+            "/api/trains/replay?at=0",
+        ]
+        .into_iter()
+        .map(get_request)
+        .collect();
+        requests.push(ingest_request(
+            Some(TEST_INGEST_TOKEN),
+            serde_json::json!([status_json("L")]),
+        ));
+
+        for request in requests {
+            let uri = request.uri().clone();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(
+                response.status(),
+                StatusCode::SERVICE_UNAVAILABLE,
+                "{}",
+                uri
+            );
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["error"], "Unavailable in demo mode", "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_train_count_by_route() {
+        let state = fixture_station_state();
+        state.trains.store(gtfs::TrainPositions {
+            positions: vec![route_position("1", "L")],
+            scheduled: Vec::new(),
+            feeds_total: 1,
+            feeds_failed: 0,
+        });
+        let app = app(state);
+
+        let count = get_json(&app, "/api/trains/count").await;
+
+        assert_eq!(
+            count,
+            serde_json::json!({ "count": 1, "by_route": { "L": 1 } })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_departures_for_unknown_station_return_404() {
+        let response = app(fixture_station_state())
+            .oneshot(get_request("/api/stations/X99/departures"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_station_returns_404() {
+        let response = app(fixture_station_state())
+            .oneshot(get_request("/api/stations/X99N"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error["error"], "Unknown stop ID: X99N");
+    }
+
+    #[tokio::test]
+    async fn test_route_feed_lookup() {
+        let app = app(test_state());
+
+        for (route_id, key) in [("A", "ace"), ("7", "1234567"), ("SI", "si")] {
+            let feed = get_json(&app, &format!("/api/routes/{}/feed", route_id)).await;
+            assert_eq!(feed["route_id"], route_id);
+            assert_eq!(feed["key"], key, "{}", route_id);
+            assert!(feed["url"]
+                .as_str()
+                .unwrap()
+                .starts_with(backend::feeds::MTA_FEED_BASE_URL));
+        }
+
+        let response = app
+            .oneshot(get_request("/api/routes/XX/feed"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_request_id_generated_when_absent() {
+        let response = app(test_state())
+            .oneshot(get_request("/api/nope"))
+            .await
+            .unwrap();
+
+        let id = response.headers()[&request_id::X_REQUEST_ID]
+            .to_str()
+            .unwrap();
+        assert!(uuid::Uuid::parse_str(id).is_ok(), "{}", id);
+    }
+
+    #[tokio::test]
+    async fn test_request_id_echoed_unchanged() {
+        let request = Request::builder()
+            .uri("/api/feeds/status")
+            .header("X-Request-Id", "frontend-42")
+            .body(Body::empty())
+            .unwrap();
+        let response = app(test_state()).oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[&request_id::X_REQUEST_ID], "frontend-42");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_route_returns_json_404() {
+        let response = app(test_state())
+            .oneshot(get_request("/api/nope"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            error,
+            serde_json::json!({ "error": "not found", "path": "/api/nope" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_documented_routes_are_registered() {
+        let mut state = fixture_station_state();
+        // Nothing listens on port 9, so database handlers fail fast instead of 404ing
+        state.db = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(100))
+            .connect_lazy("postgres://127.0.0.1:9/nyc_pulse")
+            .unwrap();
+        state.query_timeout = Duration::from_millis(100);
+        state.debug_endpoints = true;
+        state.trains.store(gtfs::TrainPositions {
+            positions: vec![route_position("1", "L")],
+            scheduled: Vec::new(),
+            feeds_total: 1,
+            feeds_failed: 0,
+        });
+        let app = app(state);
+
+        let mut requests: Vec<Request<Body>> = [
+            "/api/subway/status",
+            "/api/subway/status/grouped",
+            "/api/subway/status/changes?since=0",
+            "/api/subway/status/latest-timestamp",
+            "/api/subway/history",
+            "/api/subway/history.csv",
+            "/api/subway/reliability",
+            "/api/trains",
+            "/api/trains/count",
+            "/api/trains/latest",
+            "/api/trains/replay?at=0",
+            "/api/trains.geojson",
+            "/api/trains.ndjson",
+            "/api/feeds/status",
+            "/api/stations",
+            "/api/stops.geojson",
+            "/api/stations/L08",
+            "/api/stations/L08/departures",
+            "/api/routes/L/feed",
+            "/api/debug/feed/l",
+            "/ws/status",
+        ]
+        .into_iter()
+        .map(get_request)
+        .collect();
+        requests.push(ingest_request(
+            Some(TEST_INGEST_TOKEN),
+            serde_json::json!([]),
+        ));
+
+        for request in requests {
+            let route = format!("{} {}", request.method(), request.uri());
+            let response = app.clone().oneshot(request).await.unwrap();
+            if response.status() != StatusCode::NOT_FOUND {
+                continue;
+            }
+            // Handlers may 404 too, but only the fallback answers "not found"
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_ne!(error["error"], "not found", "{} isn't routed", route);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wrong_method_returns_json_405() {
+        let request = Request::builder()
+            .method("DELETE")
+            .uri("/api/trains")
+            .body(Body::empty())
+            .unwrap();
+        let response = app(test_state()).oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert!(response.headers().contains_key(header::ALLOW));
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error["error"], "method not allowed");
+        assert_eq!(error["method"], "DELETE");
+        assert_eq!(error["path"], "/api/trains");
+    }
 }
@@ -0,0 +1,328 @@
+//! Persistence of train position snapshots
+//!
+//! The collector stores the train positions it fetches with [`store_positions`],
+//! and the server reads the newest snapshot back with [`latest_positions`]. That
+//! gives clients a slightly stale view of the trains when the live GTFS feeds
+//! are down. [`positions_at`] reads an earlier snapshot, for replaying the
+//! recent past.
+//!
+//! Each position is one `train_positions` row holding it as JSON, tagged with
+//! the snapshot's `recorded_at`. A snapshot's rows are written with multi-row
+//! inserts of up to [`INSERT_BATCH_SIZE`] rows each. Snapshots are kept for
+//! [`REPLAY_WINDOW_SECS`]: storing one deletes the rows older than that.
+
+use crate::{Result, TrainPosition};
+use chrono::{DateTime, Utc};
+use nyc_pulse_common::serde_helpers::rfc3339;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+
+/// Most rows written by one `INSERT`
+///
+/// Each row binds four parameters, keeping a statement far below Postgres'
+/// limit of 65,535 bind parameters.
+pub const INSERT_BATCH_SIZE: usize = 1000;
+
+/// Seconds of snapshots kept before the newest one, for replay
+pub const REPLAY_WINDOW_SECS: i64 = 60 * 60;
+
+/// Train positions as recorded at one moment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionSnapshot {
+    /// When the positions were fetched, in RFC 3339
+    #[serde(with = "rfc3339")]
+    pub recorded_at: DateTime<Utc>,
+    /// Every train in transit at `recorded_at`
+    pub positions: Vec<TrainPosition>,
+}
+
+/// Creates the `train_positions` table if it doesn't exist
+///
+/// A table from before positions had a row each, holding whole snapshots in a
+/// `positions` column, is converted in place. Its rows are dropped, since they
+/// only ever held the newest snapshot.
+///
+/// # Errors
+/// - If any statement fails
+pub async fn ensure_schema(db: &PgPool) -> Result<()> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS train_positions (
+            id SERIAL PRIMARY KEY,
+            recorded_at TIMESTAMPTZ NOT NULL,
+            trip_id TEXT NOT NULL,
+            route_id VARCHAR(10) NOT NULL,
+            position JSONB NOT NULL
+        )
+        "#,
+    )
+    .execute(db)
+    .await?;
+
+    for statement in [
+        "ALTER TABLE train_positions ADD COLUMN IF NOT EXISTS trip_id TEXT",
+        "ALTER TABLE train_positions ADD COLUMN IF NOT EXISTS route_id VARCHAR(10)",
+        "ALTER TABLE train_positions ADD COLUMN IF NOT EXISTS position JSONB",
+        "DELETE FROM train_positions WHERE position IS NULL",
+        "ALTER TABLE train_positions DROP COLUMN IF EXISTS positions",
+        "CREATE INDEX IF NOT EXISTS idx_train_positions_recorded_at ON train_positions(recorded_at DESC)",
+    ] {
+        sqlx::query(statement).execute(db).await?;
+    }
+    Ok(())
+}
+
+/// Builds the inserts for `positions`, one per [`INSERT_BATCH_SIZE`] positions
+fn insert_statements(
+    recorded_at: DateTime<Utc>,
+    positions: &[TrainPosition],
+) -> Vec<QueryBuilder<'static, Postgres>> {
+    positions
+        .chunks(INSERT_BATCH_SIZE)
+        .map(|batch| {
+            let mut query = QueryBuilder::new(
+                "INSERT INTO train_positions (recorded_at, trip_id, route_id, position) ",
+            );
+            query.push_values(batch, |mut row, position| {
+                let json = serde_json::to_string(position).expect("train positions serialize");
+                row.push_bind(recorded_at)
+                    .push_bind(position.trip_id.clone())
+                    .push_bind(position.route_id.clone())
+                    .push_bind(json)
+                    .push_unseparated("::text::jsonb");
+            });
+            query
+        })
+        .collect()
+}
+
+/// Stores `snapshot` as the newest snapshot, deleting any older than
+/// [`REPLAY_WINDOW_SECS`] before it
+///
+/// Positions are inserted [`INSERT_BATCH_SIZE`] at a time rather than one
+/// query each. An empty snapshot stores no rows, so it only prunes older ones.
+///
+/// # Errors
+/// - If an insert or the delete fails, in which case nothing changes
+pub async fn store_positions(db: &PgPool, snapshot: &PositionSnapshot) -> Result<()> {
+    let mut tx = db.begin().await?;
+
+    for mut insert in insert_statements(snapshot.recorded_at, &snapshot.positions) {
+        insert.build().execute(&mut *tx).await?;
+    }
+
+    sqlx::query!(
+        "DELETE FROM train_positions WHERE recorded_at < $1",
+        snapshot.recorded_at - chrono::Duration::seconds(REPLAY_WINDOW_SECS)
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Assembles the rows of one snapshot, as `(recorded_at, position JSON)` pairs
+///
+/// # Errors
+/// - If a stored position can't be decoded
+fn snapshot_from_rows(rows: Vec<(DateTime<Utc>, String)>) -> Result<Option<PositionSnapshot>> {
+    let Some(recorded_at) = rows.first().map(|(recorded_at, _)| *recorded_at) else {
+        return Ok(None);
+    };
+    let positions = rows
+        .iter()
+        .map(|(_, position)| serde_json::from_str(position))
+        .collect::<serde_json::Result<_>>()?;
+    Ok(Some(PositionSnapshot {
+        recorded_at,
+        positions,
+    }))
+}
+
+/// Reads the newest stored snapshot, its positions in the order they were stored
+///
+/// # Returns
+/// - `None` if no snapshot with any positions has been stored
+///
+/// # Errors
+/// - If the query fails or the stored positions can't be decoded
+pub async fn latest_positions(db: &PgPool) -> Result<Option<PositionSnapshot>> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT recorded_at, position::text AS "position!"
+        FROM train_positions
+        WHERE recorded_at = (SELECT MAX(recorded_at) FROM train_positions)
+        ORDER BY id
+        "#
+    )
+    .fetch_all(db)
+    .await?;
+
+    snapshot_from_rows(
+        rows.into_iter()
+            .map(|row| (row.recorded_at, row.position))
+            .collect(),
+    )
+}
+
+/// Reads the newest snapshot recorded at or before `at`, its positions in the
+/// order they were stored
+///
+/// # Returns
+/// - `None` if no stored snapshot is that old, e.g. because `at` is further
+///   back than [`REPLAY_WINDOW_SECS`]
+///
+/// # Errors
+/// - If the query fails or the stored positions can't be decoded
+pub async fn positions_at(db: &PgPool, at: DateTime<Utc>) -> Result<Option<PositionSnapshot>> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT recorded_at, position::text AS "position!"
+        FROM train_positions
+        WHERE recorded_at = (
+            SELECT MAX(recorded_at) FROM train_positions WHERE recorded_at <= $1
+        )
+        ORDER BY id
+        "#,
+        at
+    )
+    .fetch_all(db)
+    .await?;
+
+    snapshot_from_rows(
+        rows.into_iter()
+            .map(|row| (row.recorded_at, row.position))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use nyc_pulse_common::trains::StopLocation;
+
+    fn position(trip_id: &str) -> TrainPosition {
+        let stop = |stop_id: &str, longitude| {
+            StopLocation::new(stop_id.to_string(), None, 40.717304, longitude)
+        };
+        TrainPosition {
+            trip_id: trip_id.to_string(),
+            route_id: "L".to_string(),
+            is_known: true,
+            express: false,
+            from_stop: stop("L08N", -73.956872),
+            to_stop: stop("L06N", -73.981963),
+            progress: 0.5,
+            start_time: 1_704_110_400,
+            end_time: 1_704_110_520,
+            direction: None,
+            destination_stop_id: None,
+            destination_name: None,
+            occupancy: None,
+            remaining_stops: Vec::new(),
+            eta_to_destination_secs: None,
+            speed_kmh: None,
+        }
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_snapshots_outside_replay_window_are_deleted(db: PgPool) {
+        ensure_schema(&db).await.unwrap();
+        assert!(latest_positions(&db).await.unwrap().is_none());
+
+        let at = |hour, minute| Utc.with_ymd_and_hms(2024, 1, 1, hour, minute, 0).unwrap();
+        for (recorded_at, trip_id) in [
+            (at(12, 0), "063350_L..N01R"),
+            (at(12, 30), "064000_L..S01R"),
+            (at(13, 15), "070000_L..N01R"),
+        ] {
+            let snapshot = PositionSnapshot {
+                recorded_at,
+                positions: vec![position(trip_id)],
+            };
+            store_positions(&db, &snapshot).await.unwrap();
+        }
+
+        let latest = latest_positions(&db).await.unwrap().unwrap();
+        assert_eq!(latest.recorded_at, at(13, 15));
+        assert_eq!(latest.positions.len(), 1);
+        assert_eq!(latest.positions[0].trip_id, "070000_L..N01R");
+        // 12:00 is more than an hour before 13:15
+        let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM train_positions")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(rows, 2);
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_positions_at_reads_newest_snapshot_not_after(db: PgPool) {
+        ensure_schema(&db).await.unwrap();
+        let at = |minute| Utc.with_ymd_and_hms(2024, 1, 1, 12, minute, 0).unwrap();
+        for (minute, trip_id) in [(0, "063350_L..N01R"), (10, "064000_L..S01R")] {
+            let snapshot = PositionSnapshot {
+                recorded_at: at(minute),
+                positions: vec![position(trip_id)],
+            };
+            store_positions(&db, &snapshot).await.unwrap();
+        }
+
+        let replayed = positions_at(&db, at(5)).await.unwrap().unwrap();
+        assert_eq!(replayed.recorded_at, at(0));
+        assert_eq!(replayed.positions[0].trip_id, "063350_L..N01R");
+        let exact = positions_at(&db, at(10)).await.unwrap().unwrap();
+        assert_eq!(exact.positions[0].trip_id, "064000_L..S01R");
+        assert!(positions_at(&db, at(0) - chrono::Duration::seconds(1))
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_inserts_are_chunked_above_batch_size() {
+        let at = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        let positions: Vec<TrainPosition> = (0..INSERT_BATCH_SIZE * 2 + 1)
+            .map(|i| position(&format!("trip{}", i)))
+            .collect();
+
+        assert!(insert_statements(at, &[]).is_empty());
+        assert_eq!(insert_statements(at, &positions[..1]).len(), 1);
+        assert_eq!(
+            insert_statements(at, &positions[..INSERT_BATCH_SIZE]).len(),
+            1
+        );
+
+        let inserts = insert_statements(at, &positions);
+        assert_eq!(inserts.len(), 3);
+        // Four parameters per row: a full batch, then the single leftover
+        let full = inserts[0].sql();
+        assert!(full.contains(&format!("${}", INSERT_BATCH_SIZE * 4)));
+        assert!(!full.contains(&format!("${}", INSERT_BATCH_SIZE * 4 + 1)));
+        assert!(inserts[2].sql().ends_with("($1, $2, $3, $4::text::jsonb)"));
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_batched_snapshot_round_trips(db: PgPool) {
+        ensure_schema(&db).await.unwrap();
+
+        let count = INSERT_BATCH_SIZE + 5;
+        let snapshot = PositionSnapshot {
+            recorded_at: Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap(),
+            positions: (0..count)
+                .map(|i| position(&format!("trip{}", i)))
+                .collect(),
+        };
+        store_positions(&db, &snapshot).await.unwrap();
+
+        let latest = latest_positions(&db).await.unwrap().unwrap();
+        assert_eq!(latest.recorded_at, snapshot.recorded_at);
+        let trip_ids: Vec<String> = latest.positions.into_iter().map(|p| p.trip_id).collect();
+        let expected: Vec<String> = (0..count).map(|i| format!("trip{}", i)).collect();
+        assert_eq!(trip_ids, expected);
+    }
+}
@@ -0,0 +1,153 @@
+//! Per-client rate limiting for the API
+//!
+//! Each client IP gets a token bucket holding up to `per_minute` tokens, refilled
+//! continuously at `per_minute / 60` tokens per second. Every request spends one
+//! token; when the bucket is empty the request is rejected with
+//! `429 Too Many Requests` and a `Retry-After` header giving the whole seconds
+//! until a token is available again.
+
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Number of tracked clients above which idle buckets are discarded
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Token bucket for a single client
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    /// Tokens currently available
+    tokens: f64,
+    /// When `tokens` was last brought up to date
+    updated: Instant,
+}
+
+/// Shared per-IP token bucket rate limiter
+#[derive(Clone)]
+pub struct RateLimiter {
+    /// Bucket capacity, equal to the per-minute allowance
+    capacity: f64,
+    /// Tokens added per second
+    refill_per_sec: f64,
+    /// Buckets indexed by client IP
+    buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing `per_minute` requests per client per minute
+    pub fn new(per_minute: u32) -> Self {
+        let capacity = f64::from(per_minute.max(1));
+        Self {
+            capacity,
+            refill_per_sec: capacity / 60.0,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Spends a token for `ip` at time `now`
+    ///
+    /// # Returns
+    /// - `Ok(())` if the request is allowed
+    /// - `Err(wait)` with the time until the next token if the bucket is empty
+    fn check(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock();
+
+        if buckets.len() > MAX_TRACKED_CLIENTS {
+            // A bucket idle for a minute is full again, so forgetting it is lossless
+            buckets
+                .retain(|_, bucket| now.duration_since(bucket.updated) < Duration::from_secs(60));
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.refill_per_sec,
+            ))
+        }
+    }
+}
+
+/// Middleware rejecting requests from clients that exceeded their rate limit
+///
+/// The client is identified by the peer address from [`ConnectInfo`]; requests
+/// without one (e.g. in-process tests) share a single unspecified-address bucket.
+pub async fn rate_limit<B>(
+    State(limiter): State<RateLimiter>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let ip = connect_info
+        .map(|ConnectInfo(addr)| addr.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+
+    match limiter.check(ip, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+            let mut response = StatusCode::TOO_MANY_REQUESTS.into_response();
+            response.headers_mut().insert(
+                header::RETRY_AFTER,
+                HeaderValue::from_str(&retry_after.to_string()).unwrap(),
+            );
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_exhausts_and_refills() {
+        let limiter = RateLimiter::new(60);
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let start = Instant::now();
+
+        for _ in 0..60 {
+            assert!(limiter.check(ip, start).is_ok());
+        }
+        let wait = limiter.check(ip, start).unwrap_err();
+        assert!(wait <= Duration::from_secs(1));
+
+        // One token per second at 60/min
+        assert!(limiter.check(ip, start + Duration::from_secs(1)).is_ok());
+        assert!(limiter.check(ip, start + Duration::from_secs(1)).is_err());
+    }
+
+    #[test]
+    fn test_buckets_are_per_client() {
+        let limiter = RateLimiter::new(1);
+        let now = Instant::now();
+
+        assert!(limiter
+            .check(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), now)
+            .is_ok());
+        assert!(limiter
+            .check(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), now)
+            .is_err());
+        assert!(limiter
+            .check(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), now)
+            .is_ok());
+    }
+}
@@ -0,0 +1,69 @@
+//! Request IDs for correlating client errors with server logs
+//!
+//! Every request gets an ID: the client's `X-Request-Id` header if it sent a
+//! usable one, otherwise a fresh UUID. The request is handled inside a tracing
+//! span carrying the ID, so every log line emitted while serving it (including
+//! `log` records from the library) is tagged with it, and the ID is echoed back
+//! in the response's `X-Request-Id` header.
+
+use axum::{
+    http::{HeaderName, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+
+/// Header carrying the request ID in both directions
+pub static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client-provided ID that is reused rather than replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Returns the client's request ID if it is short, non-empty, visible ASCII
+fn provided_id(value: &HeaderValue) -> Option<&str> {
+    let id = value.to_str().ok()?;
+    let usable = !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.bytes().all(|b| b.is_ascii_graphic());
+    usable.then_some(id)
+}
+
+/// Middleware assigning each request an ID, logging under it and echoing it back
+pub async fn request_id<B>(request: Request<B>, next: Next<B>) -> Response {
+    let id = match request.headers().get(&X_REQUEST_ID).and_then(provided_id) {
+        Some(id) => id.to_string(),
+        None => uuid::Uuid::new_v4().to_string(),
+    };
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = %id,
+        method = %request.method(),
+        path = %request.uri().path(),
+    );
+    let mut response = next.run(request).instrument(span).await;
+
+    response.headers_mut().insert(
+        X_REQUEST_ID.clone(),
+        HeaderValue::from_str(&id).expect("request IDs are visible ASCII"),
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provided_id_validation() {
+        let value = |s: &str| HeaderValue::from_str(s).unwrap();
+
+        assert_eq!(provided_id(&value("abc-123")), Some("abc-123"));
+        assert_eq!(provided_id(&value("")), None);
+        assert_eq!(provided_id(&value("has space")), None);
+        assert_eq!(
+            provided_id(&value(&"x".repeat(MAX_REQUEST_ID_LEN + 1))),
+            None
+        );
+    }
+}
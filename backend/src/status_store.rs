@@ -0,0 +1,1134 @@
+//! Persistence of subway line statuses
+//!
+//! Statuses are kept according to a [`StatusRetention`] mode, chosen with the
+//! `STATUS_RETENTION` environment variable:
+//!
+//! - `history` (default): a status is appended to `subway_status` when its status
+//!   or delays differ from the line's latest row; an unchanged status only moves
+//!   that row's `timestamp` forward, so the table grows by one row per transition.
+//! - `latest`: `subway_status` holds one row per line, replaced on every
+//!   collection via `ON CONFLICT (line) DO UPDATE`. Setting `STATUS_HISTORY=true`
+//!   additionally appends each status to `subway_status_history`.
+//!
+//! Both the collector and the ingestion endpoint store statuses through
+//! [`store_statuses`], so they agree on the table layout. Whichever table keeps
+//! the history is trimmed by [`prune_statuses`] to `STATUS_RETENTION_DAYS`, and
+//! is read back by [`stream_history`].
+//!
+//! Each stored status gets a `changed_at` time (see [`changed_at`]). The
+//! nullable column is added to existing tables by [`ensure_schema`]; rows
+//! written before it existed read back with their own timestamp.
+
+use crate::{Error, Result, SubwayStatus};
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
+use nyc_pulse_common::lines::{line_info, Division};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+/// Default number of days of status history kept by [`prune_statuses`]
+pub const DEFAULT_RETENTION_DAYS: i32 = 7;
+
+/// How `subway_status` retains past statuses
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StatusRetention {
+    /// Append every status to `subway_status`
+    #[default]
+    History,
+    /// Keep only the current status per line in `subway_status`
+    Latest {
+        /// Also append every status to `subway_status_history`
+        history_table: bool,
+    },
+}
+
+impl StatusRetention {
+    /// Parses `STATUS_RETENTION` and `STATUS_HISTORY` values
+    ///
+    /// # Errors
+    /// - `Error::Environment` if the retention isn't `history` or `latest`, or
+    ///   the history flag isn't `true` or `false`
+    pub fn parse(retention: Option<&str>, history: Option<&str>) -> Result<Self> {
+        let history_table = match history.map(|value| value.trim().to_lowercase()).as_deref() {
+            None | Some("") | Some("false") | Some("0") => false,
+            Some("true") | Some("1") => true,
+            Some(other) => {
+                return Err(Error::Environment(format!(
+                    "STATUS_HISTORY must be true or false, got {:?}",
+                    other
+                )))
+            }
+        };
+
+        match retention
+            .map(|value| value.trim().to_lowercase())
+            .as_deref()
+        {
+            None | Some("") | Some("history") => Ok(Self::History),
+            Some("latest") => Ok(Self::Latest { history_table }),
+            Some(other) => Err(Error::Environment(format!(
+                "STATUS_RETENTION must be \"history\" or \"latest\", got {:?}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Parses a `STATUS_RETENTION_DAYS` value, defaulting to [`DEFAULT_RETENTION_DAYS`]
+///
+/// # Errors
+/// - `Error::Environment` if the value isn't a positive integer
+pub fn parse_retention_days(value: Option<&str>) -> Result<i32> {
+    match value {
+        Some(value) => value
+            .trim()
+            .parse::<i32>()
+            .ok()
+            .filter(|&days| days > 0)
+            .ok_or_else(|| {
+                Error::Environment(format!(
+                    "STATUS_RETENTION_DAYS must be a positive integer, got {:?}",
+                    value
+                ))
+            }),
+        None => Ok(DEFAULT_RETENTION_DAYS),
+    }
+}
+
+/// Creates the status tables and indices required by `retention`
+///
+/// Switching to `latest` collapses `subway_status` to the newest row per line
+/// before adding the unique `line` index; switching back to `history` drops it.
+///
+/// # Errors
+/// - If any statement fails
+pub async fn ensure_schema(db: &PgPool, retention: StatusRetention) -> Result<()> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS subway_status (
+            id SERIAL PRIMARY KEY,
+            line VARCHAR(10) NOT NULL,
+            status VARCHAR(100) NOT NULL,
+            timestamp TIMESTAMPTZ NOT NULL,
+            delays BOOLEAN NOT NULL,
+            changed_at TIMESTAMPTZ
+        )
+        "#,
+    )
+    .execute(db)
+    .await?;
+
+    // Tables created before `changed_at` existed
+    sqlx::query("ALTER TABLE subway_status ADD COLUMN IF NOT EXISTS changed_at TIMESTAMPTZ")
+        .execute(db)
+        .await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_subway_status_timestamp ON subway_status(timestamp DESC)",
+    )
+    .execute(db)
+    .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_subway_status_line ON subway_status(line)")
+        .execute(db)
+        .await?;
+
+    match retention {
+        StatusRetention::History => {
+            sqlx::query("DROP INDEX IF EXISTS idx_subway_status_line_unique")
+                .execute(db)
+                .await?;
+        }
+        StatusRetention::Latest { history_table } => {
+            sqlx::query(
+                r#"
+                DELETE FROM subway_status a
+                USING subway_status b
+                WHERE a.line = b.line
+                  AND (a.timestamp, a.id) < (b.timestamp, b.id)
+                "#,
+            )
+            .execute(db)
+            .await?;
+
+            sqlx::query(
+                "CREATE UNIQUE INDEX IF NOT EXISTS idx_subway_status_line_unique ON subway_status(line)",
+            )
+            .execute(db)
+            .await?;
+
+            if history_table {
+                sqlx::query(
+                    r#"
+                    CREATE TABLE IF NOT EXISTS subway_status_history (
+                        id SERIAL PRIMARY KEY,
+                        line VARCHAR(10) NOT NULL,
+                        status VARCHAR(100) NOT NULL,
+                        timestamp TIMESTAMPTZ NOT NULL,
+                        delays BOOLEAN NOT NULL,
+                        changed_at TIMESTAMPTZ
+                    )
+                    "#,
+                )
+                .execute(db)
+                .await?;
+
+                sqlx::query(
+                    "ALTER TABLE subway_status_history ADD COLUMN IF NOT EXISTS changed_at TIMESTAMPTZ",
+                )
+                .execute(db)
+                .await?;
+
+                sqlx::query(
+                    "CREATE INDEX IF NOT EXISTS idx_subway_status_history_line_timestamp ON subway_status_history(line, timestamp DESC)",
+                )
+                .execute(db)
+                .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether `new` differs from `old` in what riders see: the status or the delay flag
+pub fn status_changed(old: &SubwayStatus, new: &SubwayStatus) -> bool {
+    old.status != new.status || old.delays != new.delays
+}
+
+/// When the line of `status` last transitioned, given its `previous` stored status
+///
+/// The previous `changed_at` carries over while nothing changed; a line seen
+/// for the first time, or whose status or delays differ, changed at `status.timestamp`.
+pub fn changed_at(previous: Option<&SubwayStatus>, status: &SubwayStatus) -> DateTime<Utc> {
+    match previous {
+        Some(previous) if !status_changed(previous, status) => previous.changed_at,
+        _ => status.timestamp,
+    }
+}
+
+/// Orders `statuses` for triage: delayed lines first, then busier lines first
+///
+/// Lines are ranked by [`LineInfo::ridership_rank`](nyc_pulse_common::lines::LineInfo);
+/// lines without an entry in [`LINES`](nyc_pulse_common::lines::LINES) come
+/// last, and ties fall back to the line identifier.
+pub fn sort_by_priority(statuses: &mut [SubwayStatus]) {
+    statuses.sort_by_cached_key(|status| {
+        (
+            !status.delays,
+            line_info(&status.line).map_or(u8::MAX, |line| line.ridership_rank),
+            status.line.clone(),
+        )
+    });
+}
+
+/// Latest statuses split into the MTA's IRT, BMT and IND status grid
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DivisionedStatus {
+    /// Numbered lines and the 42 St Shuttle
+    pub irt: Vec<SubwayStatus>,
+    /// Broadway, Nassau St, Canarsie and Franklin Av lines
+    pub bmt: Vec<SubwayStatus>,
+    /// 8 Av, 6 Av and Crosstown lines, plus the Rockaway Park Shuttle
+    pub ind: Vec<SubwayStatus>,
+}
+
+impl DivisionedStatus {
+    /// Groups `statuses` by [`LineInfo::division`](nyc_pulse_common::lines::LineInfo),
+    /// busiest line first within each group
+    ///
+    /// Lines without a division, such as the Staten Island Railway or lines
+    /// missing from [`LINES`](nyc_pulse_common::lines::LINES), are left out.
+    pub fn from_statuses(statuses: Vec<SubwayStatus>) -> Self {
+        let mut grouped = Self::default();
+        for status in statuses {
+            let group = match line_info(&status.line).and_then(|line| line.division) {
+                Some(Division::Irt) => &mut grouped.irt,
+                Some(Division::Bmt) => &mut grouped.bmt,
+                Some(Division::Ind) => &mut grouped.ind,
+                None => continue,
+            };
+            group.push(status);
+        }
+        for group in [&mut grouped.irt, &mut grouped.bmt, &mut grouped.ind] {
+            group.sort_by_key(|status| line_info(&status.line).map(|line| line.ridership_rank));
+        }
+        grouped
+    }
+}
+
+/// Stores `statuses` in a single transaction according to `retention`
+///
+/// Each status's `changed_at` is recomputed with [`changed_at`] against the
+/// latest stored row for its line, ignoring the value passed in.
+///
+/// In `history` mode a newer status that hasn't changed (see [`status_changed`])
+/// isn't inserted; the latest row's `timestamp` is advanced to it instead, so
+/// [`latest_timestamp`] still tracks the collector.
+///
+/// # Returns
+/// - Number of `subway_status` rows inserted or, in `latest` mode, upserted;
+///   an unchanged status folded into the previous row isn't counted
+///
+/// # Errors
+/// - If any insert fails, in which case nothing is stored
+pub async fn store_statuses(
+    db: &PgPool,
+    statuses: &[SubwayStatus],
+    retention: StatusRetention,
+) -> Result<usize> {
+    let mut tx = db.begin().await?;
+    let mut inserted = 0;
+
+    let mut latest: HashMap<String, SubwayStatus> = sqlx::query_as!(
+        SubwayStatus,
+        r#"
+        SELECT DISTINCT ON (line)
+            line, status, timestamp, delays,
+            COALESCE(changed_at, timestamp) AS "changed_at!"
+        FROM subway_status
+        ORDER BY line, timestamp DESC
+        "#
+    )
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|status| (status.line.clone(), status))
+    .collect();
+
+    for status in statuses {
+        let status = SubwayStatus {
+            changed_at: changed_at(latest.get(&status.line), status),
+            ..status.clone()
+        };
+
+        match retention {
+            StatusRetention::History => match latest.get(&status.line) {
+                Some(previous)
+                    if previous.timestamp <= status.timestamp
+                        && !status_changed(previous, &status) =>
+                {
+                    sqlx::query!(
+                        "UPDATE subway_status SET timestamp = $1 WHERE line = $2 AND timestamp = $3",
+                        status.timestamp,
+                        status.line,
+                        previous.timestamp
+                    )
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                _ => {
+                    sqlx::query!(
+                        r#"
+                        INSERT INTO subway_status (line, status, timestamp, delays, changed_at)
+                        VALUES ($1, $2, $3, $4, $5)
+                        "#,
+                        status.line,
+                        status.status,
+                        status.timestamp,
+                        status.delays,
+                        status.changed_at
+                    )
+                    .execute(&mut *tx)
+                    .await?;
+                    inserted += 1;
+                }
+            },
+            StatusRetention::Latest { history_table } => {
+                // Not checked at compile time: the unique index only exists in latest mode
+                sqlx::query(
+                    r#"
+                    INSERT INTO subway_status (line, status, timestamp, delays, changed_at)
+                    VALUES ($1, $2, $3, $4, $5)
+                    ON CONFLICT (line) DO UPDATE
+                    SET status = EXCLUDED.status,
+                        timestamp = EXCLUDED.timestamp,
+                        delays = EXCLUDED.delays,
+                        changed_at = EXCLUDED.changed_at
+                    "#,
+                )
+                .bind(&status.line)
+                .bind(&status.status)
+                .bind(status.timestamp)
+                .bind(status.delays)
+                .bind(status.changed_at)
+                .execute(&mut *tx)
+                .await?;
+                inserted += 1;
+
+                if history_table {
+                    sqlx::query!(
+                        r#"
+                        INSERT INTO subway_status_history (line, status, timestamp, delays, changed_at)
+                        VALUES ($1, $2, $3, $4, $5)
+                        "#,
+                        status.line,
+                        status.status,
+                        status.timestamp,
+                        status.delays,
+                        status.changed_at
+                    )
+                    .execute(&mut *tx)
+                    .await?;
+                }
+            }
+        }
+
+        latest.insert(status.line.clone(), status);
+    }
+
+    tx.commit().await?;
+    Ok(inserted)
+}
+
+/// Reads the most recent status of every line, ordered by line
+///
+/// Works in either retention mode, since `subway_status` always holds the
+/// newest row per line. With `delays_only`, lines whose latest status has no
+/// delays are left out.
+///
+/// # Errors
+/// - If the query fails
+pub async fn latest_statuses(db: &PgPool, delays_only: bool) -> Result<Vec<SubwayStatus>> {
+    Ok(sqlx::query_as!(
+        SubwayStatus,
+        r#"
+        WITH latest_statuses AS (
+            SELECT DISTINCT ON (line) *
+            FROM subway_status
+            ORDER BY line, timestamp DESC
+        )
+        SELECT line, status, timestamp, delays,
+            COALESCE(changed_at, timestamp) AS "changed_at!"
+        FROM latest_statuses
+        WHERE NOT $1 OR delays = true
+        ORDER BY line ASC
+        "#,
+        delays_only
+    )
+    .fetch_all(db)
+    .await?)
+}
+
+/// Time of the newest status stored in `subway_status`, for monitoring the collector
+///
+/// # Returns
+/// - `None` if no status has been stored
+///
+/// # Errors
+/// - If the query fails
+pub async fn latest_timestamp(db: &PgPool) -> Result<Option<DateTime<Utc>>> {
+    Ok(
+        sqlx::query_scalar!("SELECT MAX(timestamp) FROM subway_status")
+            .fetch_one(db)
+            .await?,
+    )
+}
+
+/// Reads the latest status of each line whose `changed_at` is after `since`
+///
+/// Lets polling clients fetch only the lines that transitioned since their
+/// last poll instead of every line.
+///
+/// # Returns
+/// - Matching statuses ordered by line, empty if nothing changed
+///
+/// # Errors
+/// - If the query fails
+pub async fn statuses_changed_since(
+    db: &PgPool,
+    since: DateTime<Utc>,
+) -> Result<Vec<SubwayStatus>> {
+    Ok(sqlx::query_as!(
+        SubwayStatus,
+        r#"
+        WITH latest_statuses AS (
+            SELECT DISTINCT ON (line) *
+            FROM subway_status
+            ORDER BY line, timestamp DESC
+        )
+        SELECT line, status, timestamp, delays,
+            COALESCE(changed_at, timestamp) AS "changed_at!"
+        FROM latest_statuses
+        WHERE COALESCE(changed_at, timestamp) > $1
+        ORDER BY line ASC
+        "#,
+        since
+    )
+    .fetch_all(db)
+    .await?)
+}
+
+/// Days of history scored by [`line_reliability`] when no `days` is given
+pub const DEFAULT_RELIABILITY_DAYS: i32 = 7;
+
+/// Upper bound on the `days` accepted by [`line_reliability`]
+pub const MAX_RELIABILITY_DAYS: i32 = 90;
+
+/// Window for scoring line reliability, deserializable from a query string
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct ReliabilityWindow {
+    /// Days of history to score, defaulting to [`DEFAULT_RELIABILITY_DAYS`] and
+    /// clamped to `1..=`[`MAX_RELIABILITY_DAYS`]
+    pub days: Option<i32>,
+}
+
+impl ReliabilityWindow {
+    /// The number of days to score, after defaulting and clamping
+    pub fn effective_days(&self) -> i32 {
+        self.days
+            .unwrap_or(DEFAULT_RELIABILITY_DAYS)
+            .clamp(1, MAX_RELIABILITY_DAYS)
+    }
+}
+
+/// Share of a line's recent history without delays
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LineReliability {
+    /// Subway line identifier
+    pub line: String,
+    /// Percentage (0 to 100) of the window with `delays = false`
+    pub pct_good_service: f64,
+}
+
+/// Scores each line by how often it ran without delays over `window`
+///
+/// Reads whichever table keeps the history for `retention`, like
+/// [`stream_history`]. The `latest` mode history table stores every poll, so
+/// there each sample counts once.
+///
+/// In `history` mode unchanged statuses aren't stored again (see
+/// [`store_statuses`]), so a row is a run of one status from its `changed_at`
+/// to its `timestamp`. Runs are weighted by how long they lasted within the
+/// window; a line whose runs all lasted no time falls back to counting them.
+/// In `latest` mode without a history table each line has a single run.
+///
+/// # Returns
+/// - One entry per line with samples in the window, ordered by line
+///
+/// # Errors
+/// - If the query fails
+pub async fn line_reliability(
+    db: &PgPool,
+    retention: StatusRetention,
+    window: ReliabilityWindow,
+) -> Result<Vec<LineReliability>> {
+    let days = window.effective_days();
+    let reliability = match retention {
+        StatusRetention::Latest {
+            history_table: true,
+        } => {
+            sqlx::query_as!(
+                LineReliability,
+                r#"
+                SELECT line,
+                    (100.0 * AVG(CASE WHEN delays THEN 0 ELSE 1 END))::float8
+                        AS "pct_good_service!"
+                FROM subway_status_history
+                WHERE timestamp >= now() - make_interval(days => $1)
+                GROUP BY line
+                ORDER BY line ASC
+                "#,
+                days
+            )
+            .fetch_all(db)
+            .await?
+        }
+        _ => {
+            sqlx::query_as!(
+                LineReliability,
+                r#"
+                WITH runs AS (
+                    SELECT line, delays,
+                        GREATEST(EXTRACT(EPOCH FROM timestamp - GREATEST(
+                            COALESCE(changed_at, timestamp),
+                            now() - make_interval(days => $1)
+                        )), 0) AS seconds
+                    FROM subway_status
+                    WHERE timestamp >= now() - make_interval(days => $1)
+                )
+                SELECT line,
+                    COALESCE(
+                        100.0 * SUM(CASE WHEN delays THEN 0 ELSE seconds END)
+                            / NULLIF(SUM(seconds), 0),
+                        100.0 * AVG(CASE WHEN delays THEN 0 ELSE 1 END)
+                    )::float8 AS "pct_good_service!"
+                FROM runs
+                GROUP BY line
+                ORDER BY line ASC
+                "#,
+                days
+            )
+            .fetch_all(db)
+            .await?
+        }
+    };
+    Ok(reliability)
+}
+
+/// Deletes history rows older than `days` days
+///
+/// In `history` mode this trims `subway_status`; in `latest` mode it trims
+/// `subway_status_history` if enabled, and otherwise does nothing since only
+/// current rows are kept.
+///
+/// # Returns
+/// - Number of rows deleted
+///
+/// # Errors
+/// - If the delete fails
+pub async fn prune_statuses(db: &PgPool, retention: StatusRetention, days: i32) -> Result<u64> {
+    let result = match retention {
+        StatusRetention::History => {
+            sqlx::query!(
+                "DELETE FROM subway_status WHERE timestamp < now() - make_interval(days => $1)",
+                days
+            )
+            .execute(db)
+            .await?
+        }
+        StatusRetention::Latest {
+            history_table: true,
+        } => {
+            sqlx::query!(
+            "DELETE FROM subway_status_history WHERE timestamp < now() - make_interval(days => $1)",
+            days
+        )
+            .execute(db)
+            .await?
+        }
+        StatusRetention::Latest {
+            history_table: false,
+        } => return Ok(0),
+    };
+    Ok(result.rows_affected())
+}
+
+/// Rows returned by [`stream_history`] when no `limit` is given
+pub const DEFAULT_HISTORY_LIMIT: i64 = 1_000;
+
+/// Upper bound on the `limit` accepted by [`stream_history`]
+pub const MAX_HISTORY_LIMIT: i64 = 10_000;
+
+/// Filters for reading status history
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    /// Only statuses for this line
+    pub line: Option<String>,
+    /// Only statuses recorded at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Maximum number of rows, defaulting to [`DEFAULT_HISTORY_LIMIT`] and
+    /// capped at [`MAX_HISTORY_LIMIT`]
+    pub limit: Option<i64>,
+}
+
+impl HistoryFilter {
+    /// The row limit to apply, after defaulting and clamping
+    pub fn effective_limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_HISTORY_LIMIT)
+            .clamp(0, MAX_HISTORY_LIMIT)
+    }
+}
+
+/// Streams stored statuses matching `filter`, newest first
+///
+/// Reads whichever table keeps the history for `retention`; in `latest` mode
+/// without a history table only the current row per line is available. Rows
+/// are fetched by a background task and sent as they arrive, so large exports
+/// aren't buffered in memory. The task stops once the stream is dropped.
+pub fn stream_history(
+    db: PgPool,
+    retention: StatusRetention,
+    filter: HistoryFilter,
+) -> impl Stream<Item = Result<SubwayStatus>> + Send + 'static {
+    let (tx, rx) = tokio::sync::mpsc::channel(64);
+
+    tokio::spawn(async move {
+        let limit = filter.effective_limit();
+        let mut rows = match retention {
+            StatusRetention::Latest {
+                history_table: true,
+            } => sqlx::query_as!(
+                SubwayStatus,
+                r#"
+                SELECT line, status, timestamp, delays,
+                    COALESCE(changed_at, timestamp) AS "changed_at!"
+                FROM subway_status_history
+                WHERE ($1::text IS NULL OR line = $1)
+                  AND ($2::timestamptz IS NULL OR timestamp >= $2)
+                ORDER BY timestamp DESC
+                LIMIT $3
+                "#,
+                filter.line,
+                filter.since,
+                limit
+            )
+            .fetch(&db),
+            _ => sqlx::query_as!(
+                SubwayStatus,
+                r#"
+                SELECT line, status, timestamp, delays,
+                    COALESCE(changed_at, timestamp) AS "changed_at!"
+                FROM subway_status
+                WHERE ($1::text IS NULL OR line = $1)
+                  AND ($2::timestamptz IS NULL OR timestamp >= $2)
+                ORDER BY timestamp DESC
+                LIMIT $3
+                "#,
+                filter.line,
+                filter.since,
+                limit
+            )
+            .fetch(&db),
+        };
+
+        while let Some(row) = rows.next().await {
+            let failed = row.is_err();
+            if tx.send(row.map_err(Error::from)).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    futures::stream::unfold(
+        rx,
+        |mut rx| async move { rx.recv().await.map(|row| (row, rx)) },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_history_limit_defaults_and_clamps() {
+        let filter = |limit| HistoryFilter {
+            limit,
+            ..Default::default()
+        };
+        assert_eq!(filter(None).effective_limit(), DEFAULT_HISTORY_LIMIT);
+        assert_eq!(filter(Some(5)).effective_limit(), 5);
+        assert_eq!(filter(Some(-1)).effective_limit(), 0);
+        assert_eq!(filter(Some(1_000_000)).effective_limit(), MAX_HISTORY_LIMIT);
+    }
+
+    #[test]
+    fn test_parse_retention() {
+        assert_eq!(
+            StatusRetention::parse(None, None).unwrap(),
+            StatusRetention::History
+        );
+        assert_eq!(
+            StatusRetention::parse(Some("Latest"), None).unwrap(),
+            StatusRetention::Latest {
+                history_table: false
+            }
+        );
+        assert_eq!(
+            StatusRetention::parse(Some("latest"), Some("true")).unwrap(),
+            StatusRetention::Latest {
+                history_table: true
+            }
+        );
+        assert!(StatusRetention::parse(Some("forever"), None).is_err());
+        assert!(StatusRetention::parse(Some("latest"), Some("yes please")).is_err());
+    }
+
+    #[test]
+    fn test_parse_retention_days() {
+        assert_eq!(parse_retention_days(None).unwrap(), DEFAULT_RETENTION_DAYS);
+        assert_eq!(parse_retention_days(Some(" 30 ")).unwrap(), 30);
+        assert!(parse_retention_days(Some("0")).is_err());
+        assert!(parse_retention_days(Some("a week")).is_err());
+    }
+
+    fn statuses(minute: u32) -> Vec<SubwayStatus> {
+        ["A", "C", "E"]
+            .into_iter()
+            .map(|line| {
+                let timestamp = Utc.with_ymd_and_hms(2024, 1, 1, 12, minute, 0).unwrap();
+                SubwayStatus {
+                    line: line.to_string(),
+                    status: "Good Service".to_string(),
+                    timestamp,
+                    delays: false,
+                    changed_at: timestamp,
+                }
+            })
+            .collect()
+    }
+
+    fn status_at(minute: u32, status: &str, delays: bool) -> SubwayStatus {
+        let timestamp = Utc.with_ymd_and_hms(2024, 1, 1, 12, minute, 0).unwrap();
+        SubwayStatus {
+            line: "L".to_string(),
+            status: status.to_string(),
+            timestamp,
+            delays,
+            changed_at: timestamp,
+        }
+    }
+
+    #[test]
+    fn test_changed_at_only_moves_on_transitions() {
+        let first = status_at(0, "Good Service", false);
+        assert_eq!(changed_at(None, &first), first.timestamp);
+
+        // Unchanged statuses keep the time of the last transition
+        let same = status_at(5, "Good Service", false);
+        assert_eq!(changed_at(Some(&first), &same), first.timestamp);
+
+        let delayed = status_at(10, "Delays", true);
+        assert_eq!(changed_at(Some(&first), &delayed), delayed.timestamp);
+
+        // A new delay flag alone is a transition too
+        let flagged = status_at(15, "Good Service", true);
+        assert_eq!(changed_at(Some(&first), &flagged), flagged.timestamp);
+    }
+
+    #[test]
+    fn test_priority_puts_delayed_lines_before_busy_ones() {
+        let line = |line: &str, delays: bool| SubwayStatus {
+            line: line.to_string(),
+            ..status_at(0, if delays { "Delays" } else { "Good Service" }, delays)
+        };
+        // The H is the least ridden line and the F the most
+        let mut statuses = vec![
+            line("F", false),
+            line("X", false),
+            line("6", false),
+            line("H", true),
+            line("L", true),
+        ];
+
+        sort_by_priority(&mut statuses);
+
+        let order: Vec<&str> = statuses.iter().map(|s| s.line.as_str()).collect();
+        assert_eq!(order, ["L", "H", "F", "6", "X"]);
+    }
+
+    #[test]
+    fn test_grouped_by_division() {
+        let line = |line: &str| SubwayStatus {
+            line: line.to_string(),
+            ..status_at(0, "Good Service", false)
+        };
+        let statuses = ["1", "2", "3", "A", "L", "SI", "X"].map(line).to_vec();
+
+        let grouped = DivisionedStatus::from_statuses(statuses);
+
+        let lines = |group: &[SubwayStatus]| -> Vec<String> {
+            group.iter().map(|status| status.line.clone()).collect()
+        };
+        // Busier lines first: the 1 outranks the 2, which outranks the 3
+        assert_eq!(lines(&grouped.irt), ["1", "2", "3"]);
+        assert_eq!(lines(&grouped.bmt), ["L"]);
+        assert_eq!(lines(&grouped.ind), ["A"]);
+    }
+
+    async fn count(db: &PgPool, table: &str) -> i64 {
+        sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(db)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_history_mode_appends(db: PgPool) {
+        let retention = StatusRetention::History;
+        ensure_schema(&db, retention).await.unwrap();
+
+        store_statuses(&db, &statuses(0), retention).await.unwrap();
+        let delayed: Vec<SubwayStatus> = statuses(1)
+            .into_iter()
+            .map(|status| SubwayStatus {
+                status: "Delays".to_string(),
+                delays: true,
+                ..status
+            })
+            .collect();
+        store_statuses(&db, &delayed, retention).await.unwrap();
+
+        assert_eq!(count(&db, "subway_status").await, 6);
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_history_mode_skips_unchanged_statuses(db: PgPool) {
+        let retention = StatusRetention::History;
+        ensure_schema(&db, retention).await.unwrap();
+
+        let inserted = store_statuses(&db, &statuses(0), retention).await.unwrap();
+        assert_eq!(inserted, 3);
+        for minute in [1, 2] {
+            let inserted = store_statuses(&db, &statuses(minute), retention)
+                .await
+                .unwrap();
+            assert_eq!(inserted, 0);
+        }
+
+        assert_eq!(count(&db, "subway_status").await, 3);
+        let latest = latest_statuses(&db, false).await.unwrap();
+        let at = |minute| Utc.with_ymd_and_hms(2024, 1, 1, 12, minute, 0).unwrap();
+        assert!(latest.iter().all(|status| status.timestamp == at(2)));
+        assert!(latest.iter().all(|status| status.changed_at == at(0)));
+
+        // A transition is still recorded
+        store_statuses(&db, &[status_at(3, "Delays", true)], retention)
+            .await
+            .unwrap();
+        assert_eq!(count(&db, "subway_status").await, 4);
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_latest_mode_keeps_one_row_per_line(db: PgPool) {
+        // Rows left over from history mode are collapsed when switching
+        ensure_schema(&db, StatusRetention::History).await.unwrap();
+        store_statuses(&db, &statuses(0), StatusRetention::History)
+            .await
+            .unwrap();
+        store_statuses(&db, &statuses(1), StatusRetention::History)
+            .await
+            .unwrap();
+
+        let retention = StatusRetention::Latest {
+            history_table: true,
+        };
+        ensure_schema(&db, retention).await.unwrap();
+        assert_eq!(count(&db, "subway_status").await, 3);
+
+        store_statuses(&db, &statuses(2), retention).await.unwrap();
+        store_statuses(&db, &statuses(3), retention).await.unwrap();
+
+        assert_eq!(count(&db, "subway_status").await, 3);
+        assert_eq!(count(&db, "subway_status_history").await, 6);
+        let latest: chrono::DateTime<Utc> =
+            sqlx::query_scalar("SELECT timestamp FROM subway_status WHERE line = 'A'")
+                .fetch_one(&db)
+                .await
+                .unwrap();
+        assert_eq!(latest, Utc.with_ymd_and_hms(2024, 1, 1, 12, 3, 0).unwrap());
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_stored_changed_at_follows_transitions(db: PgPool) {
+        for retention in [
+            StatusRetention::History,
+            StatusRetention::Latest {
+                history_table: false,
+            },
+        ] {
+            ensure_schema(&db, retention).await.unwrap();
+            sqlx::query("DELETE FROM subway_status")
+                .execute(&db)
+                .await
+                .unwrap();
+
+            for status in [
+                status_at(0, "Good Service", false),
+                status_at(1, "Delays", true),
+                status_at(2, "Delays", true),
+            ] {
+                store_statuses(&db, &[status], retention).await.unwrap();
+            }
+
+            let latest = latest_statuses(&db, false).await.unwrap();
+            assert_eq!(
+                latest[0].timestamp,
+                Utc.with_ymd_and_hms(2024, 1, 1, 12, 2, 0).unwrap()
+            );
+            assert_eq!(
+                latest[0].changed_at,
+                Utc.with_ymd_and_hms(2024, 1, 1, 12, 1, 0).unwrap(),
+                "{:?}",
+                retention
+            );
+        }
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_latest_timestamp(db: PgPool) {
+        let retention = StatusRetention::History;
+        ensure_schema(&db, retention).await.unwrap();
+        assert_eq!(latest_timestamp(&db).await.unwrap(), None);
+
+        store_statuses(&db, &statuses(0), retention).await.unwrap();
+        store_statuses(&db, &statuses(2), retention).await.unwrap();
+
+        assert_eq!(
+            latest_timestamp(&db).await.unwrap(),
+            Some(Utc.with_ymd_and_hms(2024, 1, 1, 12, 2, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_reliability_window_is_clamped() {
+        let window = |days| ReliabilityWindow { days };
+        assert_eq!(window(None).effective_days(), DEFAULT_RELIABILITY_DAYS);
+        assert_eq!(window(Some(30)).effective_days(), 30);
+        assert_eq!(window(Some(0)).effective_days(), 1);
+        assert_eq!(window(Some(10_000)).effective_days(), MAX_RELIABILITY_DAYS);
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_line_reliability(db: PgPool) {
+        let retention = StatusRetention::History;
+        ensure_schema(&db, retention).await.unwrap();
+        let now = Utc::now();
+        let hours_ago = |line: &str, hours: i64, delays: bool| {
+            let timestamp = now - chrono::Duration::hours(hours);
+            SubwayStatus {
+                line: line.to_string(),
+                status: if delays { "Delays" } else { "Good Service" }.to_string(),
+                timestamp,
+                delays,
+                changed_at: timestamp,
+            }
+        };
+        // Polls in order, each pair stored as one run. The L runs well for 6
+        // hours and is delayed for 2; the A's delayed run is outside the
+        // window; the B's good run started 2 days before the window
+        let polls = [
+            hours_ago("A", 24 * 10 + 2, true),
+            hours_ago("A", 24 * 10, true),
+            hours_ago("B", 24 * 9, false),
+            hours_ago("B", 24 * 5, false),
+            hours_ago("L", 10, false),
+            hours_ago("L", 4, false),
+            hours_ago("L", 3, true),
+            hours_ago("L", 1, true),
+            hours_ago("A", 2, false),
+            hours_ago("A", 1, false),
+            hours_ago("B", 24 * 2, true),
+            hours_ago("B", 0, true),
+        ];
+        for poll in polls {
+            store_statuses(&db, &[poll], retention).await.unwrap();
+        }
+
+        let reliability = line_reliability(&db, retention, ReliabilityWindow::default())
+            .await
+            .unwrap();
+        let pct = |reliability: &[LineReliability]| {
+            reliability
+                .iter()
+                .map(|line| (line.line.clone(), line.pct_good_service))
+                .collect::<Vec<_>>()
+        };
+        let close = |actual: &[(String, f64)], expected: &[(&str, f64)]| {
+            assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+            for ((line, pct), (expected_line, expected_pct)) in actual.iter().zip(expected) {
+                assert_eq!(line, expected_line);
+                assert!((pct - expected_pct).abs() < 0.01, "{}: {}", line, pct);
+            }
+        };
+        // The B's good run counts only from the window's start
+        close(
+            &pct(&reliability),
+            &[("A", 100.0), ("B", 50.0), ("L", 75.0)],
+        );
+
+        let window = ReliabilityWindow { days: Some(30) };
+        let reliability = line_reliability(&db, retention, window).await.unwrap();
+        close(
+            &pct(&reliability),
+            &[("A", 100.0 / 3.0), ("B", 200.0 / 3.0), ("L", 75.0)],
+        );
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_line_reliability_counts_instant_runs(db: PgPool) {
+        let retention = StatusRetention::History;
+        ensure_schema(&db, retention).await.unwrap();
+        for (minute, delays) in [(0, false), (10, true), (20, false)] {
+            let timestamp = Utc::now() - chrono::Duration::minutes(60 - minute);
+            let status = SubwayStatus {
+                line: "G".to_string(),
+                status: if delays { "Delays" } else { "Good Service" }.to_string(),
+                timestamp,
+                delays,
+                changed_at: timestamp,
+            };
+            store_statuses(&db, &[status], retention).await.unwrap();
+        }
+
+        // Each change was polled once, so no run has any length
+        let reliability = line_reliability(&db, retention, ReliabilityWindow::default())
+            .await
+            .unwrap();
+        assert_eq!(reliability.len(), 1);
+        assert!((reliability[0].pct_good_service - 200.0 / 3.0).abs() < 0.01);
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_statuses_changed_since(db: PgPool) {
+        let retention = StatusRetention::History;
+        ensure_schema(&db, retention).await.unwrap();
+        let on_line = |line: &str, status: SubwayStatus| SubwayStatus {
+            line: line.to_string(),
+            ..status
+        };
+        // The A changes at 12:00 and the L at 12:10; both are polled again at 12:20
+        for statuses in [
+            vec![on_line("A", status_at(0, "Delays", true))],
+            vec![on_line("L", status_at(10, "Delays", true))],
+            vec![
+                on_line("A", status_at(20, "Delays", true)),
+                on_line("L", status_at(20, "Delays", true)),
+            ],
+        ] {
+            store_statuses(&db, &statuses, retention).await.unwrap();
+        }
+
+        let at = |minute| Utc.with_ymd_and_hms(2024, 1, 1, 12, minute, 0).unwrap();
+        let changed = statuses_changed_since(&db, at(5)).await.unwrap();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].line, "L");
+        assert_eq!(changed[0].changed_at, at(10));
+
+        assert_eq!(statuses_changed_since(&db, at(0)).await.unwrap().len(), 1);
+        assert!(statuses_changed_since(&db, at(10))
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "db-tests"), ignore = "needs Postgres; enable db-tests")]
+    async fn test_prune_deletes_rows_past_retention(db: PgPool) {
+        let retention = StatusRetention::History;
+        ensure_schema(&db, retention).await.unwrap();
+        let now = Utc::now();
+        // Alternating delays, so every row is a transition and gets stored
+        let seeded: Vec<SubwayStatus> = [10, 8, 6, 1]
+            .into_iter()
+            .enumerate()
+            .map(|(i, days_ago)| {
+                let timestamp = now - chrono::Duration::days(days_ago);
+                let delays = i % 2 == 1;
+                SubwayStatus {
+                    line: "L".to_string(),
+                    status: if delays { "Delays" } else { "Good Service" }.to_string(),
+                    timestamp,
+                    delays,
+                    changed_at: timestamp,
+                }
+            })
+            .collect();
+        store_statuses(&db, &seeded, retention).await.unwrap();
+
+        assert_eq!(prune_statuses(&db, retention, 7).await.unwrap(), 2);
+        assert_eq!(count(&db, "subway_status").await, 2);
+        assert_eq!(prune_statuses(&db, retention, 7).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,239 @@
+//! Live subway status updates over WebSocket
+//!
+//! A background task polls the latest status of every line and hands it to a
+//! [`StatusHub`], which remembers the last-known status per line and broadcasts
+//! only the lines whose status or delay flag changed. A freshly recorded row
+//! with the same status as before is not a change, so clients only hear about
+//! real transitions.
+//!
+//! Each `/ws/status` client first receives a [`StatusMessage::Snapshot`] of
+//! every known line, then one [`StatusMessage::Changed`] per status change.
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        State, WebSocketUpgrade,
+    },
+    response::Response,
+};
+use nyc_pulse_backend::{status_store, SubwayStatus};
+use parking_lot::Mutex;
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::warn;
+
+/// How often the latest statuses are read from the database
+pub const STATUS_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Number of unsent changes buffered per client before it is resynced
+const CHANNEL_CAPACITY: usize = 256;
+
+/// Message sent to `/ws/status` clients
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StatusMessage {
+    /// Every known line's status, sent on connect and after falling behind
+    Snapshot { statuses: Vec<SubwayStatus> },
+    /// A single line whose status changed
+    Changed { status: SubwayStatus },
+}
+
+/// Last-known status per line, shared with a broadcast channel of changes
+#[derive(Clone)]
+pub struct StatusHub {
+    /// Most recent status of each line, keyed by line
+    last_known: Arc<Mutex<HashMap<String, SubwayStatus>>>,
+    /// Sender for changed lines
+    changes: broadcast::Sender<SubwayStatus>,
+}
+
+impl StatusHub {
+    /// Creates a hub that knows no lines yet
+    pub fn new() -> Self {
+        let (changes, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            last_known: Arc::new(Mutex::new(HashMap::new())),
+            changes,
+        }
+    }
+
+    /// Records `fresh` statuses and broadcasts the lines that changed
+    ///
+    /// Unknown lines count as changed. Lines missing from `fresh` keep their
+    /// last-known status.
+    ///
+    /// # Returns
+    /// - Number of lines that changed
+    pub fn publish(&self, fresh: Vec<SubwayStatus>) -> usize {
+        let mut last_known = self.last_known.lock();
+        let mut changed = 0;
+        for status in fresh {
+            let is_change = last_known
+                .get(&status.line)
+                .is_none_or(|old| status_store::status_changed(old, &status));
+            if is_change {
+                changed += 1;
+                // No receivers just means nobody is connected
+                let _ = self.changes.send(status.clone());
+            }
+            last_known.insert(status.line.clone(), status);
+        }
+        changed
+    }
+
+    /// Every last-known status, ordered by line
+    fn snapshot(&self) -> Vec<SubwayStatus> {
+        sorted_by_line(&self.last_known.lock())
+    }
+
+    /// Returns the current snapshot and a receiver for every change after it
+    ///
+    /// Both are taken under the same lock, so no change is missed or repeated.
+    pub fn subscribe(&self) -> (Vec<SubwayStatus>, broadcast::Receiver<SubwayStatus>) {
+        let last_known = self.last_known.lock();
+        (sorted_by_line(&last_known), self.changes.subscribe())
+    }
+}
+
+/// The statuses in `last_known`, ordered by line
+fn sorted_by_line(last_known: &HashMap<String, SubwayStatus>) -> Vec<SubwayStatus> {
+    let mut statuses: Vec<SubwayStatus> = last_known.values().cloned().collect();
+    statuses.sort_by(|a, b| a.line.cmp(&b.line));
+    statuses
+}
+
+/// Polls the latest statuses forever, publishing changes to `hub`
+///
+/// A failed poll is logged and retried on the next tick.
+pub async fn poll_statuses(db: PgPool, hub: StatusHub) {
+    let mut interval = tokio::time::interval(STATUS_POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        match status_store::latest_statuses(&db, false).await {
+            Ok(statuses) => {
+                hub.publish(statuses);
+            }
+            Err(e) => warn!("Failed to poll subway statuses: {}", e),
+        }
+    }
+}
+
+/// Handler upgrading `/ws/status` requests to a status WebSocket
+pub async fn ws_status(State(hub): State<StatusHub>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| stream_statuses(socket, hub))
+}
+
+/// Sends `message` as JSON text
+///
+/// # Returns
+/// - `false` if the client has gone away
+async fn send(socket: &mut WebSocket, message: &StatusMessage) -> bool {
+    let text = serde_json::to_string(message).expect("status messages serialize");
+    socket.send(Message::Text(text)).await.is_ok()
+}
+
+/// Sends a snapshot, then every change, until the client disconnects
+async fn stream_statuses(mut socket: WebSocket, hub: StatusHub) {
+    let (statuses, mut changes) = hub.subscribe();
+    if !send(&mut socket, &StatusMessage::Snapshot { statuses }).await {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            change = changes.recv() => {
+                let message = match change {
+                    Ok(status) => StatusMessage::Changed { status },
+                    Err(broadcast::error::RecvError::Lagged(_)) => StatusMessage::Snapshot {
+                        statuses: hub.snapshot(),
+                    },
+                    Err(broadcast::error::RecvError::Closed) => return,
+                };
+                if !send(&mut socket, &message).await {
+                    return;
+                }
+            }
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone, Utc};
+
+    fn statuses(offset_secs: i64) -> Vec<SubwayStatus> {
+        let timestamp =
+            Utc.timestamp_opt(1_700_000_000, 0).unwrap() + Duration::seconds(offset_secs);
+        vec![
+            SubwayStatus {
+                line: "A".to_string(),
+                status: "Good Service".to_string(),
+                timestamp,
+                delays: false,
+                changed_at: timestamp,
+            },
+            SubwayStatus {
+                line: "L".to_string(),
+                status: "Delays".to_string(),
+                timestamp,
+                delays: true,
+                changed_at: timestamp,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_identical_data_sends_snapshot_and_no_deltas() {
+        let hub = StatusHub::new();
+        hub.publish(statuses(0));
+
+        let (snapshot, mut changes) = hub.subscribe();
+        // Same statuses recorded again later: not a change
+        assert_eq!(hub.publish(statuses(5)), 0);
+
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[0].line, "A");
+        assert_eq!(snapshot[1].line, "L");
+        assert!(matches!(
+            changes.try_recv(),
+            Err(broadcast::error::TryRecvError::Empty)
+        ));
+    }
+
+    #[test]
+    fn test_changed_line_is_broadcast() {
+        let hub = StatusHub::new();
+        hub.publish(statuses(0));
+        let (_, mut changes) = hub.subscribe();
+
+        let mut fresh = statuses(5);
+        fresh[1].status = "Good Service".to_string();
+        fresh[1].delays = false;
+        assert_eq!(hub.publish(fresh), 1);
+
+        let change = changes.try_recv().unwrap();
+        assert_eq!(change.line, "L");
+        assert!(!change.delays);
+        assert!(changes.try_recv().is_err());
+        assert_eq!(hub.snapshot()[1].status, "Good Service");
+    }
+
+    #[test]
+    fn test_status_message_is_tagged() {
+        let message = StatusMessage::Changed {
+            status: statuses(0).remove(0),
+        };
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(json["type"], "changed");
+        assert_eq!(json["status"]["line"], "A");
+    }
+}
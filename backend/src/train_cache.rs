@@ -0,0 +1,122 @@
+//! Shared cache of live train positions
+//!
+//! Fetching and decoding every GTFS feed is the most expensive thing the API
+//! does, so train handlers read positions from a [`TrainCache`] instead of
+//! fetching per request. A background task ([`refresh_trains`]) refills it every
+//! [`TRAIN_REFRESH_INTERVAL`], so MTA load doesn't grow with the number of
+//! clients. Without the task, as in tests, the first request after the cache goes
+//! stale refills it, and requests arriving meanwhile wait for that refill rather
+//! than fetching the feeds themselves.
+
+use nyc_pulse_backend::gtfs::{FeedHealth, GtfsHandler, TrainPositions};
+use parking_lot::{Mutex, RwLock};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// How often the background task refetches train positions
+pub const TRAIN_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Age past which cached positions are refetched by the next request
+///
+/// A few refresh intervals, so a slow round of feed fetches doesn't send every
+/// request to the feeds while the background task is still working.
+const TRAIN_CACHE_MAX_AGE: Duration = Duration::from_secs(5);
+
+/// Most recent train positions and when they were fetched, shared by every clone
+#[derive(Clone, Default)]
+pub struct TrainCache {
+    latest: Arc<RwLock<Option<(Instant, TrainPositions)>>>,
+    /// Held while a request refills the cache, so only one fetches at a time
+    refill: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl TrainCache {
+    /// Creates an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the cached positions with `trains`, fetched just now
+    pub fn store(&self, trains: TrainPositions) {
+        *self.latest.write() = Some((Instant::now(), trains));
+    }
+
+    /// Cached positions fetched less than `max_age` ago
+    fn fresh(&self, max_age: Duration) -> Option<TrainPositions> {
+        self.latest
+            .read()
+            .as_ref()
+            .filter(|(fetched_at, _)| fetched_at.elapsed() < max_age)
+            .map(|(_, trains)| trains.clone())
+    }
+
+    /// Cached positions, fetching and caching them first if the cache is stale
+    ///
+    /// Concurrent requests for a stale cache share one fetch: the first
+    /// refills it while the others wait, then read what it stored.
+    pub async fn get(
+        &self,
+        gtfs: &GtfsHandler,
+        feed_health: &Mutex<HashMap<String, FeedHealth>>,
+    ) -> TrainPositions {
+        if let Some(trains) = self.fresh(TRAIN_CACHE_MAX_AGE) {
+            return trains;
+        }
+        let _refill = self.refill.lock().await;
+        // Another request may have refilled the cache while this one waited
+        if let Some(trains) = self.fresh(TRAIN_CACHE_MAX_AGE) {
+            return trains;
+        }
+        let trains = gtfs.get_train_positions(feed_health).await;
+        self.store(trains.clone());
+        trains
+    }
+}
+
+/// Refetches train positions into `cache` forever, every [`TRAIN_REFRESH_INTERVAL`]
+///
+/// A round in which every feed failed is logged and leaves the previous
+/// positions cached until they go stale.
+pub async fn refresh_trains(
+    gtfs: GtfsHandler,
+    feed_health: Arc<Mutex<HashMap<String, FeedHealth>>>,
+    cache: TrainCache,
+) {
+    let mut interval = tokio::time::interval(TRAIN_REFRESH_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let trains = gtfs.get_train_positions(&feed_health).await;
+        if trains.all_feeds_failed() {
+            warn!(
+                "Failed to refresh train positions: all {} feeds failed",
+                trains.feeds_total
+            );
+        } else {
+            cache.store(trains);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cached_positions_go_stale() {
+        let cache = TrainCache::new();
+        assert!(cache.fresh(TRAIN_CACHE_MAX_AGE).is_none());
+
+        cache.store(TrainPositions {
+            positions: Vec::new(),
+            scheduled: Vec::new(),
+            feeds_total: 8,
+            feeds_failed: 1,
+        });
+        let cached = cache.fresh(TRAIN_CACHE_MAX_AGE).unwrap();
+        assert_eq!(cached.feeds_failed, 1);
+        assert!(cache.fresh(Duration::ZERO).is_none());
+    }
+}
@@ -0,0 +1,19 @@
+//! Installing a `LINES_FILE` table changes the colors every lookup returns
+//!
+//! Kept out of the unit tests because the installed table is process-wide.
+
+use nyc_pulse_backend::lines::{install_lines, load_lines_file};
+use nyc_pulse_common::lines::{line_info, route_color};
+
+#[test]
+fn installed_lines_file_colors_are_used() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/lines.json");
+    assert!(install_lines(load_lines_file(Some(path)).unwrap()));
+
+    assert_eq!(route_color("L"), "#123456");
+    assert_eq!(line_info("L").unwrap().color, "#123456");
+    assert_eq!(route_color("A"), "#0039A6");
+    // The table can only be installed once
+    assert!(!install_lines(Vec::new()));
+    assert_eq!(route_color("L"), "#123456");
+}
@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// One of the five NYC boroughs
+///
+/// Serializes as the full name ("Staten Island") and parses from either the
+/// full name or the MTA's abbreviation ("SI"), ignoring case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "&'static str", try_from = "String")]
+pub enum Borough {
+    Manhattan,
+    Brooklyn,
+    Queens,
+    Bronx,
+    StatenIsland,
+}
+
+impl Borough {
+    /// Every borough
+    pub const ALL: [Borough; 5] = [
+        Borough::Manhattan,
+        Borough::Brooklyn,
+        Borough::Queens,
+        Borough::Bronx,
+        Borough::StatenIsland,
+    ];
+
+    /// Full name, e.g. "Staten Island"
+    pub fn name(self) -> &'static str {
+        match self {
+            Borough::Manhattan => "Manhattan",
+            Borough::Brooklyn => "Brooklyn",
+            Borough::Queens => "Queens",
+            Borough::Bronx => "Bronx",
+            Borough::StatenIsland => "Staten Island",
+        }
+    }
+
+    /// Abbreviation used in MTA station data, e.g. "SI"
+    pub fn abbreviation(self) -> &'static str {
+        match self {
+            Borough::Manhattan => "M",
+            Borough::Brooklyn => "Bk",
+            Borough::Queens => "Q",
+            Borough::Bronx => "Bx",
+            Borough::StatenIsland => "SI",
+        }
+    }
+}
+
+impl fmt::Display for Borough {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Error returned when a string names no borough
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseBoroughError(pub String);
+
+impl fmt::Display for ParseBoroughError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown borough: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseBoroughError {}
+
+impl FromStr for Borough {
+    type Err = ParseBoroughError;
+
+    /// Parses a full name ("Brooklyn", "The Bronx") or MTA abbreviation ("Bk")
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let key = s.trim().to_lowercase();
+        let key = key.strip_prefix("the ").unwrap_or(&key);
+        Borough::ALL
+            .into_iter()
+            .find(|borough| {
+                key == borough.name().to_lowercase() || key == borough.abbreviation().to_lowercase()
+            })
+            .ok_or_else(|| ParseBoroughError(s.to_string()))
+    }
+}
+
+impl From<Borough> for &'static str {
+    fn from(borough: Borough) -> Self {
+        borough.name()
+    }
+}
+
+impl TryFrom<String> for Borough {
+    type Error = ParseBoroughError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_abbreviations() {
+        assert_eq!("M".parse(), Ok(Borough::Manhattan));
+        assert_eq!("Bk".parse(), Ok(Borough::Brooklyn));
+        assert_eq!("Q".parse(), Ok(Borough::Queens));
+        assert_eq!("Bx".parse(), Ok(Borough::Bronx));
+        assert_eq!("SI".parse(), Ok(Borough::StatenIsland));
+    }
+
+    #[test]
+    fn test_parse_full_names() {
+        for borough in Borough::ALL {
+            assert_eq!(borough.name().parse(), Ok(borough));
+        }
+        assert_eq!(" staten island ".parse(), Ok(Borough::StatenIsland));
+        assert_eq!("The Bronx".parse(), Ok(Borough::Bronx));
+        assert_eq!("bk".parse(), Ok(Borough::Brooklyn));
+    }
+
+    #[test]
+    fn test_parse_unknown() {
+        assert_eq!(
+            "Jersey".parse::<Borough>(),
+            Err(ParseBoroughError("Jersey".to_string()))
+        );
+        assert!("".parse::<Borough>().is_err());
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let json = serde_json::to_string(&Borough::StatenIsland).unwrap();
+        assert_eq!(json, "\"Staten Island\"");
+        let parsed: Borough = serde_json::from_str("\"Bk\"").unwrap();
+        assert_eq!(parsed, Borough::Brooklyn);
+    }
+}
@@ -0,0 +1,179 @@
+//! MTA GTFS-realtime feed catalogue
+//!
+//! The MTA splits the subway's real-time data across eight feeds, each covering
+//! a group of lines. They are listed in [`SUBWAY_FEEDS`], shared by the backend,
+//! which polls them, and the frontend, which styles their lines.
+
+/// Base URL shared by all MTA subway feeds
+pub const MTA_FEED_BASE_URL: &str = "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/";
+
+/// A single MTA GTFS-realtime feed and the lines it carries
+#[derive(Debug, PartialEq)]
+pub struct FeedInfo {
+    /// Short identifier used in configuration (e.g. "ace")
+    pub key: &'static str,
+    /// Path of the feed relative to [`MTA_FEED_BASE_URL`]
+    pub path: &'static str,
+    /// Line identifiers included in the feed
+    pub lines: &'static [&'static str],
+}
+
+impl FeedInfo {
+    /// Full URL of the feed on the MTA API
+    pub fn url(&self) -> String {
+        self.url_from(MTA_FEED_BASE_URL)
+    }
+
+    /// Full URL of the feed relative to `base_url`, which should end in `/`
+    pub fn url_from(&self, base_url: &str) -> String {
+        format!("{}{}", base_url, self.path)
+    }
+}
+
+/// All MTA subway feeds
+pub const SUBWAY_FEEDS: [FeedInfo; 8] = [
+    FeedInfo {
+        key: "1234567",
+        path: "nyct%2Fgtfs",
+        lines: &["1", "2", "3", "4", "5", "6", "7", "GS"],
+    },
+    FeedInfo {
+        key: "ace",
+        path: "nyct%2Fgtfs-ace",
+        lines: &["A", "C", "E", "FS", "H"],
+    },
+    FeedInfo {
+        key: "bdfm",
+        path: "nyct%2Fgtfs-bdfm",
+        lines: &["B", "D", "F", "M"],
+    },
+    FeedInfo {
+        key: "g",
+        path: "nyct%2Fgtfs-g",
+        lines: &["G"],
+    },
+    FeedInfo {
+        key: "jz",
+        path: "nyct%2Fgtfs-jz",
+        lines: &["J", "Z"],
+    },
+    FeedInfo {
+        key: "nqrw",
+        path: "nyct%2Fgtfs-nqrw",
+        lines: &["N", "Q", "R", "W"],
+    },
+    FeedInfo {
+        key: "l",
+        path: "nyct%2Fgtfs-l",
+        lines: &["L"],
+    },
+    FeedInfo {
+        key: "si",
+        path: "nyct%2Fgtfs-si",
+        lines: &["SI"],
+    },
+];
+
+/// The MTA's subway service alerts feed, covering every line
+///
+/// It carries GTFS-realtime `Alert` entities only, no trip updates, so it isn't
+/// one of the [`SUBWAY_FEEDS`] polled for trains.
+pub const SUBWAY_ALERTS_FEED: FeedInfo = FeedInfo {
+    key: "alerts",
+    path: "camsys%2Fsubway-alerts",
+    lines: &[],
+};
+
+/// Finds the feed in [`SUBWAY_FEEDS`] carrying `route_id`
+///
+/// Route IDs are resolved with [`crate::lines::normalize_route`] first, so
+/// express variants such as `6X` find their base line's feed.
+pub fn feed_for_route(route_id: &str) -> Option<&'static FeedInfo> {
+    let line = crate::lines::normalize_route(route_id)?;
+    SUBWAY_FEEDS.iter().find(|feed| feed.lines.contains(&line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feed_for_route() {
+        let key = |route_id| feed_for_route(route_id).map(|feed| feed.key);
+        assert_eq!(key("A"), Some("ace"));
+        assert_eq!(key("7"), Some("1234567"));
+        assert_eq!(key("SI"), Some("si"));
+        assert_eq!(key("6X"), Some("1234567"));
+        assert_eq!(key("FS"), Some("ace"));
+        // Plain "S" is a line but no feed reports it
+        assert_eq!(key("S"), None);
+        assert_eq!(key("XX"), None);
+    }
+
+    #[test]
+    fn test_feed_urls_validity() {
+        for feed in SUBWAY_FEEDS.iter() {
+            // Check URL format
+            let url = feed.url();
+            assert!(url.starts_with("https://"));
+            assert!(url.contains("api-endpoint.mta.info"));
+            assert!(url.contains("gtfs"));
+
+            // Check line IDs
+            for line in feed.lines {
+                assert!(!line.is_empty());
+                assert!(line.len() <= 2); // NYC subway lines are 1-2 characters
+            }
+        }
+    }
+
+    #[test]
+    fn test_feed_urls_completeness() {
+        // Get all unique lines from SUBWAY_FEEDS
+        let mut all_lines: Vec<&str> = SUBWAY_FEEDS
+            .iter()
+            .flat_map(|feed| feed.lines.iter().copied())
+            .collect();
+
+        all_lines.sort();
+        all_lines.dedup();
+
+        // Check for major subway lines
+        let required_lines = [
+            "A", "B", "C", "D", "E", "F", "G", "L", "M", "N", "Q", "R", "W", "1", "2", "3", "4",
+            "5", "6", "7",
+        ];
+        for line in required_lines.iter() {
+            assert!(all_lines.contains(line), "Missing line: {}", line);
+        }
+    }
+
+    #[test]
+    fn test_feed_lines_have_line_info() {
+        for feed in SUBWAY_FEEDS.iter() {
+            for line in feed.lines {
+                assert!(
+                    crate::lines::line_info(line).is_some(),
+                    "Line {} has no LineInfo entry",
+                    line
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_feed_urls_no_duplicates() {
+        // Check that no line appears in multiple feeds
+        let mut seen_lines = std::collections::HashSet::new();
+
+        for feed in SUBWAY_FEEDS.iter() {
+            for &line in feed.lines {
+                assert!(
+                    seen_lines.insert(line),
+                    "Line {} appears in multiple feeds",
+                    line
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+// common/src/geohash.rs
+//! Geohash encoding for spatial bucketing
+//!
+//! A geohash names a latitude/longitude cell with a base-32 string; points
+//! sharing a prefix are in the same cell, so clients can group stations and
+//! trains on the map by prefix instead of comparing coordinates.
+
+/// Characters of the geohash base-32 alphabet, in value order
+const BASE32: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// Characters in the geohashes attached to stops, cells of about 150 m by 150 m
+pub const GEOHASH_PRECISION: usize = 7;
+
+/// Geohash of `(latitude, longitude)` with `precision` characters
+///
+/// Bits alternate between longitude and latitude, starting with longitude,
+/// each halving the remaining range; every 5 bits make one character.
+pub fn encode(latitude: f64, longitude: f64, precision: usize) -> String {
+    let mut latitudes = (-90.0, 90.0);
+    let mut longitudes = (-180.0, 180.0);
+    let mut hash = String::with_capacity(precision);
+    let mut even_bit = true;
+
+    while hash.len() < precision {
+        let mut index = 0;
+        for _ in 0..5 {
+            let (range, value) = if even_bit {
+                (&mut longitudes, longitude)
+            } else {
+                (&mut latitudes, latitude)
+            };
+            let middle = (range.0 + range.1) / 2.0;
+            index <<= 1;
+            if value >= middle {
+                index |= 1;
+                range.0 = middle;
+            } else {
+                range.1 = middle;
+            }
+            even_bit = !even_bit;
+        }
+        hash.push(BASE32[index] as char);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_known_points() {
+        // The reference example from the geohash definition
+        assert_eq!(encode(57.64911, 10.40744, 11), "u4pruydqqvj");
+        // Bedford Av, Brooklyn, and Times Sq, Manhattan
+        assert!(encode(40.717304, -73.956872, GEOHASH_PRECISION).starts_with("dr5r"));
+        assert!(encode(40.75529, -73.987495, GEOHASH_PRECISION).starts_with("dr5ru"));
+    }
+
+    #[test]
+    fn test_encode_precision_and_prefixes() {
+        let long = encode(40.717304, -73.956872, 9);
+        let short = encode(40.717304, -73.956872, GEOHASH_PRECISION);
+
+        assert_eq!(short.len(), GEOHASH_PRECISION);
+        assert!(long.starts_with(&short));
+        assert_eq!(encode(0.0, 0.0, 0), "");
+    }
+}
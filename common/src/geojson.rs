@@ -0,0 +1,164 @@
+// common/src/geojson.rs
+use serde::Serialize;
+
+/// A GeoJSON Feature representing a subway station or train
+#[derive(Debug, Serialize, Clone)]
+pub struct GeoJsonFeature {
+    #[serde(rename = "type")]
+    pub feature_type: String,
+    pub properties: GeoJsonProperties,
+    pub geometry: GeoJsonGeometry,
+}
+
+/// Properties associated with a GeoJSON Feature
+#[derive(Debug, Serialize, Clone)]
+pub struct GeoJsonProperties {
+    pub name: String,
+    pub lines: String,
+    pub division: String,
+    pub borough: String,
+    pub ada: bool,
+    pub ada_notes: String,
+    pub north_direction: String,
+    pub south_direction: String,
+    pub color: String,
+    /// Glyph drawn on a train's bullet (see [`crate::lines::route_bullet`]);
+    /// empty, and left out, for stations
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub bullet: String,
+    /// How far a train is between its stops, from 0.0 to 1.0; `None`, and left
+    /// out, for stations
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<f64>,
+    /// Label color legible on the bullet (see [`crate::lines::text_color_for_line`]);
+    /// empty, and left out, for stations
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub text_color: String,
+    /// Geohash of the feature's point (see [`crate::geohash`]), so clients can
+    /// cluster features sharing a prefix; empty, and left out, when unknown
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub geohash: String,
+}
+
+/// Geometry component of a GeoJSON Feature
+#[derive(Debug, Serialize, Clone)]
+pub struct GeoJsonGeometry {
+    #[serde(rename = "type")]
+    pub geometry_type: String,
+    #[serde(rename = "coordinates")]
+    pub coordinates: GeoJsonCoordinates,
+}
+
+/// Decimal places map coordinates are rounded to, about 0.1 m at NYC's latitude
+///
+/// Full `f64` precision adds ten or so digits per coordinate that no map can
+/// show, which adds up in the train layer fetched every few seconds.
+pub const COORDINATE_DECIMALS: i32 = 6;
+
+/// Rounds a longitude or latitude to `decimals` places
+pub fn round_coordinate(value: f64, decimals: i32) -> f64 {
+    let scale = 10f64.powi(decimals);
+    (value * scale).round() / scale
+}
+
+impl GeoJsonGeometry {
+    /// Point at `[longitude, latitude]`, rounded to [`COORDINATE_DECIMALS`] places
+    pub fn point([longitude, latitude]: [f64; 2]) -> Self {
+        Self {
+            geometry_type: "Point".to_string(),
+            coordinates: GeoJsonCoordinates::Point([
+                round_coordinate(longitude, COORDINATE_DECIMALS),
+                round_coordinate(latitude, COORDINATE_DECIMALS),
+            ]),
+        }
+    }
+}
+
+/// Coordinates for either a Point or LineString geometry
+#[derive(Debug, Serialize, Clone)]
+#[serde(untagged)]
+pub enum GeoJsonCoordinates {
+    Point([f64; 2]),
+    LineString(Vec<[f64; 2]>),
+}
+
+/// Collection of GeoJSON Features
+#[derive(Debug, Serialize, Clone)]
+pub struct GeoJsonCollection {
+    #[serde(rename = "type")]
+    pub collection_type: String,
+    pub features: Vec<GeoJsonFeature>,
+}
+
+impl GeoJsonCollection {
+    /// Wraps `features` in a `FeatureCollection`
+    pub fn from_features(features: Vec<GeoJsonFeature>) -> Self {
+        Self {
+            collection_type: "FeatureCollection".to_string(),
+            features,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collection_serializes_as_geojson() {
+        let feature = GeoJsonFeature {
+            feature_type: "Feature".to_string(),
+            properties: GeoJsonProperties {
+                name: "Bedford Av".to_string(),
+                lines: "L".to_string(),
+                division: "BMT".to_string(),
+                borough: "Bk".to_string(),
+                ada: false,
+                ada_notes: String::new(),
+                north_direction: "Manhattan".to_string(),
+                south_direction: "Canarsie".to_string(),
+                color: "#A7A9AC".to_string(),
+                bullet: String::new(),
+                progress: None,
+                text_color: String::new(),
+                geohash: String::new(),
+            },
+            geometry: GeoJsonGeometry::point([-73.956872, 40.717304]),
+        };
+
+        let json = serde_json::to_value(GeoJsonCollection::from_features(vec![feature])).unwrap();
+
+        assert_eq!(json["type"], "FeatureCollection");
+        assert_eq!(json["features"][0]["type"], "Feature");
+        assert_eq!(json["features"][0]["geometry"]["type"], "Point");
+        assert!(json["features"][0]["properties"].get("bullet").is_none());
+        assert!(json["features"][0]["properties"].get("progress").is_none());
+        assert!(json["features"][0]["properties"]
+            .get("text_color")
+            .is_none());
+        assert_eq!(
+            json["features"][0]["geometry"]["coordinates"],
+            serde_json::json!([-73.956872, 40.717304])
+        );
+    }
+
+    #[test]
+    fn test_point_coordinates_are_rounded() {
+        let geometry = GeoJsonGeometry::point([-73.956872345678, 40.71730449999999]);
+        let GeoJsonCoordinates::Point([longitude, latitude]) = geometry.coordinates else {
+            panic!("expected a point");
+        };
+        assert_eq!(longitude, -73.956872);
+        assert_eq!(latitude, 40.717304);
+        // Moved by well under a meter
+        assert!((longitude - -73.956872345678).abs() < 1e-6);
+        assert!((latitude - 40.71730449999999).abs() < 1e-6);
+
+        assert_eq!(
+            serde_json::to_string(&geometry).unwrap(),
+            r#"{"type":"Point","coordinates":[-73.956872,40.717304]}"#
+        );
+        assert_eq!(round_coordinate(40.7, COORDINATE_DECIMALS), 40.7);
+        assert_eq!(round_coordinate(-73.9876545, 3), -73.988);
+    }
+}
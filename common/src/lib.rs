@@ -1,4 +1,14 @@
 // common/src/lib.rs
+pub mod borough;
+pub mod feeds;
+pub mod geohash;
+pub mod geojson;
+pub mod lines;
+pub mod open_data;
+pub mod serde_helpers;
+pub mod service_status;
+pub mod trains;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
@@ -6,8 +16,19 @@ use serde::{Deserialize, Serialize};
 pub struct SubwayStatus {
     pub line: String,
     pub status: String,
+    #[serde(with = "serde_helpers::rfc3339")]
     pub timestamp: DateTime<Utc>,
     pub delays: bool,
+    /// When `status` or `delays` last changed
+    #[serde(with = "serde_helpers::rfc3339")]
+    pub changed_at: DateTime<Utc>,
+}
+
+impl SubwayStatus {
+    /// Whole minutes the line has been in its current status at unix time `now_secs`
+    pub fn minutes_since_change(&self, now_secs: i64) -> i64 {
+        (now_secs - self.changed_at.timestamp()).max(0) / 60
+    }
 }
 
 #[cfg(test)]
@@ -23,6 +44,7 @@ mod tests {
             status: "Good Service".to_string(),
             timestamp,
             delays: false,
+            changed_at: timestamp,
         };
 
         let status2 = SubwayStatus {
@@ -30,11 +52,47 @@ mod tests {
             status: "Good Service".to_string(),
             timestamp,
             delays: false,
+            changed_at: timestamp,
         };
 
         assert_eq!(status1, status2);
     }
 
+    #[test]
+    fn test_subway_status_timestamp_is_rfc3339() {
+        let status = SubwayStatus {
+            line: "A".to_string(),
+            status: "Good Service".to_string(),
+            timestamp: Utc.timestamp_opt(1640995200, 0).unwrap(),
+            delays: false,
+            changed_at: Utc.timestamp_opt(1640994000, 0).unwrap(),
+        };
+
+        let json = serde_json::to_value(&status).unwrap();
+        assert_eq!(json["timestamp"], "2022-01-01T00:00:00Z");
+        assert_eq!(json["changed_at"], "2021-12-31T23:40:00Z");
+        assert_eq!(
+            serde_json::from_value::<SubwayStatus>(json).unwrap(),
+            status
+        );
+    }
+
+    #[test]
+    fn test_minutes_since_change() {
+        let changed_at = Utc.timestamp_opt(1640995200, 0).unwrap();
+        let status = SubwayStatus {
+            line: "L".to_string(),
+            status: "Delays".to_string(),
+            timestamp: changed_at,
+            delays: true,
+            changed_at,
+        };
+
+        assert_eq!(status.minutes_since_change(1640995200 + 14 * 60 + 59), 14);
+        // Clock skew never produces a negative duration
+        assert_eq!(status.minutes_since_change(1640995200 - 120), 0);
+    }
+
     #[test]
     fn test_subway_status_inequality() {
         let timestamp = Utc.timestamp_opt(1640995200, 0).unwrap();
@@ -43,6 +101,7 @@ mod tests {
             status: "Good Service".to_string(),
             timestamp,
             delays: false,
+            changed_at: timestamp,
         };
 
         let status2 = SubwayStatus {
@@ -50,6 +109,7 @@ mod tests {
             status: "Good Service".to_string(),
             timestamp,
             delays: false,
+            changed_at: timestamp,
         };
 
         assert_ne!(status1, status2);
@@ -0,0 +1,502 @@
+// common/src/lines.rs
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::sync::OnceLock;
+
+/// Historical operating division a line belongs to, used to group the status grid
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Division {
+    /// Numbered lines and the 42 St Shuttle
+    Irt,
+    /// Broadway, Nassau St, Canarsie and Franklin Av lines
+    Bmt,
+    /// 8 Av, 6 Av and Crosstown lines, plus the Rockaway Park Shuttle
+    Ind,
+}
+
+/// Static metadata for a subway line
+///
+/// Deserializes only from `'static` input, since the strings are borrowed.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct LineInfo {
+    /// Line identifier as used in GTFS and the status table (e.g. "A", "1")
+    pub id: &'static str,
+    /// MTA service name
+    pub name: &'static str,
+    /// Bullet color as a `#RRGGBB` hex string
+    pub color: &'static str,
+    /// Position by average weekday ridership, 1 being the busiest line
+    pub ridership_rank: u8,
+    /// Division the line is signed under; `None` for the Staten Island Railway
+    /// and unspecified shuttles
+    pub division: Option<Division>,
+}
+
+/// Every subway line, grouped by trunk
+pub const LINES: [LineInfo; 27] = [
+    LineInfo {
+        id: "1",
+        name: "Broadway-7 Av Local",
+        color: "#EE352E",
+        ridership_rank: 6,
+        division: Some(Division::Irt),
+    },
+    LineInfo {
+        id: "2",
+        name: "7 Av Express",
+        color: "#EE352E",
+        ridership_rank: 8,
+        division: Some(Division::Irt),
+    },
+    LineInfo {
+        id: "3",
+        name: "7 Av Express",
+        color: "#EE352E",
+        ridership_rank: 15,
+        division: Some(Division::Irt),
+    },
+    LineInfo {
+        id: "4",
+        name: "Lexington Av Express",
+        color: "#00933C",
+        ridership_rank: 4,
+        division: Some(Division::Irt),
+    },
+    LineInfo {
+        id: "5",
+        name: "Lexington Av Express",
+        color: "#00933C",
+        ridership_rank: 14,
+        division: Some(Division::Irt),
+    },
+    LineInfo {
+        id: "6",
+        name: "Lexington Av Local",
+        color: "#00933C",
+        ridership_rank: 2,
+        division: Some(Division::Irt),
+    },
+    LineInfo {
+        id: "7",
+        name: "Flushing Local",
+        color: "#B933AD",
+        ridership_rank: 3,
+        division: Some(Division::Irt),
+    },
+    LineInfo {
+        id: "A",
+        name: "8 Av Express",
+        color: "#0039A6",
+        ridership_rank: 7,
+        division: Some(Division::Ind),
+    },
+    LineInfo {
+        id: "C",
+        name: "8 Av Local",
+        color: "#0039A6",
+        ridership_rank: 18,
+        division: Some(Division::Ind),
+    },
+    LineInfo {
+        id: "E",
+        name: "8 Av Local",
+        color: "#0039A6",
+        ridership_rank: 5,
+        division: Some(Division::Ind),
+    },
+    LineInfo {
+        id: "B",
+        name: "6 Av Express",
+        color: "#FF6319",
+        ridership_rank: 17,
+        division: Some(Division::Ind),
+    },
+    LineInfo {
+        id: "D",
+        name: "6 Av Express",
+        color: "#FF6319",
+        ridership_rank: 12,
+        division: Some(Division::Ind),
+    },
+    LineInfo {
+        id: "F",
+        name: "6 Av Local",
+        color: "#FF6319",
+        ridership_rank: 1,
+        division: Some(Division::Ind),
+    },
+    LineInfo {
+        id: "M",
+        name: "6 Av Local",
+        color: "#FF6319",
+        ridership_rank: 16,
+        division: Some(Division::Ind),
+    },
+    LineInfo {
+        id: "G",
+        name: "Brooklyn-Queens Crosstown",
+        color: "#6CBE45",
+        ridership_rank: 20,
+        division: Some(Division::Ind),
+    },
+    LineInfo {
+        id: "J",
+        name: "Nassau St Express",
+        color: "#996633",
+        ridership_rank: 19,
+        division: Some(Division::Bmt),
+    },
+    LineInfo {
+        id: "Z",
+        name: "Nassau St Express",
+        color: "#996633",
+        ridership_rank: 22,
+        division: Some(Division::Bmt),
+    },
+    LineInfo {
+        id: "L",
+        name: "14 St-Canarsie Local",
+        color: "#A7A9AC",
+        ridership_rank: 9,
+        division: Some(Division::Bmt),
+    },
+    LineInfo {
+        id: "N",
+        name: "Broadway Express",
+        color: "#FCCC0A",
+        ridership_rank: 10,
+        division: Some(Division::Bmt),
+    },
+    LineInfo {
+        id: "Q",
+        name: "Broadway Express",
+        color: "#FCCC0A",
+        ridership_rank: 11,
+        division: Some(Division::Bmt),
+    },
+    LineInfo {
+        id: "R",
+        name: "Broadway Local",
+        color: "#FCCC0A",
+        ridership_rank: 13,
+        division: Some(Division::Bmt),
+    },
+    LineInfo {
+        id: "W",
+        name: "Broadway Local",
+        color: "#FCCC0A",
+        ridership_rank: 21,
+        division: Some(Division::Bmt),
+    },
+    LineInfo {
+        id: "GS",
+        name: "42 St Shuttle",
+        color: "#808183",
+        ridership_rank: 23,
+        division: Some(Division::Irt),
+    },
+    LineInfo {
+        id: "FS",
+        name: "Franklin Av Shuttle",
+        color: "#808183",
+        ridership_rank: 25,
+        division: Some(Division::Bmt),
+    },
+    LineInfo {
+        id: "H",
+        name: "Rockaway Park Shuttle",
+        color: "#808183",
+        ridership_rank: 26,
+        division: Some(Division::Ind),
+    },
+    // Sources that don't say which shuttle report plain "S"
+    LineInfo {
+        id: "S",
+        name: "Shuttle",
+        color: "#808183",
+        ridership_rank: 27,
+        division: None,
+    },
+    LineInfo {
+        id: "SI",
+        name: "Staten Island Railway",
+        color: "#808183",
+        ridership_rank: 24,
+        division: None,
+    },
+];
+
+/// Table set by [`install_lines`], read instead of [`LINES`] once set
+static INSTALLED_LINES: OnceLock<Vec<LineInfo>> = OnceLock::new();
+
+/// The line table every lookup here reads: the one set by [`install_lines`],
+/// or the built-in [`LINES`]
+pub fn lines() -> &'static [LineInfo] {
+    INSTALLED_LINES.get().map_or(&LINES, Vec::as_slice)
+}
+
+/// Replaces the built-in [`LINES`] for the rest of the process
+///
+/// Meant to be called once at startup, before any lookup. Only the first call
+/// takes effect; later ones return `false`.
+pub fn install_lines(table: Vec<LineInfo>) -> bool {
+    INSTALLED_LINES.set(table).is_ok()
+}
+
+/// [`LINES`] with each of `overrides` replacing the line with the same ID
+///
+/// Overrides with an ID missing from [`LINES`] are appended, in order.
+pub fn merge_lines(overrides: &[LineInfo]) -> Vec<LineInfo> {
+    let mut table = LINES.to_vec();
+    for line in overrides {
+        match table.iter_mut().find(|existing| existing.id == line.id) {
+            Some(existing) => *existing = *line,
+            None => table.push(*line),
+        }
+    }
+    table
+}
+
+/// Whether `color` is a `#RRGGBB` hex string, as [`LineInfo::color`] must be
+pub fn is_hex_color(color: &str) -> bool {
+    color.len() == 7 && color.starts_with('#') && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Looks up a line by identifier in [`lines`]
+pub fn line_info(id: &str) -> Option<&'static LineInfo> {
+    lines().iter().find(|line| line.id == id)
+}
+
+/// Identifiers of every line in [`lines`]
+pub fn known_routes() -> BTreeSet<&'static str> {
+    lines().iter().map(|line| line.id).collect()
+}
+
+/// Resolves a GTFS route ID to the identifier of a line in [`LINES`]
+///
+/// Express variants such as `6X` resolve to their base line, and `SIR`, which
+/// the NY Open Data station list uses for the Staten Island Railway, resolves
+/// to the feeds' `SI`. Anything else missing from [`LINES`] (e.g. `SS` or a
+/// blank ID) is `None`.
+pub fn normalize_route(route_id: &str) -> Option<&'static str> {
+    let route_id = match route_id.trim() {
+        "SIR" => "SI",
+        route_id => route_id,
+    };
+    let base = route_id.strip_suffix('X').filter(|base| !base.is_empty());
+    [Some(route_id), base]
+        .into_iter()
+        .flatten()
+        .find_map(line_info)
+        .map(|line| line.id)
+}
+
+/// Whether `route_id` is an express variant of a line in [`LINES`], such as `6X`
+pub fn is_express(route_id: &str) -> bool {
+    let route_id = route_id.trim();
+    line_info(route_id).is_none()
+        && route_id
+            .strip_suffix('X')
+            .is_some_and(|base| line_info(base).is_some())
+}
+
+/// Suffix marking an express (diamond) bullet, e.g. "6◆" for `6X`
+pub const EXPRESS_MARKER: char = '◆';
+
+/// Returns the glyph drawn on a route's bullet
+///
+/// Express variants keep their base line with [`EXPRESS_MARKER`] appended, and
+/// the 42 St, Franklin Av and Rockaway Park shuttles are all signed "S". Routes
+/// missing from [`LINES`] are shown as given.
+pub fn route_bullet(route_id: &str) -> String {
+    let route_id = route_id.trim();
+    match normalize_route(route_id) {
+        Some("GS" | "FS" | "H") => "S".to_string(),
+        Some(line) if line != route_id => format!("{}{}", line, EXPRESS_MARKER),
+        Some(line) => line.to_string(),
+        None => route_id.to_string(),
+    }
+}
+
+/// Color for routes missing from [`LINES`]
+pub const DEFAULT_COLOR: &str = "#808183";
+
+/// Returns the bullet color for a GTFS route ID
+///
+/// Whole IDs are looked up first so the shuttles (`GS`, `FS`, `H`) and `SI`
+/// aren't mistaken for their first letter; express variants such as `6X`
+/// fall back to their base line.
+pub fn route_color(route_id: &str) -> &'static str {
+    line_info(route_id)
+        .or_else(|| line_info(route_id.get(..1)?))
+        .map_or(DEFAULT_COLOR, |line| line.color)
+}
+
+/// Label text color for bullets light enough to need dark text
+pub const DARK_TEXT: &str = "#000000";
+
+/// Label text color for every other bullet
+pub const LIGHT_TEXT: &str = "#ffffff";
+
+/// Returns the text color legible on a line's bullet (see [`route_color`])
+///
+/// Bullets whose luma (Rec. 601 weights) is above one half, such as the yellow
+/// Broadway lines and the gray shuttles, get [`DARK_TEXT`]; the rest get
+/// [`LIGHT_TEXT`].
+pub fn text_color_for_line(line: &str) -> &'static str {
+    if luma(route_color(line)) > 0.5 {
+        DARK_TEXT
+    } else {
+        LIGHT_TEXT
+    }
+}
+
+/// Perceived lightness of a `#RRGGBB` color, from 0.0 (black) to 1.0 (white)
+fn luma(color: &str) -> f64 {
+    let channel = |range: std::ops::Range<usize>| {
+        let value = color
+            .get(range)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        f64::from(value.unwrap_or(0)) / 255.0
+    };
+    0.299 * channel(1..3) + 0.587 * channel(3..5) + 0.114 * channel(5..7)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_info_lookup() {
+        assert_eq!(line_info("L").unwrap().color, "#A7A9AC");
+        assert_eq!(line_info("SI").unwrap().name, "Staten Island Railway");
+        assert!(line_info("X").is_none());
+    }
+
+    #[test]
+    fn test_shuttles_are_distinct() {
+        assert_eq!(line_info("GS").unwrap().name, "42 St Shuttle");
+        assert_eq!(line_info("FS").unwrap().name, "Franklin Av Shuttle");
+        assert_eq!(line_info("H").unwrap().name, "Rockaway Park Shuttle");
+        for id in ["GS", "FS", "H", "S", "SI"] {
+            assert_eq!(line_info(id).unwrap().color, "#808183", "{}", id);
+        }
+    }
+
+    #[test]
+    fn test_divisions() {
+        assert_eq!(line_info("GS").unwrap().division, Some(Division::Irt));
+        assert_eq!(line_info("FS").unwrap().division, Some(Division::Bmt));
+        assert_eq!(line_info("H").unwrap().division, Some(Division::Ind));
+        assert_eq!(line_info("SI").unwrap().division, None);
+    }
+
+    #[test]
+    fn test_ridership_ranks_are_distinct() {
+        let mut ranks: Vec<u8> = LINES.iter().map(|line| line.ridership_rank).collect();
+        ranks.sort_unstable();
+        assert_eq!(ranks, (1..=LINES.len() as u8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_route_color_falls_back_to_base_line() {
+        assert_eq!(route_color("6X"), "#00933C");
+        assert_eq!(route_color("F"), "#FF6319");
+        assert_eq!(route_color("FS"), "#808183");
+        assert_eq!(route_color(""), DEFAULT_COLOR);
+        assert_eq!(route_color("?"), DEFAULT_COLOR);
+    }
+
+    #[test]
+    fn test_normalize_route() {
+        assert_eq!(normalize_route("L"), Some("L"));
+        assert_eq!(normalize_route("GS"), Some("GS"));
+        assert_eq!(normalize_route("6X"), Some("6"));
+        assert_eq!(normalize_route("FX"), Some("F"));
+        assert_eq!(normalize_route("SIR"), Some("SI"));
+        assert_eq!(normalize_route("SS"), None);
+        assert_eq!(normalize_route("X"), None);
+        assert_eq!(normalize_route(""), None);
+    }
+
+    #[test]
+    fn test_text_color_for_line() {
+        // Yellow Broadway lines
+        assert_eq!(text_color_for_line("N"), DARK_TEXT);
+        assert_eq!(text_color_for_line("W"), DARK_TEXT);
+        // Blue 8 Av lines
+        assert_eq!(text_color_for_line("A"), LIGHT_TEXT);
+        assert_eq!(text_color_for_line("E"), LIGHT_TEXT);
+
+        assert_eq!(text_color_for_line("L"), DARK_TEXT);
+        assert_eq!(text_color_for_line("GS"), DARK_TEXT);
+        assert_eq!(text_color_for_line("1"), LIGHT_TEXT);
+    }
+
+    #[test]
+    fn test_route_bullet() {
+        assert_eq!(route_bullet("L"), "L");
+        assert_eq!(route_bullet("6X"), "6◆");
+        assert_eq!(route_bullet("FX"), "F◆");
+        assert_eq!(route_bullet("GS"), "S");
+        assert_eq!(route_bullet("H"), "S");
+        assert_eq!(route_bullet("SI"), "SI");
+        assert_eq!(route_bullet("SS"), "SS");
+    }
+
+    #[test]
+    fn test_is_express() {
+        assert!(is_express("6X"));
+        assert!(is_express(" FX "));
+        assert!(!is_express("6"));
+        assert!(!is_express("SIR"));
+        assert!(!is_express("SS"));
+        assert!(!is_express("X"));
+    }
+
+    #[test]
+    fn test_merge_lines_replaces_by_id() {
+        let mut l_train = *line_info("L").unwrap();
+        l_train.color = "#123456";
+        let mut new_line = l_train;
+        new_line.id = "T";
+
+        let table = merge_lines(&[l_train, new_line]);
+
+        assert_eq!(table.len(), LINES.len() + 1);
+        let l_entries: Vec<_> = table.iter().filter(|line| line.id == "L").collect();
+        assert_eq!(l_entries, [&l_train]);
+        assert_eq!(table.last(), Some(&new_line));
+    }
+
+    #[test]
+    fn test_is_hex_color() {
+        assert!(is_hex_color("#FCCC0A"));
+        assert!(is_hex_color("#a7a9ac"));
+        assert!(LINES.iter().all(|line| is_hex_color(line.color)));
+        assert!(!is_hex_color("FCCC0A"));
+        assert!(!is_hex_color("#FCC"));
+        assert!(!is_hex_color("#GGGGGG"));
+        assert!(!is_hex_color("#FCCC0A0"));
+    }
+
+    #[test]
+    fn test_known_routes_match_lines() {
+        let known = known_routes();
+        assert_eq!(known.len(), LINES.len());
+        assert!(known.contains("SI"));
+        assert!(!known.contains("6X"));
+    }
+
+    #[test]
+    fn test_line_ids_unique() {
+        for (i, line) in LINES.iter().enumerate() {
+            assert!(
+                LINES[i + 1..].iter().all(|other| other.id != line.id),
+                "Duplicate line {}",
+                line.id
+            );
+        }
+    }
+}
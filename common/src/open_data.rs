@@ -0,0 +1,41 @@
+/// NY Open Data endpoint listing every subway station
+pub const STATIONS_API_URL: &str = "https://data.ny.gov/resource/39hk-dx4f.json";
+
+/// Rows requested per page; SoQL returns at most 1000 rows unless `$limit` is set
+pub const STATIONS_PAGE_SIZE: usize = 1000;
+
+/// URL of the page of `limit` rows starting at row `offset`
+///
+/// Rows are ordered by their internal ID so consecutive pages neither overlap
+/// nor skip rows.
+pub fn page_url(base_url: &str, limit: usize, offset: usize) -> String {
+    format!(
+        "{}?$limit={}&$offset={}&$order=:id",
+        base_url, limit, offset
+    )
+}
+
+/// Whether a page of `rows` rows, requested `limit` at a time, was the last one
+pub fn is_last_page(rows: usize, limit: usize) -> bool {
+    rows < limit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_url() {
+        assert_eq!(
+            page_url(STATIONS_API_URL, 1000, 2000),
+            "https://data.ny.gov/resource/39hk-dx4f.json?$limit=1000&$offset=2000&$order=:id"
+        );
+    }
+
+    #[test]
+    fn test_is_last_page() {
+        assert!(!is_last_page(1000, 1000));
+        assert!(is_last_page(999, 1000));
+        assert!(is_last_page(0, 1000));
+    }
+}
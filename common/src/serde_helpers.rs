@@ -0,0 +1,189 @@
+// common/src/serde_helpers.rs
+use serde::{de, Deserialize, Deserializer};
+
+/// A numeric field that upstream APIs send either as a JSON number or a string
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StringOrNumber {
+    Number(f64),
+    String(String),
+}
+
+impl StringOrNumber {
+    fn into_f64<E: de::Error>(self) -> Result<f64, E> {
+        match self {
+            Self::Number(n) => Ok(n),
+            Self::String(s) => s
+                .trim()
+                .parse()
+                .map_err(|_| E::custom(format!("invalid number: {:?}", s))),
+        }
+    }
+}
+
+/// Deserializes `1.5` or `"1.5"` into an `f64`
+///
+/// Use with `#[serde(deserialize_with = "string_or_number_to_f64")]`.
+pub fn string_or_number_to_f64<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    StringOrNumber::deserialize(deserializer)?.into_f64()
+}
+
+/// Deserializes `1.5`, `"1.5"`, `null` or `""` into an `Option<f64>`
+///
+/// Use with `#[serde(default, deserialize_with = "opt_string_to_f64")]` so a
+/// missing field is also `None`.
+pub fn opt_string_to_f64<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<StringOrNumber>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(StringOrNumber::String(s)) if s.trim().is_empty() => Ok(None),
+        Some(value) => value.into_f64().map(Some),
+    }
+}
+
+/// Serde for `DateTime<Utc>` as RFC 3339 in UTC, e.g. `2024-01-01T12:00:00Z`
+///
+/// Use with `#[serde(with = "rfc3339")]`, or `rfc3339::option` for an
+/// `Option<DateTime<Utc>>`. Fractional seconds are written only when present.
+/// Unix-second fields stay plain integers and don't use this.
+pub mod rfc3339 {
+    use chrono::{DateTime, ParseError, SecondsFormat, Utc};
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    fn format(value: &DateTime<Utc>) -> String {
+        value.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+    }
+
+    fn parse(value: &str) -> Result<DateTime<Utc>, ParseError> {
+        DateTime::parse_from_rfc3339(value.trim()).map(|dt| dt.with_timezone(&Utc))
+    }
+
+    pub fn serialize<S: Serializer>(
+        value: &DateTime<Utc>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format(value))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        parse(&value)
+            .map_err(|e| de::Error::custom(format!("invalid RFC 3339 time {:?}: {}", value, e)))
+    }
+
+    /// The same format for an optional time, `null` when absent
+    ///
+    /// Add `#[serde(default)]` so a missing field is also `None`.
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(
+            value: &Option<DateTime<Utc>>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match value {
+                Some(value) => serializer.serialize_some(&format(value)),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            Option::<String>::deserialize(deserializer)?
+                .map(|value| {
+                    parse(&value).map_err(|e| {
+                        de::Error::custom(format!("invalid RFC 3339 time {:?}: {}", value, e))
+                    })
+                })
+                .transpose()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, TimeZone, Utc};
+    use serde::Serialize;
+
+    #[derive(Debug, Deserialize)]
+    struct Reading {
+        #[serde(deserialize_with = "string_or_number_to_f64")]
+        value: f64,
+        #[serde(default, deserialize_with = "opt_string_to_f64")]
+        optional: Option<f64>,
+    }
+
+    fn parse(json: &str) -> Result<Reading, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    #[test]
+    fn test_accepts_strings_and_numbers() {
+        let reading = parse(r#"{"value": "1.5", "optional": 1.5}"#).unwrap();
+        assert_eq!(reading.value, 1.5);
+        assert_eq!(reading.optional, Some(1.5));
+
+        let reading = parse(r#"{"value": 1.5, "optional": " 2.5 "}"#).unwrap();
+        assert_eq!(reading.value, 1.5);
+        assert_eq!(reading.optional, Some(2.5));
+    }
+
+    #[test]
+    fn test_optional_accepts_null_and_missing() {
+        assert_eq!(
+            parse(r#"{"value": 1, "optional": null}"#).unwrap().optional,
+            None
+        );
+        assert_eq!(
+            parse(r#"{"value": 1, "optional": ""}"#).unwrap().optional,
+            None
+        );
+        assert_eq!(parse(r#"{"value": 1}"#).unwrap().optional, None);
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Event {
+        #[serde(with = "rfc3339")]
+        at: DateTime<Utc>,
+        #[serde(default, with = "rfc3339::option")]
+        until: Option<DateTime<Utc>>,
+    }
+
+    #[test]
+    fn test_rfc3339_round_trip() {
+        let event = Event {
+            at: Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap(),
+            until: None,
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "at": "2024-01-01T12:00:00Z", "until": null })
+        );
+        assert_eq!(serde_json::from_value::<Event>(json).unwrap(), event);
+
+        // Offsets are normalized to UTC
+        let parsed: Event = serde_json::from_str(r#"{"at": "2024-01-01T07:00:00-05:00"}"#).unwrap();
+        assert_eq!(parsed.at, event.at);
+        assert!(serde_json::from_str::<Event>(r#"{"at": "yesterday"}"#).is_err());
+    }
+
+    #[test]
+    fn test_rejects_garbage() {
+        assert!(parse(r#"{"value": "abc"}"#).is_err());
+        assert!(parse(r#"{"value": null}"#).is_err());
+        assert!(parse(r#"{"value": true}"#).is_err());
+        assert!(parse(r#"{"value": 1, "optional": "n/a"}"#).is_err());
+    }
+}
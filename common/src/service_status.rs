@@ -0,0 +1,58 @@
+// common/src/service_status.rs
+
+/// Rider-facing condition of a line, ordered from least to most disruptive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ServiceStatus {
+    GoodService,
+    /// Planned reroutes or schedule changes, such as weekend diversions
+    PlannedWork,
+    Delays,
+}
+
+impl ServiceStatus {
+    /// Every status, least disruptive first
+    pub const ALL: [ServiceStatus; 3] = [
+        ServiceStatus::GoodService,
+        ServiceStatus::PlannedWork,
+        ServiceStatus::Delays,
+    ];
+
+    /// Label stored in `SubwayStatus::status`, e.g. "Planned Work"
+    pub fn label(self) -> &'static str {
+        match self {
+            ServiceStatus::GoodService => "Good Service",
+            ServiceStatus::PlannedWork => "Planned Work",
+            ServiceStatus::Delays => "Delays",
+        }
+    }
+
+    /// Parses a label produced by [`ServiceStatus::label`]
+    pub fn from_label(label: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|status| status.label() == label)
+    }
+
+    /// Whether the status sets `SubwayStatus::delays`; planned work doesn't
+    pub fn has_delays(self) -> bool {
+        self == ServiceStatus::Delays
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels_round_trip() {
+        for status in ServiceStatus::ALL {
+            assert_eq!(ServiceStatus::from_label(status.label()), Some(status));
+        }
+        assert_eq!(ServiceStatus::from_label("Suspended"), None);
+    }
+
+    #[test]
+    fn test_only_delays_are_delays() {
+        assert!(ServiceStatus::Delays.has_delays());
+        assert!(!ServiceStatus::PlannedWork.has_delays());
+        assert!(ServiceStatus::PlannedWork < ServiceStatus::Delays);
+    }
+}
@@ -0,0 +1,428 @@
+// common/src/trains.rs
+use crate::geohash::{self, GEOHASH_PRECISION};
+use crate::geojson::{GeoJsonFeature, GeoJsonGeometry, GeoJsonProperties};
+use crate::lines::{route_bullet, route_color, text_color_for_line, EXPRESS_MARKER};
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// Latitudes inside the five boroughs, with some margin
+pub const NYC_LATITUDES: RangeInclusive<f64> = 40.4..=41.0;
+
+/// Longitudes inside the five boroughs, with some margin
+pub const NYC_LONGITUDES: RangeInclusive<f64> = -74.3..=-73.6;
+
+/// Whether `(latitude, longitude)` falls within [`NYC_LATITUDES`] and [`NYC_LONGITUDES`]
+///
+/// Catches placeholder `(0, 0)` and swapped or garbled coordinates, which would
+/// put stations and trains in the Atlantic.
+pub fn is_valid_nyc_coordinate(latitude: f64, longitude: f64) -> bool {
+    NYC_LATITUDES.contains(&latitude) && NYC_LONGITUDES.contains(&longitude)
+}
+
+/// Mean Earth radius used by [`haversine_meters`]
+const EARTH_RADIUS_METERS: f64 = 6_371_000.0;
+
+/// Segments shorter than this many meters are a train standing still, e.g. in a yard
+pub const IDLE_SEGMENT_METERS: f64 = 5.0;
+
+/// Great-circle distance in meters between two `(latitude, longitude)` points
+pub fn haversine_meters(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (lat1, lat2) = (from.0.to_radians(), to.0.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (to.1 - from.1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_METERS * a.sqrt().asin()
+}
+
+/// Represents the current position of a subway train
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrainPosition {
+    /// GTFS trip identifier
+    pub trip_id: String,
+    /// Subway route identifier (e.g., "A", "1"), normalized to a line in
+    /// [`crate::lines::LINES`] when `is_known`
+    pub route_id: String,
+    /// Whether `route_id` is a known line; unknown routes are passed through as
+    /// the feed reported them so the client can choose to hide them
+    #[serde(default = "known_by_default")]
+    pub is_known: bool,
+    /// Whether the feed reported an express variant of the line (e.g. `6X`),
+    /// which `route_id` no longer shows once normalized
+    #[serde(default)]
+    pub express: bool,
+    /// The previous stop location
+    pub from_stop: StopLocation,
+    /// The next stop location
+    pub to_stop: StopLocation,
+    /// Progress between stops (0.0 to 1.0)
+    pub progress: f64,
+    /// Unix timestamp in seconds when train departed from_stop, serialized as an integer
+    pub start_time: i64,
+    /// Estimated Unix timestamp in seconds when train will arrive at to_stop,
+    /// serialized as an integer
+    pub end_time: i64,
+    /// Direction of travel, when the trip ID encodes one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<Direction>,
+    /// GTFS stop identifier of the last stop in the trip's update
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination_stop_id: Option<String>,
+    /// Name of the destination stop, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination_name: Option<String>,
+    /// How crowded the train is, when the feed reports it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub occupancy: Option<OccupancyStatus>,
+    /// Upcoming stop IDs after `to_stop`, in order and capped in length
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remaining_stops: Vec<String>,
+    /// Approximate seconds until the train reaches its destination, when every
+    /// remaining stop has a predicted time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eta_to_destination_secs: Option<i64>,
+    /// Average speed over the current segment (see [`TrainPosition::speed_kmh`]),
+    /// so clients can tell fast trains from slow ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed_kmh: Option<f64>,
+}
+
+/// Positions serialized before `is_known` existed are assumed known
+fn known_by_default() -> bool {
+    true
+}
+
+/// Passenger load of a vehicle, mirroring GTFS-realtime's `OccupancyStatus`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OccupancyStatus {
+    /// Few or no passengers onboard
+    Empty,
+    /// A large number of seats available
+    ManySeatsAvailable,
+    /// A small number of seats available
+    FewSeatsAvailable,
+    /// Standing passengers only
+    StandingRoomOnly,
+    /// Standing passengers only, with limited space
+    CrushedStandingRoomOnly,
+    /// Full, though possibly still boarding
+    Full,
+    /// Temporarily not accepting passengers
+    NotAcceptingPassengers,
+    /// The feed has no occupancy data for the vehicle
+    NoDataAvailable,
+    /// Never accepts passengers (e.g. maintenance trains)
+    NotBoardable,
+}
+
+/// Direction of travel of a subway train
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Northbound (uptown / toward the Bronx)
+    North,
+    /// Southbound (downtown / toward Brooklyn)
+    South,
+}
+
+/// Represents a subway stop location
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StopLocation {
+    /// GTFS stop identifier
+    pub stop_id: String,
+    /// Human-readable station name (e.g. "Bedford Av"), when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_name: Option<String>,
+    /// Stop latitude coordinate
+    pub latitude: f64,
+    /// Stop longitude coordinate
+    pub longitude: f64,
+    /// Geohash of the coordinates with [`GEOHASH_PRECISION`] characters, for
+    /// grouping nearby stops by prefix; empty when deserialized without one
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub geohash: String,
+}
+
+impl StopLocation {
+    /// Creates a stop at `(latitude, longitude)`, computing its geohash
+    pub fn new(stop_id: String, stop_name: Option<String>, latitude: f64, longitude: f64) -> Self {
+        Self {
+            stop_id,
+            stop_name,
+            latitude,
+            longitude,
+            geohash: geohash::encode(latitude, longitude, GEOHASH_PRECISION),
+        }
+    }
+
+    /// Whether the stop's coordinates are inside NYC (see [`is_valid_nyc_coordinate`])
+    pub fn is_valid_nyc(&self) -> bool {
+        is_valid_nyc_coordinate(self.latitude, self.longitude)
+    }
+
+    /// Distance in meters to `other` (see [`haversine_meters`])
+    pub fn distance_meters(&self, other: &StopLocation) -> f64 {
+        haversine_meters(
+            (self.latitude, self.longitude),
+            (other.latitude, other.longitude),
+        )
+    }
+}
+
+impl TrainPosition {
+    /// Whether the train isn't really moving: its segment starts and ends at the
+    /// same stop, or spans less than [`IDLE_SEGMENT_METERS`]
+    pub fn is_idle(&self) -> bool {
+        self.from_stop.stop_id == self.to_stop.stop_id
+            || self.from_stop.distance_meters(&self.to_stop) < IDLE_SEGMENT_METERS
+    }
+
+    /// Straight-line distance in meters between `from_stop` and `to_stop`
+    pub fn distance_m(&self) -> f64 {
+        self.from_stop.distance_meters(&self.to_stop)
+    }
+
+    /// Average speed in km/h needed to cover [`Self::distance_m`] between
+    /// `start_time` and `end_time`
+    ///
+    /// # Returns
+    /// - `None` if the segment takes no time, or ends before it starts
+    pub fn speed_kmh(&self) -> Option<f64> {
+        let duration_secs = self.end_time - self.start_time;
+        if duration_secs <= 0 {
+            return None;
+        }
+        Some(self.distance_m() / duration_secs as f64 * 3.6)
+    }
+
+    /// Popup label, e.g. "L train to 8 Av: Bedford Av → 1 Av"
+    ///
+    /// Stops without a name are shown by their stop ID, and the destination is
+    /// omitted when unknown.
+    pub fn label(&self) -> String {
+        let name = |stop: &StopLocation| {
+            stop.stop_name
+                .clone()
+                .unwrap_or_else(|| stop.stop_id.clone())
+        };
+        let train = match &self.destination_name {
+            Some(destination) => format!("{} train to {}", self.route_id, destination),
+            None => format!("{} train", self.route_id),
+        };
+        format!(
+            "{}: {} → {}",
+            train,
+            name(&self.from_stop),
+            name(&self.to_stop)
+        )
+    }
+
+    /// `[longitude, latitude]` of the point `progress` of the way between the two stops
+    pub fn point_at(&self, progress: f64) -> [f64; 2] {
+        let (from, to) = (&self.from_stop, &self.to_stop);
+        [
+            from.longitude + (to.longitude - from.longitude) * progress,
+            from.latitude + (to.latitude - from.latitude) * progress,
+        ]
+    }
+
+    /// Glyph drawn on the train's bullet (see [`route_bullet`]), with
+    /// [`EXPRESS_MARKER`] for an express train
+    pub fn bullet(&self) -> String {
+        let mut bullet = route_bullet(&self.route_id);
+        if self.express && !bullet.ends_with(EXPRESS_MARKER) {
+            bullet.push(EXPRESS_MARKER);
+        }
+        bullet
+    }
+
+    /// Map feature at the train's reported progress
+    pub fn to_geojson_feature(&self) -> GeoJsonFeature {
+        self.to_geojson_feature_at(self.progress)
+    }
+
+    /// Map feature at `progress`, for clients animating between updates
+    pub fn to_geojson_feature_at(&self, progress: f64) -> GeoJsonFeature {
+        let [longitude, latitude] = self.point_at(progress);
+        GeoJsonFeature {
+            feature_type: "Feature".to_string(),
+            properties: GeoJsonProperties {
+                name: self.label(),
+                lines: self.route_id.clone(),
+                division: String::new(),
+                borough: String::new(),
+                ada: false,
+                ada_notes: String::new(),
+                north_direction: String::new(),
+                south_direction: String::new(),
+                color: route_color(&self.route_id).to_string(),
+                bullet: self.bullet(),
+                progress: Some(progress),
+                text_color: text_color_for_line(&self.route_id).to_string(),
+                geohash: geohash::encode(latitude, longitude, GEOHASH_PRECISION),
+            },
+            geometry: GeoJsonGeometry::point([longitude, latitude]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geojson::GeoJsonCoordinates;
+
+    fn train() -> TrainPosition {
+        TrainPosition {
+            trip_id: "123".to_string(),
+            route_id: "L".to_string(),
+            is_known: true,
+            express: false,
+            from_stop: StopLocation::new("L06".to_string(), Some("1 Av".to_string()), 40.7, -73.9),
+            to_stop: StopLocation::new("L08".to_string(), None, 40.71, -73.92),
+            progress: 0.5,
+            start_time: 1000,
+            end_time: 2000,
+            direction: None,
+            destination_stop_id: None,
+            destination_name: None,
+            occupancy: None,
+            remaining_stops: Vec::new(),
+            eta_to_destination_secs: None,
+            speed_kmh: None,
+        }
+    }
+
+    #[test]
+    fn test_nyc_coordinate_validation() {
+        let stop =
+            |latitude, longitude| StopLocation::new("L08N".to_string(), None, latitude, longitude);
+        assert!(stop(40.717304, -73.956872).is_valid_nyc());
+        assert!(!stop(0.0, 0.0).is_valid_nyc());
+        // Swapped latitude and longitude
+        assert!(!stop(-73.956872, 40.717304).is_valid_nyc());
+    }
+
+    #[test]
+    fn test_stop_location_geohash() {
+        let bedford = StopLocation::new("L08N".to_string(), None, 40.717304, -73.956872);
+        assert_eq!(bedford.geohash.len(), GEOHASH_PRECISION);
+        assert!(bedford.geohash.starts_with("dr5r"), "{}", bedford.geohash);
+        // Platforms of one station share the cell
+        let southbound = StopLocation::new("L08S".to_string(), None, 40.717304, -73.956872);
+        assert_eq!(southbound.geohash, bedford.geohash);
+
+        let json = serde_json::to_value(&bedford).unwrap();
+        assert_eq!(json["geohash"], bedford.geohash.as_str());
+        let legacy: StopLocation = serde_json::from_value(serde_json::json!({
+            "stop_id": "L08N",
+            "latitude": 40.717304,
+            "longitude": -73.956872,
+        }))
+        .unwrap();
+        assert_eq!(legacy.geohash, "");
+    }
+
+    #[test]
+    fn test_haversine_meters() {
+        // Bedford Av to 1 Av on the L is a little over 2.5 km
+        let distance = haversine_meters((40.717304, -73.956872), (40.730953, -73.981628));
+        assert!((2_500.0..2_600.0).contains(&distance), "{}", distance);
+        assert_eq!(haversine_meters((40.7, -73.9), (40.7, -73.9)), 0.0);
+    }
+
+    #[test]
+    fn test_distance_and_speed() {
+        // A hundredth of a degree of latitude is about 1,112 m
+        let mut train = train();
+        train.to_stop.latitude = 40.71;
+        train.to_stop.longitude = train.from_stop.longitude;
+        train.end_time = train.start_time + 100;
+        assert!(
+            (train.distance_m() - 1_111.95).abs() < 0.01,
+            "{}",
+            train.distance_m()
+        );
+        let speed = train.speed_kmh().unwrap();
+        assert!((speed - 40.03).abs() < 0.01, "{}", speed);
+
+        train.end_time = train.start_time;
+        assert_eq!(train.speed_kmh(), None);
+        train.end_time = train.start_time - 10;
+        assert_eq!(train.speed_kmh(), None);
+    }
+
+    #[test]
+    fn test_idle_segments() {
+        assert!(!train().is_idle());
+
+        let mut standing = train();
+        standing.to_stop.latitude = standing.from_stop.latitude;
+        standing.to_stop.longitude = standing.from_stop.longitude;
+        assert!(standing.is_idle());
+
+        let mut same_stop = train();
+        same_stop.to_stop.stop_id = same_stop.from_stop.stop_id.clone();
+        assert!(same_stop.is_idle());
+    }
+
+    #[test]
+    fn test_times_serialize_as_unix_seconds() {
+        let json = serde_json::to_value(train()).unwrap();
+        assert_eq!(json["start_time"], 1000);
+        assert!(json["start_time"].is_i64());
+        assert!(json["end_time"].is_i64());
+    }
+
+    #[test]
+    fn test_label_names_destination_when_known() {
+        let mut train = train();
+        assert_eq!(train.label(), "L train: 1 Av → L08");
+
+        train.destination_name = Some("8 Av".to_string());
+        assert_eq!(train.label(), "L train to 8 Av: 1 Av → L08");
+    }
+
+    #[test]
+    fn test_geojson_feature_at_interpolated_point() {
+        let train = train();
+        let feature = train.to_geojson_feature();
+
+        assert_eq!(feature.feature_type, "Feature");
+        assert_eq!(feature.properties.lines, "L");
+        assert_eq!(feature.properties.color, "#A7A9AC");
+        assert_eq!(feature.properties.bullet, "L");
+        assert_eq!(feature.properties.text_color, "#000000");
+        assert_eq!(feature.properties.progress, Some(0.5));
+        assert_eq!(
+            feature.properties.geohash,
+            geohash::encode(40.705, -73.91, GEOHASH_PRECISION)
+        );
+        assert_eq!(
+            train.to_geojson_feature_at(0.75).properties.progress,
+            Some(0.75)
+        );
+        let GeoJsonCoordinates::Point([lon, lat]) = feature.geometry.coordinates else {
+            panic!("train features are points");
+        };
+        assert!((lon - -73.91).abs() < 1e-9, "{}", lon);
+        assert!((lat - 40.705).abs() < 1e-9, "{}", lat);
+
+        assert_eq!(train.point_at(0.0), [-73.9, 40.7]);
+        assert_eq!(train.point_at(1.0), [-73.92, 40.71]);
+    }
+
+    #[test]
+    fn test_express_feature_marks_bullet() {
+        let mut train = train();
+        train.route_id = "6".to_string();
+        train.express = true;
+        let feature = train.to_geojson_feature();
+
+        assert_eq!(feature.properties.lines, "6");
+        assert_eq!(feature.properties.bullet, "6◆");
+        assert_eq!(feature.properties.color, "#00933C");
+
+        train.express = false;
+        assert_eq!(train.bullet(), "6");
+    }
+}
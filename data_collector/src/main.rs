@@ -7,11 +7,34 @@
 //! The collector runs as a background process that:
 //! - Connects to a PostgreSQL database using connection details from environment variables
 //! - Creates necessary database tables and indices if they don't exist
-//! - Polls subway status data at regular intervals (currently every 30 seconds)
+//! - Runs every [`DataCollector`] in its own task on its own interval (subway status
+//!   every 5 seconds, train positions every 30)
 //! - Stores status updates in the database
+//! - Backs off exponentially, with jitter, while a collector keeps failing
+//! - Prunes status history older than `STATUS_RETENTION_DAYS` once an hour
+//! - On `SIGTERM` or Ctrl-C, lets any in-flight collection finish, starts no new
+//!   one, closes the database pool and exits
+//!
+//! New data sources (bikes, air quality, 311) are added by implementing
+//! [`DataCollector`] and adding an instance to the list in `main`.
+//!
+//! # Arguments
+//! - `--once`: Collect and prune once, then exit (non-zero on failure) instead of
+//!   looping, for cron-driven deployments
 //!
 //! # Environment Variables
 //! - `DATABASE_URL`: PostgreSQL connection string (required)
+//! - `FEEDS`: Comma-separated feed keys (e.g. `ace,l`) limiting which lines are collected (optional, default all)
+//! - `COLLECTOR_SEED`: Integer seed making the generated sample statuses reproducible (optional)
+//! - `STATUS_RETENTION`: `history` to append each changed status (default) or `latest` to keep one row per line
+//! - `STATUS_HISTORY`: With `latest` retention, `true` also appends every status to `subway_status_history`
+//! - `STATUS_RETENTION_DAYS`: Days of status history kept before hourly pruning deletes it (default 7)
+//! - `STATIONS_SOURCE`, `STATIONS_FILE`, `FEED_CONCURRENCY`, `INCLUDE_IDLE_TRAINS`: How train
+//!   positions are fetched, as for the server; train positions aren't collected if
+//!   the stations can't be loaded
+//! - `LOG_FORMAT`: `pretty` for readable logs (default) or `json` for one JSON object per line
+//! - `LINES_FILE`: JSON array of lines whose colors and names replace the built-in ones with the same ID (optional)
+//! - `RUST_LOG`: Log verbosity (default `info`)
 //!
 //! # Database Schema
 //! The collector manages the `subway_status` table with the following structure:
@@ -20,65 +43,64 @@
 //! - `status`: Current service status
 //! - `timestamp`: When the status was recorded
 //! - `delays`: Boolean indicating if there are delays
+//! - `changed_at`: When `status` or `delays` last changed for the line (nullable,
+//!   added to existing tables on startup)
+//!
+//! Appropriate indices are created for efficient querying by timestamp and line. With
+//! `latest` retention `line` is also unique, and `subway_status_history` (same columns)
+//! keeps the full record when enabled. See [`nyc_pulse_backend::status_store`].
 //!
-//! Appropriate indices are created for efficient querying by timestamp and line.
+//! It also manages `train_positions`, holding the last hour of train position
+//! snapshots, one JSON position per row with its `recorded_at` time. See
+//! [`nyc_pulse_backend::position_store`].
 
+use async_trait::async_trait;
 use dotenv::dotenv;
 use nyc_pulse_backend as backend;
-use rand::Rng;
+use nyc_pulse_backend::config::Config;
+use nyc_pulse_backend::feeds::FeedInfo;
+use nyc_pulse_backend::gtfs::GtfsHandler;
+use nyc_pulse_backend::position_store::{self, PositionSnapshot};
+use nyc_pulse_backend::status_store::{self, StatusRetention};
+use nyc_pulse_backend::ServiceStatus;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use sqlx::PgPool;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use tokio::sync::watch;
 use tokio::time;
+use tracing::{error, info, warn};
 
-/// Mapping of MTA GTFS feed URLs to the subway lines they contain
-///
-/// Each tuple contains:
-/// - The GTFS feed URL for a group of subway lines
-/// - Array of line identifiers included in that feed
-const FEED_URLS: [(&str, &[&str]); 8] = [
-    (
-        "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-ace",
-        &["A", "C", "E", "S"],
-    ),
-    (
-        "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-bdfm",
-        &["B", "D", "F", "M"],
-    ),
-    (
-        "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-g",
-        &["G"],
-    ),
-    (
-        "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-jz",
-        &["J", "Z"],
-    ),
-    (
-        "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-nqrw",
-        &["N", "Q", "R", "W"],
-    ),
-    (
-        "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-l",
-        &["L"],
-    ),
-    (
-        "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs",
-        &["1", "2", "3", "4", "5", "6", "7"],
-    ),
-    (
-        "https://api-endpoint.mta.info/Dataservice/mtagtfsfeeds/nyct%2Fgtfs-si",
-        &["SI"],
-    ),
-];
-
-/// Main collector struct that handles database connections and data collection
+/// A source of data stored on a fixed interval, run by [`run_collector`]
+#[async_trait]
+trait DataCollector: Send + Sync {
+    /// Short name used in logs, e.g. "subway status"
+    fn name(&self) -> &str;
+
+    /// How often [`DataCollector::collect`] runs while it keeps succeeding
+    fn interval(&self) -> Duration {
+        COLLECT_INTERVAL
+    }
+
+    /// Collects one round of data and stores it in `db`
+    async fn collect(&self, db: &PgPool) -> backend::Result<()>;
+}
+
+/// Main collector struct that handles the database connection and pruning
 #[derive(Clone)]
 struct Collector {
     /// PostgreSQL connection pool
     db: PgPool,
+    /// How past statuses are retained
+    retention: StatusRetention,
+    /// Days of history kept by [`Collector::prune`]
+    retention_days: i32,
 }
 
 impl Collector {
-    /// Creates a new Collector instance
+    /// Creates a new Collector instance from a validated `config`
     ///
     /// Initializes database connection and creates required tables/indices
     ///
@@ -86,170 +108,660 @@ impl Collector {
     /// - `Result<Self>` - New collector instance or error if initialization fails
     ///
     /// # Errors
-    /// - If DATABASE_URL environment variable is not set
     /// - If database connection fails
     /// - If table/index creation fails
-    async fn new() -> backend::Result<Self> {
-        dotenv().ok();
+    async fn new(config: &Config) -> backend::Result<Self> {
+        let retention = config.status_retention;
 
-        let database_url = std::env::var("DATABASE_URL")
-            .map_err(|_| backend::Error::Environment("DATABASE_URL not set".into()))?;
-
-        let db = PgPool::connect(&database_url)
+        let db = PgPool::connect(&config.database_url)
             .await
             .expect("Failed to connect to database");
 
-        // Initialize table
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS subway_status (
-                id SERIAL PRIMARY KEY,
-                line VARCHAR(10) NOT NULL,
-                status VARCHAR(100) NOT NULL,
-                timestamp TIMESTAMPTZ NOT NULL,
-                delays BOOLEAN NOT NULL
-            )
-            "#,
-        )
-        .execute(&db)
-        .await?;
-
-        // Create indices
-        sqlx::query(
-            "CREATE INDEX IF NOT EXISTS idx_subway_status_timestamp ON subway_status(timestamp DESC)"
-        )
-        .execute(&db)
-        .await?;
-
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_subway_status_line ON subway_status(line)")
-            .execute(&db)
-            .await?;
-
-        Ok(Self { db })
-    }
-
-    /// Collects current subway status for all lines
+        status_store::ensure_schema(&db, retention).await?;
+        position_store::ensure_schema(&db).await?;
+
+        Ok(Self {
+            db,
+            retention,
+            retention_days: config.retention_days,
+        })
+    }
+
+    /// Deletes status history older than the retention window
     ///
-    /// Currently generates sample data for development. In production, this would
-    /// fetch real status data from the MTA's GTFS feeds.
+    /// # Errors
+    /// - If the delete fails
+    async fn prune(&self) -> backend::Result<()> {
+        let deleted =
+            status_store::prune_statuses(&self.db, self.retention, self.retention_days).await?;
+        info!(
+            "Pruned {} status rows older than {} days",
+            deleted, self.retention_days
+        );
+        Ok(())
+    }
+}
+
+/// Collects the status of every line in the selected feeds
+struct SubwayStatusCollector {
+    /// Feeds whose lines are collected
+    feeds: Vec<&'static FeedInfo>,
+    /// How past statuses are retained
+    retention: StatusRetention,
+    /// Source of randomness for the sample statuses (see [`rng_from_seed`])
+    rng: Mutex<StdRng>,
+    /// Handler fetching the subway alerts feed; needs no stations
+    gtfs: GtfsHandler,
+}
+
+impl SubwayStatusCollector {
+    /// Creates a collector for the feeds, retention and seed in `config`
+    fn new(config: &Config) -> Self {
+        Self {
+            feeds: config.feeds.clone(),
+            retention: config.status_retention,
+            rng: Mutex::new(rng_from_seed(config.collector_seed)),
+            gtfs: GtfsHandler::from_stop_locations(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl DataCollector for SubwayStatusCollector {
+    fn name(&self) -> &str {
+        "subway status"
+    }
+
+    /// Collects current subway status for all lines in the selected feeds
     ///
-    /// # Returns
-    /// - `Result<()>` - Success or database error
+    /// Sample statuses are generated for every line, then lines named by an alert
+    /// in effect on the MTA's subway alerts feed take the alert's status (see
+    /// [`overlay_alert_statuses`]). If the alerts feed can't be fetched the
+    /// sample statuses are stored alone.
     ///
     /// # Errors
     /// - If database insert fails
-    async fn collect_subway_status(&self) -> backend::Result<()> {
-        println!("Collecting subway status...");
-        let mut rng = rand::thread_rng();
-
-        // Generate some sample statuses for development
-        for (_, lines) in FEED_URLS.iter() {
-            for &line in *lines {
-                // Randomly decide if there are delays (20% chance)
-                let has_delays = rng.gen_bool(0.2);
-
-                let status = if has_delays { "Delays" } else { "Good Service" };
-
-                let data = backend::SubwayStatus {
-                    line: line.to_string(),
-                    status: status.to_string(),
-                    timestamp: chrono::Utc::now(),
-                    delays: has_delays,
-                };
-
-                sqlx::query!(
-                    r#"
-                    INSERT INTO subway_status (line, status, timestamp, delays)
-                    VALUES ($1, $2, $3, $4)
-                    "#,
-                    data.line,
-                    data.status,
-                    data.timestamp,
-                    data.delays
-                )
-                .execute(&self.db)
-                .await?;
-            }
+    async fn collect(&self, db: &PgPool) -> backend::Result<()> {
+        info!("Collecting subway status...");
+
+        let mut statuses = {
+            let mut rng = self.rng.lock().expect("sample rng lock poisoned");
+            sample_statuses(&self.feeds, &mut *rng)
+        };
+        match self.gtfs.get_line_statuses().await {
+            Ok(alert_statuses) => overlay_alert_statuses(&mut statuses, &alert_statuses),
+            Err(e) => warn!("Storing sample statuses only, alerts unavailable: {}", e),
         }
+        status_store::store_statuses(db, &statuses, self.retention).await?;
 
-        println!("Updated subway status");
+        info!("Updated subway status");
         Ok(())
     }
 }
 
+/// How often train positions are collected (see [`TrainPositionCollector`])
+///
+/// Slower than [`COLLECT_INTERVAL`]: every round fetches each GTFS feed.
+const TRAIN_POSITION_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Stores a snapshot of live train positions for the server's `/api/trains/latest`
+struct TrainPositionCollector {
+    /// Handler fetching the selected feeds, with the station cache loaded
+    gtfs: GtfsHandler,
+}
+
+impl TrainPositionCollector {
+    /// Creates a collector for the feeds and station source in `config`
+    ///
+    /// # Errors
+    /// - If the stations can't be loaded (see [`GtfsHandler::new`])
+    async fn new(config: &Config) -> backend::Result<Self> {
+        let gtfs = GtfsHandler::new(config.feeds.clone(), config.station_source.clone())
+            .await?
+            .with_idle_trains(config.include_idle_trains)
+            .with_feed_concurrency(config.feed_concurrency);
+        Ok(Self { gtfs })
+    }
+}
+
+#[async_trait]
+impl DataCollector for TrainPositionCollector {
+    fn name(&self) -> &str {
+        "train positions"
+    }
+
+    fn interval(&self) -> Duration {
+        TRAIN_POSITION_INTERVAL
+    }
+
+    /// Fetches live train positions and stores them as the newest snapshot
+    ///
+    /// Feeds that fail are skipped; the previous snapshot is kept when all do.
+    ///
+    /// # Errors
+    /// - `Error::FeedsUnavailable` if every feed failed
+    /// - If the snapshot can't be stored
+    async fn collect(&self, db: &PgPool) -> backend::Result<()> {
+        let recorded_at = chrono::Utc::now();
+        // Feed health is only reported by the server
+        let trains = self.gtfs.get_train_positions(&Default::default()).await;
+        if trains.all_feeds_failed() {
+            return Err(backend::Error::FeedsUnavailable(trains.feeds_total));
+        }
+
+        let snapshot = PositionSnapshot {
+            recorded_at,
+            positions: trains.positions,
+        };
+        position_store::store_positions(db, &snapshot).await?;
+        info!("Stored {} train positions", snapshot.positions.len());
+        Ok(())
+    }
+}
+
+/// Runs `collector` against `db` every [`DataCollector::interval`] until `shutdown`
+///
+/// After a failed round the next one waits for the [`Backoff`] delay with
+/// jitter instead. A round already in progress when shutdown is signaled
+/// finishes first, so a write is never cut off halfway; no new round starts
+/// after it.
+async fn run_collector(
+    collector: Arc<dyn DataCollector>,
+    db: PgPool,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut interval = time::interval(collector.interval());
+    let mut backoff = Backoff::new(collector.interval());
+    // Separate from any collector rng so retries don't change seeded sample data
+    let mut jitter_rng = StdRng::from_entropy();
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.wait_for(|stop| *stop) => break,
+        }
+
+        match collector.collect(&db).await {
+            Ok(()) => backoff.record_success(),
+            Err(e) => {
+                let delay = with_jitter(backoff.record_failure(), &mut jitter_rng);
+                error!(
+                    "Error collecting {} ({} in a row), retrying in {:.1}s: {}",
+                    collector.name(),
+                    backoff.failures,
+                    delay.as_secs_f64(),
+                    e
+                );
+                tokio::select! {
+                    _ = time::sleep(delay) => {}
+                    _ = shutdown.wait_for(|stop| *stop) => break,
+                }
+                interval.reset_immediately();
+            }
+        }
+    }
+    info!("Stopped collecting {}", collector.name());
+}
+
+/// Resolves when the process is asked to stop, by `SIGTERM` or Ctrl-C
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// How often old status history is pruned, independent of the collection interval
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Default [`DataCollector::interval`], at which subway status is collected
+const COLLECT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Longest pause between collection attempts after repeated failures
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// Capped exponential backoff over consecutive collection failures
+///
+/// The first failure waits the collector's interval, and each further one
+/// doubles the wait up to [`MAX_BACKOFF`]. A success resets it.
+#[derive(Debug)]
+struct Backoff {
+    /// Pause after the first failure
+    base: Duration,
+    /// Consecutive failed attempts
+    failures: u32,
+}
+
+impl Backoff {
+    /// Creates a backoff whose first pause is `base`
+    fn new(base: Duration) -> Self {
+        Self { base, failures: 0 }
+    }
+
+    /// Records a successful attempt, returning to the normal cadence
+    fn record_success(&mut self) {
+        self.failures = 0;
+    }
+
+    /// Records a failed attempt and returns the pause before the next one
+    fn record_failure(&mut self) -> Duration {
+        self.failures = self.failures.saturating_add(1);
+        self.delay()
+    }
+
+    /// Pause before the next attempt, zero while no attempt has failed
+    fn delay(&self) -> Duration {
+        if self.failures == 0 {
+            return Duration::ZERO;
+        }
+        let factor = 2_u32.saturating_pow(self.failures - 1);
+        self.base.saturating_mul(factor).min(MAX_BACKOFF)
+    }
+}
+
+/// Picks a pause uniformly between half of `delay` and `delay`
+///
+/// Keeps several collectors that failed together from retrying in lockstep.
+fn with_jitter(delay: Duration, rng: &mut impl Rng) -> Duration {
+    delay.mul_f64(rng.gen_range(0.5..=1.0))
+}
+
+/// Generates a sample status for every line in `feeds`
+///
+/// Each line independently has a 20% chance of delays.
+fn sample_statuses(feeds: &[&FeedInfo], rng: &mut impl Rng) -> Vec<backend::SubwayStatus> {
+    let mut statuses = Vec::new();
+    for feed in feeds {
+        for &line in feed.lines {
+            let has_delays = rng.gen_bool(0.2);
+            let status = if has_delays { "Delays" } else { "Good Service" };
+
+            let timestamp = chrono::Utc::now();
+            statuses.push(backend::SubwayStatus {
+                line: line.to_string(),
+                status: status.to_string(),
+                timestamp,
+                delays: has_delays,
+                // Replaced by the last transition time when stored
+                changed_at: timestamp,
+            });
+        }
+    }
+    statuses
+}
+
+/// Replaces the status of every line in `alert_statuses` with its alert status
+///
+/// Lines without an alert keep their status. `delays` follows the new status, so
+/// planned work doesn't count as delays.
+fn overlay_alert_statuses(
+    statuses: &mut [backend::SubwayStatus],
+    alert_statuses: &BTreeMap<&str, ServiceStatus>,
+) {
+    for status in statuses {
+        if let Some(&alert_status) = alert_statuses.get(status.line.as_str()) {
+            status.status = alert_status.label().to_string();
+            status.delays = alert_status.has_delays();
+        }
+    }
+}
+
+/// Creates the sample-data rng, seeded from `seed` (`COLLECTOR_SEED`) when set
+///
+/// Without a seed the rng is seeded from system entropy, so every run differs.
+fn rng_from_seed(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+/// Command-line options of the collector binary
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Args {
+    /// Run every collector once and exit instead of looping (`--once`)
+    once: bool,
+}
+
+impl Args {
+    /// Parses the arguments following the program name
+    ///
+    /// # Errors
+    /// - If an argument isn't recognized, with a usage message
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        for arg in args {
+            match arg.as_str() {
+                "--once" => parsed.once = true,
+                _ => {
+                    return Err(format!(
+                        "Unknown argument: {}\nUsage: nyc-pulse-collector [--once]",
+                        arg
+                    ))
+                }
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// Runs every collector one time, then prunes old history
+///
+/// # Errors
+/// - The first collection or pruning error, after which nothing else runs
+async fn collect_once(
+    collectors: &[Box<dyn DataCollector>],
+    collector: &Collector,
+) -> backend::Result<()> {
+    for data_collector in collectors {
+        data_collector.collect(&collector.db).await?;
+        info!("Collected {}", data_collector.name());
+    }
+    collector.prune().await
+}
+
 /// Main entry point for the collector binary
 ///
-/// Creates a collector instance and runs an infinite loop collecting
-/// subway status data every 30 seconds.
+/// Runs every [`DataCollector`] in its own task (see [`run_collector`]), while
+/// a background task prunes old history every [`PRUNE_INTERVAL`]. On `SIGTERM`
+/// or Ctrl-C the in-flight rounds finish, the pool is closed and it exits
+/// cleanly (see [`shutdown_signal`]). With `--once`
+/// it runs [`collect_once`] instead and exits with status 1 if that fails.
+/// Exits with a summary of every problem if the environment is invalid (see
+/// [`Config::from_env`]), or with status 2 on unknown arguments.
 #[tokio::main]
 async fn main() -> backend::Result<()> {
-    let collector = Collector::new().await?;
+    let args = Args::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
+    dotenv().ok();
+    let config = Config::from_env().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    backend::logging::init(config.log_format);
+    backend::lines::install_lines(config.lines.clone());
 
-    // collect data every 5 seconds
-    let mut interval = time::interval(Duration::from_secs(5));
+    let collector = Collector::new(&config).await?;
+    let mut collectors: Vec<Box<dyn DataCollector>> =
+        vec![Box::new(SubwayStatusCollector::new(&config))];
+    match TrainPositionCollector::new(&config).await {
+        Ok(train_positions) => collectors.push(Box::new(train_positions)),
+        Err(e) => error!(
+            "Not collecting train positions, stations failed to load: {}",
+            e
+        ),
+    }
 
-    loop {
-        interval.tick().await;
+    if args.once {
+        if let Err(e) = collect_once(&collectors, &collector).await {
+            error!("One-shot collection failed: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Shutting down, finishing in-flight collection");
+        shutdown_tx.send_replace(true);
+    });
+
+    let pruner = collector.clone();
+    let mut pruner_shutdown = shutdown_rx.clone();
+    let pruner_task = tokio::spawn(async move {
+        let mut interval = time::interval(PRUNE_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = pruner_shutdown.wait_for(|stop| *stop) => break,
+            }
+            if let Err(e) = pruner.prune().await {
+                error!("Error pruning subway status history: {}", e);
+            }
+        }
+    });
 
-        if let Err(e) = collector.collect_subway_status().await {
-            eprintln!("Error collecting subway status: {}", e);
+    let tasks: Vec<_> = collectors
+        .into_iter()
+        .map(|data_collector| {
+            tokio::spawn(run_collector(
+                Arc::from(data_collector),
+                collector.db.clone(),
+                shutdown_rx.clone(),
+            ))
+        })
+        .collect();
+    for task in tasks {
+        if let Err(e) = task.await {
+            error!("Collector task stopped: {}", e);
         }
     }
+    if let Err(e) = pruner_task.await {
+        error!("Pruner task stopped: {}", e);
+    }
+    collector.db.close().await;
+    info!("Collector shut down");
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use nyc_pulse_backend::feeds::SUBWAY_FEEDS;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn delay_pattern(seed: u64) -> Vec<bool> {
+        let feeds: Vec<&FeedInfo> = SUBWAY_FEEDS.iter().collect();
+        let mut rng = StdRng::seed_from_u64(seed);
+        // Several rounds so a coincidental match between seeds is unlikely
+        (0..5)
+            .flat_map(|_| sample_statuses(&feeds, &mut rng))
+            .map(|status| status.delays)
+            .collect()
+    }
 
     #[test]
-    fn test_feed_urls_validity() {
-        for (url, lines) in FEED_URLS.iter() {
-            // Check URL format
-            assert!(url.starts_with("https://"));
-            assert!(url.contains("api-endpoint.mta.info"));
-            assert!(url.contains("gtfs"));
-
-            // Check line IDs
-            for line in *lines {
-                assert!(!line.is_empty());
-                assert!(line.len() <= 2); // NYC subway lines are 1-2 characters
-            }
-        }
+    fn test_parse_args() {
+        let parse = |args: &[&str]| Args::parse(args.iter().map(|arg| arg.to_string()));
+        assert_eq!(parse(&[]), Ok(Args { once: false }));
+        assert_eq!(parse(&["--once"]), Ok(Args { once: true }));
+        assert!(parse(&["--twice"]).unwrap_err().contains("--twice"));
+        assert!(parse(&["once"]).is_err());
     }
 
     #[test]
-    fn test_feed_urls_completeness() {
-        // Get all unique lines from FEED_URLS
-        let mut all_lines: Vec<&str> = FEED_URLS
-            .iter()
-            .flat_map(|(_, lines)| lines.iter().copied())
-            .collect();
+    fn test_backoff_grows_to_cap_and_resets() {
+        let mut backoff = Backoff::new(COLLECT_INTERVAL);
+        assert_eq!(backoff.delay(), Duration::ZERO);
+
+        let delays: Vec<Duration> = (0..20).map(|_| backoff.record_failure()).collect();
+        assert_eq!(delays[0], COLLECT_INTERVAL);
+        assert_eq!(delays[1], COLLECT_INTERVAL * 2);
+        assert!(delays.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(*delays.last().unwrap(), MAX_BACKOFF);
+
+        backoff.record_success();
+        assert_eq!(backoff.delay(), Duration::ZERO);
+        assert_eq!(backoff.record_failure(), COLLECT_INTERVAL);
+    }
+
+    /// Counts how often it is collected
+    struct MockCollector {
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl DataCollector for MockCollector {
+        fn name(&self) -> &str {
+            "mock"
+        }
+
+        fn interval(&self) -> Duration {
+            Duration::from_millis(10)
+        }
+
+        async fn collect(&self, _db: &PgPool) -> backend::Result<()> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_collector_calls_collect_each_interval() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let collector = MockCollector {
+            calls: calls.clone(),
+        };
+        // Never connects; the mock doesn't touch the database
+        let db = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        let task = tokio::spawn(run_collector(Arc::new(collector), db, shutdown_rx));
+        time::sleep(Duration::from_millis(100)).await;
+        task.abort();
+
+        assert!(calls.load(Ordering::SeqCst) >= 3, "{:?}", calls);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_run_collector() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let collector = MockCollector {
+            calls: calls.clone(),
+        };
+        let db = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        let task = tokio::spawn(run_collector(Arc::new(collector), db, shutdown_rx));
+        time::sleep(Duration::from_millis(50)).await;
+        shutdown_tx.send_replace(true);
+
+        time::timeout(Duration::from_secs(1), task)
+            .await
+            .expect("collector didn't stop after shutdown")
+            .expect("collector task panicked");
+        let stopped_at = calls.load(Ordering::SeqCst);
+        assert!(stopped_at >= 1, "{:?}", calls);
+        time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(calls.load(Ordering::SeqCst), stopped_at);
+    }
+
+    /// Takes a while to collect, recording when a round finishes
+    struct SlowCollector {
+        started: Arc<AtomicUsize>,
+        finished: Arc<AtomicUsize>,
+    }
 
-        all_lines.sort();
-        all_lines.dedup();
+    #[async_trait]
+    impl DataCollector for SlowCollector {
+        fn name(&self) -> &str {
+            "slow"
+        }
 
-        // Check for major subway lines
-        let required_lines = [
-            "A", "B", "C", "D", "E", "F", "G", "L", "M", "N", "Q", "R", "W", "1", "2", "3", "4",
-            "5", "6", "7",
-        ];
-        for line in required_lines.iter() {
-            assert!(all_lines.contains(line), "Missing line: {}", line);
+        async fn collect(&self, _db: &PgPool) -> backend::Result<()> {
+            self.started.fetch_add(1, Ordering::SeqCst);
+            time::sleep(Duration::from_millis(100)).await;
+            self.finished.fetch_add(1, Ordering::SeqCst);
+            Ok(())
         }
     }
 
+    #[tokio::test]
+    async fn test_shutdown_finishes_in_flight_round() {
+        let started = Arc::new(AtomicUsize::new(0));
+        let finished = Arc::new(AtomicUsize::new(0));
+        let collector = SlowCollector {
+            started: started.clone(),
+            finished: finished.clone(),
+        };
+        let db = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        let task = tokio::spawn(run_collector(Arc::new(collector), db, shutdown_rx));
+        // The first tick is immediate, so the round is underway by now
+        time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+        shutdown_tx.send_replace(true);
+
+        time::timeout(Duration::from_secs(1), task)
+            .await
+            .expect("collector didn't stop after shutdown")
+            .expect("collector task panicked");
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+        assert_eq!(finished.load(Ordering::SeqCst), 1);
+    }
+
     #[test]
-    fn test_feed_urls_no_duplicates() {
-        // Check that no line appears in multiple feeds
-        let mut seen_lines = std::collections::HashSet::new();
-
-        for (_, lines) in FEED_URLS.iter() {
-            for &line in *lines {
-                assert!(
-                    seen_lines.insert(line),
-                    "Line {} appears in multiple feeds",
-                    line
-                );
+    fn test_jitter_stays_within_half_to_full_delay() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..100 {
+            let delay = with_jitter(MAX_BACKOFF, &mut rng);
+            assert!(
+                delay >= MAX_BACKOFF / 2 && delay <= MAX_BACKOFF,
+                "{:?}",
+                delay
+            );
+        }
+    }
+
+    #[test]
+    fn test_same_seed_same_delay_pattern() {
+        assert_eq!(delay_pattern(42), delay_pattern(42));
+        assert_ne!(delay_pattern(42), delay_pattern(43));
+    }
+
+    #[test]
+    fn test_alert_statuses_override_samples() {
+        let feeds: Vec<&FeedInfo> = SUBWAY_FEEDS.iter().collect();
+        let mut statuses = sample_statuses(&feeds, &mut StdRng::seed_from_u64(42));
+        let sampled: Vec<(String, String)> = statuses
+            .iter()
+            .map(|status| (status.line.clone(), status.status.clone()))
+            .collect();
+        let alert_statuses = BTreeMap::from([
+            ("L", ServiceStatus::PlannedWork),
+            ("6", ServiceStatus::Delays),
+        ]);
+
+        overlay_alert_statuses(&mut statuses, &alert_statuses);
+
+        for (status, (line, sampled_status)) in statuses.iter().zip(&sampled) {
+            match line.as_str() {
+                "L" => {
+                    assert_eq!(status.status, "Planned Work");
+                    assert!(!status.delays);
+                }
+                "6" => {
+                    assert_eq!(status.status, "Delays");
+                    assert!(status.delays);
+                }
+                _ => assert_eq!(&status.status, sampled_status),
             }
         }
     }
@@ -7,7 +7,8 @@
 //! ## Key Components
 //!
 //! - `StatusPanel`: Displays real-time status information for each subway line
-//! - `MapView`: Shows an interactive map with subway stations and real-time train positions
+//! - `MapView`: Shows an interactive map with subway stations and real-time train positions,
+//!   with a slider replaying recent snapshots; stations are clustered at low zoom
 //! - `App`: The main application component that combines the status panel and map view
 //!
 //! ## Architecture
@@ -22,13 +23,18 @@
 
 use gloo_net::http::Request;
 use js_sys::{Array, Object, Reflect};
+use nyc_pulse_common::lines::{text_color_for_line, DARK_TEXT};
+use nyc_pulse_common::service_status::ServiceStatus;
 use nyc_pulse_common::SubwayStatus;
 use nyc_pulse_frontend::subway_data::{
-    fetch_subway_stations, fetch_train_positions, get_line_style,
+    animate_train_positions, fetch_subway_stations, fetch_train_positions, get_line_style,
+    reset_train_states, GeoJsonCollection, TrainSource, ANIMATION_FRAME_MS,
+    TRAIN_FETCH_INTERVAL_MS,
 };
+use std::collections::HashSet;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::spawn_local;
-use web_sys::{console, Element, HtmlScriptElement};
+use web_sys::{console, Element, HtmlInputElement, HtmlScriptElement};
 use yew::prelude::*;
 
 /// Mapbox access token for map initialization
@@ -37,6 +43,32 @@ const MAPBOX_TOKEN: &str = "pk.eyJ1Ijoicm9iZXJ0aHNoZW5nIiwiYSI6ImNtNDNwamplbzBmZ
 /// Default center coordinates for NYC (longitude, latitude)
 const NYC_CENTER: [f64; 2] = [-73.977664, 40.761484];
 
+/// Map layers drawing unclustered stations, whose `lines` property lists every route served
+const STATION_LAYERS: [&str; 4] = [
+    "stations-glow",
+    "stations-inner-glow",
+    "stations-point",
+    "stations",
+];
+
+/// Highest zoom at which nearby stations are merged into clusters
+const STATION_CLUSTER_MAX_ZOOM: f64 = 12.0;
+
+/// Radius in pixels within which stations are merged into one cluster
+const STATION_CLUSTER_RADIUS: f64 = 40.0;
+
+/// Map layers drawing trains, whose `lines` property is the train's single route
+const TRAIN_LAYERS: [&str; 3] = ["trains-glow", "trains-bg", "trains"];
+
+/// How far back the replay slider reaches, in seconds
+const REPLAY_WINDOW_SECS: i64 = 3600;
+
+/// Mapbox GL JS script injected when the map is first initialized
+const MAPBOX_SCRIPT_URL: &str = "https://api.mapbox.com/mapbox-gl-js/v3.8.0/mapbox-gl.js";
+
+/// Message shown in place of the map when Mapbox GL JS can't be loaded or started
+const MAP_LOAD_ERROR: &str = "Map failed to load";
+
 /// Bindings for Mapbox GL JS Popup functionality
 #[wasm_bindgen]
 extern "C" {
@@ -65,107 +97,489 @@ extern "C" {
     fn new() -> NavigationControl;
 }
 
+/// Style classes for a line's badge, with the text color picked by
+/// [`text_color_for_line`] (e.g. black on the yellow Broadway lines)
+fn line_badge_classes(line: &str) -> Vec<&'static str> {
+    let mut classes: Vec<&'static str> = get_line_style(line).split_whitespace().collect();
+    classes.push(if text_color_for_line(line) == DARK_TEXT {
+        "text-black"
+    } else {
+        "text-white"
+    });
+    classes
+}
+
+/// Properties for the LineBadge component
+#[derive(Properties, Clone, PartialEq)]
+struct LineBadgeProps {
+    /// Line identifier shown in the badge
+    line: String,
+}
+
+/// Colored circular badge showing a line identifier
+#[function_component(LineBadge)]
+fn line_badge(props: &LineBadgeProps) -> Html {
+    html! {
+        <span class={classes!(
+            line_badge_classes(&props.line),
+            "w-10",
+            "h-10",
+            "rounded-full",
+            "flex",
+            "items-center",
+            "justify-center",
+            "font-bold",
+            "text-lg"
+        )}>
+            { &props.line }
+        </span>
+    }
+}
+
+/// Text color for a line's status: red for delays, amber for planned work, else green
+fn status_text_class(status: &SubwayStatus) -> &'static str {
+    if status.delays {
+        "text-red-400"
+    } else if ServiceStatus::from_label(&status.status) == Some(ServiceStatus::PlannedWork) {
+        "text-amber-400"
+    } else {
+        "text-green-400"
+    }
+}
+
+/// Progress of the subway status fetch
+#[derive(Debug, Clone, PartialEq)]
+enum StatusLoad {
+    /// No response has arrived yet
+    Loading,
+    /// The latest statuses, sorted by line
+    Loaded(Vec<SubwayStatus>),
+    /// Why the latest fetch failed
+    Error(String),
+}
+
+impl StatusLoad {
+    /// The state after a fetch, sorting the statuses by line
+    fn from_result(result: Result<Vec<SubwayStatus>, String>) -> Self {
+        match result {
+            Ok(mut statuses) => {
+                statuses.sort_by(|a, b| a.line.cmp(&b.line));
+                StatusLoad::Loaded(statuses)
+            }
+            Err(message) => StatusLoad::Error(message),
+        }
+    }
+
+    /// The loaded statuses, empty while loading or after an error
+    fn statuses(&self) -> &[SubwayStatus] {
+        match self {
+            StatusLoad::Loaded(statuses) => statuses,
+            StatusLoad::Loading | StatusLoad::Error(_) => &[],
+        }
+    }
+}
+
 /// Properties for the StatusPanel component
 #[derive(Properties, Clone, PartialEq)]
 struct StatusPanelProps {
-    /// Vector of subway line statuses
-    statuses: Vec<SubwayStatus>,
-    /// Currently selected subway line
-    active_line: Option<String>,
+    /// Subway line statuses, or where fetching them stands
+    load: StatusLoad,
+    /// Currently selected subway lines
+    active_lines: HashSet<String>,
     /// Callback for when a line is clicked
     on_line_click: Callback<String>,
 }
 
 /// Component that displays the status of all subway lines
+///
+/// Shows a spinner until the first statuses arrive, and an error message in
+/// place of the list while fetching them fails.
 #[function_component(StatusPanel)]
 fn status_panel(props: &StatusPanelProps) -> Html {
-    html! {
-        <div class="h-full bg-zinc-900 shadow-lg overflow-auto">
-            <div class="p-4">
-                <h2 class="text-2xl font-bold mb-4 text-zinc-100">{"Line Status"}</h2>
-                <div class="space-y-2">
-                {
-                    props.statuses.iter().map(|status| {
-                        let is_active = props.active_line.as_ref().map_or(false, |l| l == &status.line);
-                        let line = status.line.clone();
-                        let onclick = {
-                            let line = line.clone();
-                            let on_line_click = props.on_line_click.clone();
-                            Callback::from(move |_| {
-                                on_line_click.emit(line.clone());
-                            })
-                        };
+    let statuses = match &props.load {
+        StatusLoad::Loading => {
+            return status_panel_frame(html! {
+                <div class="flex items-center space-x-3 p-4 text-zinc-400">
+                    <span class="animate-spin rounded-full h-5 w-5 border-2 border-zinc-600 border-t-zinc-100"/>
+                    <span>{"Loading line statuses..."}</span>
+                </div>
+            })
+        }
+        StatusLoad::Error(message) => {
+            return status_panel_frame(html! {
+                <div class="p-4 rounded-lg bg-red-950/50 text-red-400">
+                    <p class="font-medium">{"Couldn't load line statuses"}</p>
+                    <p class="text-xs text-zinc-400">{ message }</p>
+                </div>
+            })
+        }
+        StatusLoad::Loaded(statuses) => statuses,
+    };
+
+    status_panel_frame(html! {
+        <div class="space-y-2">
+        {
+            statuses.iter().map(|status| {
+                let is_active = props.active_lines.contains(&status.line);
+                let line = status.line.clone();
+                let onclick = {
+                    let line = line.clone();
+                    let on_line_click = props.on_line_click.clone();
+                    Callback::from(move |_| {
+                        on_line_click.emit(line.clone());
+                    })
+                };
 
-                        html! {
-                            <div
-                                {onclick}
-                                class={classes!(
-                                    "p-4",
-                                    "rounded-lg",
-                                    "transition-colors",
-                                    "duration-200",
-                                    if is_active { "bg-zinc-800" } else { "bg-zinc-800/50" },
-                                    "hover:bg-zinc-800",
-                                    "cursor-pointer"
-                                )}
-                            >
-                                <div class="flex items-center justify-between">
-                                    <div class="flex items-center space-x-3">
-                                        <span class={classes!(
-                                            get_line_style(&status.line),
-                                            "w-10",
-                                            "h-10",
-                                            "rounded-full",
-                                            "flex",
-                                            "items-center",
-                                            "justify-center",
-                                            "text-white",
-                                            "font-bold",
-                                            "text-lg"
-                                        )}>
-                                            { &status.line }
-                                        </span>
-                                        <div class="flex flex-col">
-                                            <span class={classes!(
-                                                "font-medium",
-                                                if status.delays { "text-red-400" } else { "text-green-400" }
-                                            )}>
-                                                { &status.status }
-                                            </span>
-                                            <span class="text-xs text-zinc-400">
-                                                { "Updated "} { status.timestamp.format("%H:%M:%S").to_string() }
-                                            </span>
-                                        </div>
-                                    </div>
-                                    if status.delays {
-                                        <span class="animate-pulse rounded-full h-3 w-3 bg-red-500 shadow-[0px_0px_4px_2px_rgba(239,68,68,0.7)]"/>
-                                    }
+                html! {
+                    <div
+                        {onclick}
+                        class={classes!(
+                            "p-4",
+                            "rounded-lg",
+                            "transition-colors",
+                            "duration-200",
+                            if is_active { "bg-zinc-800" } else { "bg-zinc-800/50" },
+                            "hover:bg-zinc-800",
+                            "cursor-pointer"
+                        )}
+                    >
+                        <div class="flex items-center justify-between">
+                            <div class="flex items-center space-x-3">
+                                <LineBadge line={status.line.clone()} />
+                                <div class="flex flex-col">
+                                    <span class={classes!("font-medium", status_text_class(status))}>
+                                        { &status.status }
+                                        if status.delays {
+                                            { format!(" for {} min", status.minutes_since_change(now_secs())) }
+                                        }
+                                    </span>
+                                    <span class="text-xs text-zinc-400">
+                                        { "Updated "} { status.timestamp.format("%H:%M:%S").to_string() }
+                                    </span>
                                 </div>
                             </div>
-                        }
-                    }).collect::<Html>()
+                            if status.delays {
+                                <span class="animate-pulse rounded-full h-3 w-3 bg-red-500 shadow-[0px_0px_4px_2px_rgba(239,68,68,0.7)]"/>
+                            }
+                        </div>
+                    </div>
                 }
-                </div>
+            }).collect::<Html>()
+        }
+        </div>
+    })
+}
+
+/// The status panel's background and heading around `content`
+fn status_panel_frame(content: Html) -> Html {
+    html! {
+        <div class="h-full bg-zinc-900 shadow-lg overflow-auto">
+            <div class="p-4">
+                <h2 class="text-2xl font-bold mb-4 text-zinc-100">{"Line Status"}</h2>
+                { content }
             </div>
         </div>
     }
 }
 
+/// Fetches the current line statuses from the backend
+///
+/// # Errors
+/// - A message saying whether the request failed or its body didn't parse
+async fn fetch_statuses() -> Result<Vec<SubwayStatus>, String> {
+    let response = Request::get("http://localhost:3000/api/subway/status")
+        .send()
+        .await
+        .map_err(|e| format!("Error fetching status: {:?}", e))?;
+    let data = response
+        .json::<Vec<SubwayStatus>>()
+        .await
+        .map_err(|e| format!("Error parsing response: {:?}", e))?;
+    console::log_1(&format!("Received {} statuses", data.len()).into());
+    Ok(data)
+}
+
+/// Whether the Mapbox GL JS script has defined the `mapboxgl` global
+fn mapboxgl_loaded(window: &web_sys::Window) -> bool {
+    Reflect::get(window, &"mapboxgl".into()).is_ok_and(|mapboxgl| mapboxgl.is_object())
+}
+
 /// Properties for the MapView component
 #[derive(Properties, Clone, PartialEq)]
 struct MapProps {
     /// Vector of subway line statuses
     statuses: Vec<SubwayStatus>,
-    /// Currently selected subway line
-    active_line: Option<String>,
+    /// Currently selected subway lines; empty shows every line
+    active_lines: HashSet<String>,
+    /// Whether trains are labelled with their raw progress instead of their bullet
+    show_progress: bool,
+    /// Called when the progress debug toggle is clicked
+    on_toggle_progress: Callback<()>,
+}
+
+/// Returns a copy of `lines` with `line` added, or removed if it was already selected
+fn toggle_line(lines: &HashSet<String>, line: String) -> HashSet<String> {
+    let mut lines = lines.clone();
+    if !lines.remove(&line) {
+        lines.insert(line);
+    }
+    lines
+}
+
+/// Builds a Mapbox filter expression matching features on any of the selected lines
+///
+/// Train features carry a single route, so they are matched exactly; station
+/// features list all their routes in one string and are matched by substring.
+/// Returns `null`, which clears the filter, when no lines are selected.
+fn line_filter(active_lines: &HashSet<String>, exact: bool) -> JsValue {
+    if active_lines.is_empty() {
+        return JsValue::NULL;
+    }
+
+    let get_lines = Array::of2(&"get".into(), &"lines".into());
+    if exact {
+        let lines: Array = active_lines.iter().map(|line| JsValue::from_str(line)).collect();
+        Array::of3(&"in".into(), &get_lines, &Array::of2(&"literal".into(), &lines)).into()
+    } else {
+        let any = Array::of1(&"any".into());
+        for line in active_lines {
+            any.push(&Array::of3(&"in".into(), &JsValue::from_str(line), &get_lines));
+        }
+        any.into()
+    }
+}
+
+/// Mapbox filter expression matching single stations, leaving out clusters
+fn unclustered_filter() -> JsValue {
+    Array::of2(
+        &"!".into(),
+        &Array::of2(&"has".into(), &"point_count".into()),
+    )
+    .into()
+}
+
+/// Builds the filter of the station layers: unclustered stations on any of the
+/// selected lines (see [`line_filter`])
+fn station_filter(active_lines: &HashSet<String>) -> JsValue {
+    let lines = line_filter(active_lines, false);
+    if lines.is_null() {
+        return unclustered_filter();
+    }
+    Array::of3(&"all".into(), &unclustered_filter(), &lines).into()
+}
+
+/// Layers drawing station clusters: a circle sized by how many stations it
+/// holds, and the count on top of it
+fn station_cluster_layers() -> [Object; 2] {
+    let has_count: JsValue = Array::of2(&"has".into(), &"point_count".into()).into();
+    let point_count: JsValue = Array::of2(&"get".into(), &"point_count".into()).into();
+
+    let circle_paint = Object::new();
+    let _ = Reflect::set(
+        &circle_paint,
+        &"circle-color".into(),
+        &"rgba(0, 255, 255, 0.35)".into(),
+    );
+    let _ = Reflect::set(&circle_paint, &"circle-stroke-width".into(), &1.0.into());
+    let _ = Reflect::set(
+        &circle_paint,
+        &"circle-stroke-color".into(),
+        &"rgba(0, 255, 255, 0.8)".into(),
+    );
+    // 15 px, growing with the number of stations in the cluster
+    let radius = Array::of2(&"step".into(), &point_count);
+    for value in [15.0, 10.0, 20.0, 50.0, 25.0] {
+        radius.push(&value.into());
+    }
+    let _ = Reflect::set(&circle_paint, &"circle-radius".into(), &radius);
+
+    let clusters = Object::new();
+    let _ = Reflect::set(&clusters, &"id".into(), &"station-clusters".into());
+    let _ = Reflect::set(&clusters, &"type".into(), &"circle".into());
+    let _ = Reflect::set(&clusters, &"source".into(), &"stations".into());
+    let _ = Reflect::set(&clusters, &"filter".into(), &has_count);
+    let _ = Reflect::set(&clusters, &"paint".into(), &circle_paint);
+
+    let count_layout = Object::new();
+    let _ = Reflect::set(
+        &count_layout,
+        &"text-field".into(),
+        &Array::of2(&"get".into(), &"point_count_abbreviated".into()),
+    );
+    let _ = Reflect::set(&count_layout, &"text-size".into(), &12.0.into());
+    let count_paint = Object::new();
+    let _ = Reflect::set(&count_paint, &"text-color".into(), &"#ffffff".into());
+
+    let count = Object::new();
+    let _ = Reflect::set(&count, &"id".into(), &"station-cluster-count".into());
+    let _ = Reflect::set(&count, &"type".into(), &"symbol".into());
+    let _ = Reflect::set(&count, &"source".into(), &"stations".into());
+    let _ = Reflect::set(&count, &"filter".into(), &has_count);
+    let _ = Reflect::set(&count, &"layout".into(), &count_layout);
+    let _ = Reflect::set(&count, &"paint".into(), &count_paint);
+
+    [clusters, count]
+}
+
+/// Layer drawing each unclustered station as a dot in its first route's color
+fn station_point_layer() -> Object {
+    let paint = Object::new();
+    let _ = Reflect::set(&paint, &"circle-radius".into(), &4.0.into());
+    let _ = Reflect::set(
+        &paint,
+        &"circle-color".into(),
+        &Array::of2(&"get".into(), &"color".into()),
+    );
+    let _ = Reflect::set(&paint, &"circle-stroke-width".into(), &1.0.into());
+    let _ = Reflect::set(&paint, &"circle-stroke-color".into(), &"#ffffff".into());
+
+    let layer = Object::new();
+    let _ = Reflect::set(&layer, &"id".into(), &"stations-point".into());
+    let _ = Reflect::set(&layer, &"type".into(), &"circle".into());
+    let _ = Reflect::set(&layer, &"source".into(), &"stations".into());
+    let _ = Reflect::set(&layer, &"filter".into(), &unclustered_filter());
+    let _ = Reflect::set(&layer, &"paint".into(), &paint);
+    layer
+}
+
+/// Applies the line selection to every station and train layer on the map
+///
+/// Station clusters always count every station, whichever lines are selected.
+fn apply_line_filter(map: &JsValue, active_lines: &HashSet<String>) {
+    if let Ok(set_filter) = Reflect::get(map, &"setFilter".into())
+        .and_then(|f| f.dyn_into::<js_sys::Function>())
+    {
+        let layers = STATION_LAYERS
+            .iter()
+            .map(|layer| (layer, station_filter(active_lines)))
+            .chain(
+                TRAIN_LAYERS
+                    .iter()
+                    .map(|layer| (layer, line_filter(active_lines, true))),
+            );
+        for (layer, filter) in layers {
+            if let Err(e) = set_filter.call2(map, &JsValue::from_str(layer), &filter) {
+                console::error_1(&format!("Failed to filter layer {}: {:?}", layer, e).into());
+            }
+        }
+    }
+}
+
+/// Runs `apply` on `map` now if its layers have been added, or once it loads
+///
+/// Layers are added by the map's load handler, so changes made before then
+/// would find no layer to change.
+fn with_layers(map: &JsValue, apply: impl FnOnce(&JsValue) + 'static) {
+    let layers_added = Reflect::get(map, &"getLayer".into())
+        .and_then(|f| f.dyn_into::<js_sys::Function>())
+        .and_then(|get_layer| get_layer.call1(map, &TRAIN_LAYERS[2].into()))
+        .map(|layer| !layer.is_undefined())
+        .unwrap_or(false);
+
+    if layers_added {
+        apply(map);
+    } else if let Ok(once) =
+        Reflect::get(map, &"once".into()).and_then(|f| f.dyn_into::<js_sys::Function>())
+    {
+        let map_clone = map.clone();
+        let on_load = Closure::once_into_js(move || apply(&map_clone));
+        let _ = once.call2(map, &"load".into(), &on_load);
+    }
+}
+
+/// Mapbox expression for the train label: the route bullet, or with
+/// `show_progress` the train's progress to two decimals
+fn train_label(show_progress: bool) -> JsValue {
+    if !show_progress {
+        return Array::of2(&"get".into(), &"bullet".into()).into();
+    }
+    let format = Object::new();
+    let _ = Reflect::set(&format, &"min-fraction-digits".into(), &2.into());
+    let _ = Reflect::set(&format, &"max-fraction-digits".into(), &2.into());
+    Array::of3(
+        &"number-format".into(),
+        &Array::of2(&"get".into(), &"progress".into()),
+        &format,
+    )
+    .into()
+}
+
+/// Switches the `trains` layer's label between bullets and progress (see [`train_label`])
+fn apply_train_label(map: &JsValue, show_progress: bool) {
+    if let Ok(set_layout_property) = Reflect::get(map, &"setLayoutProperty".into())
+        .and_then(|f| f.dyn_into::<js_sys::Function>())
+    {
+        if let Err(e) = set_layout_property.call3(
+            map,
+            &TRAIN_LAYERS[2].into(),
+            &"text-field".into(),
+            &train_label(show_progress),
+        ) {
+            console::error_1(&format!("Failed to set train labels: {:?}", e).into());
+        }
+    }
+}
+
+/// Replaces the data of the map's `trains` source
+fn set_train_data(map: &JsValue, collection: &GeoJsonCollection) {
+    let source = match Reflect::get(map, &"getSource".into())
+        .and_then(|f| f.dyn_into::<js_sys::Function>())
+        .and_then(|get_source| get_source.call1(map, &"trains".into()))
+    {
+        Ok(source) => source,
+        Err(e) => {
+            console::error_1(&format!("Failed to get train source: {:?}", e).into());
+            return;
+        }
+    };
+    if let Ok(set_data) =
+        Reflect::get(&source, &"setData".into()).and_then(|f| f.dyn_into::<js_sys::Function>())
+    {
+        match serde_wasm_bindgen::to_value(collection) {
+            Ok(geojson) => {
+                let _ = set_data.call1(&source, &geojson);
+            }
+            Err(e) => console::error_1(&format!("Failed to serialize train data: {:?}", e).into()),
+        }
+    }
+}
+
+/// Current Unix time in whole seconds
+fn now_secs() -> i64 {
+    (js_sys::Date::now() / 1000.0) as i64
+}
+
+/// Slider caption for a replay `offset` in seconds before now, or `None` while live
+fn replay_label(offset: Option<i64>) -> String {
+    match offset.map(i64::abs) {
+        None => "Live".to_string(),
+        Some(secs) if secs < 60 => format!("{}s ago", secs),
+        Some(secs) => format!("{} min ago", secs / 60),
+    }
 }
 
 /// Component that displays the interactive map with subway stations and trains
+///
+/// The slider in the corner replays recent train snapshots: moving it pauses
+/// live polling and shows the positions recorded at that time, and "Live"
+/// resumes polling. Next to it, the "Progress" toggle swaps train bullets for
+/// their raw progress, for debugging interpolation.
+///
+/// If Mapbox GL JS fails to load or the map can't be created, a message with a
+/// retry button is shown in place of the map.
 #[function_component(MapView)]
-fn map_view(_props: &MapProps) -> Html {
+fn map_view(props: &MapProps) -> Html {
     let map_ref = use_state(|| None::<JsValue>);
     let container_ref = use_node_ref();
     let stations_data = use_state(|| None::<String>);
+    // Replayed timestamp, or `None` while live
+    let replay_at = use_state(|| None::<i64>);
+    // Mirrors `replay_at.is_some()` for the live update interval
+    let replaying = use_mut_ref(|| false);
+    // Why the map couldn't be shown, or `None` while it loads or runs
+    let map_error = use_state(|| None::<String>);
+    // Bumped by the retry button to run map initialization again
+    let init_attempt = use_state(|| 0_u32);
 
     // Fetch stations data
     {
@@ -196,37 +610,46 @@ fn map_view(_props: &MapProps) -> Html {
         let map_ref = map_ref.clone();
         let container_ref = container_ref.clone();
         let stations_data = stations_data.clone();
+        let replaying = replaying.clone();
+        let map_error = map_error.clone();
 
         use_effect_with_deps(
-            move |data: &Option<String>| {
+            move |(data, _attempt): &(Option<String>, u32)| {
                 if let Some(geojson_data) = data.clone() {
                     let window = web_sys::window().unwrap();
                     let document = window.document().unwrap();
 
-                    let script = document
-                        .create_element("script")
-                        .unwrap()
-                        .dyn_into::<HtmlScriptElement>()
-                        .unwrap();
-                    script.set_type("text/javascript");
-                    script.set_src("https://api.mapbox.com/mapbox-gl-js/v3.8.0/mapbox-gl.js");
-
-                    let link = document
-                        .create_element("link")
-                        .unwrap()
-                        .dyn_into::<web_sys::HtmlLinkElement>()
-                        .unwrap();
-                    link.set_rel("stylesheet");
-                    link.set_href("https://api.mapbox.com/mapbox-gl-js/v3.0.0/mapbox-gl.css");
-                    document.head().unwrap().append_child(&link).unwrap();
+                    if document.get_element_by_id("mapbox-gl-css").is_none() {
+                        let link = document
+                            .create_element("link")
+                            .unwrap()
+                            .dyn_into::<web_sys::HtmlLinkElement>()
+                            .unwrap();
+                        link.set_id("mapbox-gl-css");
+                        link.set_rel("stylesheet");
+                        link.set_href("https://api.mapbox.com/mapbox-gl-js/v3.0.0/mapbox-gl.css");
+                        document.head().unwrap().append_child(&link).unwrap();
+                    }
+
+                    // A retry after the script loaded only needs to rebuild the map
+                    let already_loaded = mapboxgl_loaded(&window);
 
                     // Store the init function in a variable to avoid FnOnce issues
                     let init_map = {
                         let container_ref = container_ref.clone();
                         let map_ref = map_ref.clone();
                         let geojson_data = geojson_data.clone();
+                        let replaying = replaying.clone();
+                        let map_error = map_error.clone();
 
                         move || {
+                            // A blocked or partial script can fire `onload` without defining the global
+                            if !mapboxgl_loaded(&window) {
+                                console::error_1(&"Mapbox GL JS loaded without defining mapboxgl".into());
+                                map_error.set(Some(MAP_LOAD_ERROR.to_string()));
+                                return;
+                            }
+
                             if let Some(container) = container_ref.cast::<Element>() {
                                 let options = Object::new();
                                 Reflect::set(&options, &"container".into(), container.as_ref())
@@ -366,6 +789,7 @@ fn map_view(_props: &MapProps) -> Html {
                                                 let load_handler = {
                                                     let map = map_clone.clone();
                                                     let data = geojson_data.clone();
+                                                    let replaying = replaying.clone();
 
                                                     Closure::wrap(Box::new(move || {
                                                         let map = map.clone();
@@ -387,6 +811,25 @@ fn map_view(_props: &MapProps) -> Html {
                                                                 &geojson_obj,
                                                             )
                                                             .unwrap();
+                                                            // Merge overlapping stations at low zoom
+                                                            Reflect::set(
+                                                                &source,
+                                                                &"cluster".into(),
+                                                                &true.into(),
+                                                            )
+                                                            .unwrap();
+                                                            Reflect::set(
+                                                                &source,
+                                                                &"clusterMaxZoom".into(),
+                                                                &STATION_CLUSTER_MAX_ZOOM.into(),
+                                                            )
+                                                            .unwrap();
+                                                            Reflect::set(
+                                                                &source,
+                                                                &"clusterRadius".into(),
+                                                                &STATION_CLUSTER_RADIUS.into(),
+                                                            )
+                                                            .unwrap();
 
                                                             // Add station source
                                                             if let Ok(add_source) = Reflect::get(
@@ -443,6 +886,12 @@ fn map_view(_props: &MapProps) -> Html {
                                                                 &"stations".into(),
                                                             )
                                                             .unwrap();
+                                                            Reflect::set(
+                                                                &glow_layer,
+                                                                &"filter".into(),
+                                                                &unclustered_filter(),
+                                                            )
+                                                            .unwrap();
                                                             Reflect::set(
                                                                 &glow_layer,
                                                                 &"paint".into(),
@@ -490,6 +939,12 @@ fn map_view(_props: &MapProps) -> Html {
                                                                 &"stations".into(),
                                                             )
                                                             .unwrap();
+                                                            Reflect::set(
+                                                                &inner_glow_layer,
+                                                                &"filter".into(),
+                                                                &unclustered_filter(),
+                                                            )
+                                                            .unwrap();
                                                             Reflect::set(
                                                                 &inner_glow_layer,
                                                                 &"paint".into(),
@@ -517,6 +972,16 @@ fn map_view(_props: &MapProps) -> Html {
                                                                 &"stations".into(),
                                                             )
                                                             .unwrap();
+                                                            Reflect::set(
+                                                                &station_layer,
+                                                                &"filter".into(),
+                                                                &unclustered_filter(),
+                                                            )
+                                                            .unwrap();
+
+                                                            // Clusters, then unclustered stations
+                                                            let [cluster_layer, cluster_count_layer] =
+                                                                station_cluster_layers();
 
                                                             // Add layers in order
                                                             if let Ok(add_layer_fn) = Reflect::get(
@@ -526,6 +991,14 @@ fn map_view(_props: &MapProps) -> Html {
                                                                 let func = add_layer_fn
                                                                     .dyn_into::<js_sys::Function>()
                                                                     .unwrap();
+                                                                let _ = func.call1(&map, &cluster_layer).unwrap_or_else(|e| {
+                                                                console::error_1(&format!("Failed to add station cluster layer: {:?}", e).into());
+                                                                e
+                                                            });
+                                                                let _ = func.call1(&map, &cluster_count_layer).unwrap_or_else(|e| {
+                                                                console::error_1(&format!("Failed to add station cluster count layer: {:?}", e).into());
+                                                                e
+                                                            });
                                                                 let _ = func.call1(&map, &glow_layer).unwrap_or_else(|e| {
                                                                 console::error_1(&format!("Failed to add glow layer: {:?}", e).into());
                                                                 e
@@ -533,6 +1006,10 @@ fn map_view(_props: &MapProps) -> Html {
                                                                 let _ = func.call1(&map, &inner_glow_layer).unwrap_or_else(|e| {
                                                                 console::error_1(&format!("Failed to add inner glow layer: {:?}", e).into());
                                                                 e
+                                                            });
+                                                                let _ = func.call1(&map, &station_point_layer()).unwrap_or_else(|e| {
+                                                                console::error_1(&format!("Failed to add station point layer: {:?}", e).into());
+                                                                e
                                                             });
                                                                 let _ = func.call1(&map, &station_layer).unwrap_or_else(|e| {
                                                                 console::error_1(&format!("Failed to add station layer: {:?}", e).into());
@@ -713,10 +1190,7 @@ fn map_view(_props: &MapProps) -> Html {
                                                             Reflect::set(
                                                                 &train_layout,
                                                                 &"text-field".into(),
-                                                                &Array::of2(
-                                                                    &"get".into(),
-                                                                    &"lines".into(),
-                                                                ),
+                                                                &train_label(false),
                                                             )
                                                             .unwrap();
                                                             Reflect::set(
@@ -738,12 +1212,19 @@ fn map_view(_props: &MapProps) -> Html {
                                                             )
                                                             .unwrap();
 
-                                                            // Paint properties for the symbol layer
+                                                            // Paint properties for the symbol layer, with the
+                                                            // label color legible on each train's bullet
+                                                            let text_color_expression =
+                                                                Array::new();
+                                                            text_color_expression
+                                                                .push(&"get".into());
+                                                            text_color_expression
+                                                                .push(&"text_color".into());
                                                             let train_paint = Object::new();
                                                             Reflect::set(
                                                                 &train_paint,
                                                                 &"text-color".into(),
-                                                                &"#ffffff".into(),
+                                                                &text_color_expression,
                                                             )
                                                             .unwrap();
 
@@ -867,35 +1348,23 @@ fn map_view(_props: &MapProps) -> Html {
                                                             }
 
                                                             let map_clone = map.clone();
+                                                            let fetch_replaying = replaying.clone();
                                                             let update_trains = Closure::wrap(
                                                                 Box::new(move || {
+                                                                    // Live updates pause while a snapshot is replayed
+                                                                    if *fetch_replaying.borrow() {
+                                                                        return;
+                                                                    }
                                                                     console::log_1(&"Starting train position update...".into());
                                                                     let map_clone =
                                                                         map_clone.clone();
+                                                                    let replaying = fetch_replaying.clone();
                                                                     wasm_bindgen_futures::spawn_local(async move {
-                                                                match fetch_train_positions().await {
+                                                                match fetch_train_positions(TrainSource::Live).await {
                                                                     Ok(train_collection) => {
                                                                         console::log_1(&format!("Successfully fetched {} train positions", train_collection.features.len()).into());
-                                                                        // Get the source
-                                                                        if let Ok(get_source) = Reflect::get(&map_clone, &"getSource".into()) {
-                                                                            if let Ok(source_func) = get_source.dyn_into::<js_sys::Function>() {
-                                                                                match source_func.call1(&map_clone, &"trains".into()) {
-                                                                                    Ok(source) => {
-                                                                                        console::log_1(&"Successfully got train source".into());
-                                                                                        if let Ok(set_data) = Reflect::get(&source, &"setData".into()) {
-                                                                                            let func = set_data.dyn_into::<js_sys::Function>().unwrap();
-                                                                                            match serde_wasm_bindgen::to_value(&train_collection) {
-                                                                                                Ok(geojson) => {
-                                                                                                    let _ = func.call1(&source, &geojson);
-                                                                                                    console::log_1(&"Updated train source data".into());
-                                                                                                }
-                                                                                                Err(e) => console::error_1(&format!("Failed to serialize train data: {:?}", e).into()),
-                                                                                            }
-                                                                                        }
-                                                                                    }
-                                                                                    Err(e) => console::error_1(&format!("Failed to get train source: {:?}", e).into()),
-                                                                                }
-                                                                            }
+                                                                        if !*replaying.borrow() {
+                                                                            set_train_data(&map_clone, &train_collection);
                                                                         }
                                                                     }
                                                                     Err(e) => console::error_1(&format!("Failed to fetch train positions: {:?}", e).into()),
@@ -905,15 +1374,33 @@ fn map_view(_props: &MapProps) -> Html {
                                                                     as Box<dyn FnMut()>,
                                                             );
 
-                                                            // Updates every 0.5s
+                                                            // Redraws trains easing toward the last fetched positions
+                                                            let map_clone = map.clone();
+                                                            let replaying = replaying.clone();
+                                                            let animate_trains = Closure::wrap(
+                                                                Box::new(move || {
+                                                                    if !*replaying.borrow() {
+                                                                        set_train_data(&map_clone, &animate_train_positions());
+                                                                    }
+                                                                })
+                                                                    as Box<dyn FnMut()>,
+                                                            );
+
                                                             let window = web_sys::window().unwrap();
                                                             window
                                                             .set_interval_with_callback_and_timeout_and_arguments_0(
                                                                 update_trains.as_ref().unchecked_ref(),
-                                                                500,
+                                                                TRAIN_FETCH_INTERVAL_MS as i32,
+                                                            )
+                                                            .unwrap();
+                                                            window
+                                                            .set_interval_with_callback_and_timeout_and_arguments_0(
+                                                                animate_trains.as_ref().unchecked_ref(),
+                                                                ANIMATION_FRAME_MS as i32,
                                                             )
                                                             .unwrap();
                                                             update_trains.forget();
+                                                            animate_trains.forget();
                                                         }
                                                     })
                                                         as Box<dyn FnMut()>)
@@ -932,6 +1419,10 @@ fn map_view(_props: &MapProps) -> Html {
                                                 }
                                                 load_handler.forget();
                                                 map_ref.set(Some(map));
+                                            } else {
+                                                // e.g. no WebGL support or a rejected token
+                                                console::error_1(&"Failed to construct the Mapbox map".into());
+                                                map_error.set(Some(MAP_LOAD_ERROR.to_string()));
                                             }
                                         }
                                     }
@@ -940,20 +1431,117 @@ fn map_view(_props: &MapProps) -> Html {
                         }
                     };
 
-                    let onload = Closure::wrap(Box::new(move || {
+                    if already_loaded {
                         init_map();
-                    }) as Box<dyn FnMut()>);
+                    } else {
+                        let script = document
+                            .create_element("script")
+                            .unwrap()
+                            .dyn_into::<HtmlScriptElement>()
+                            .unwrap();
+                        script.set_type("text/javascript");
+                        script.set_src(MAPBOX_SCRIPT_URL);
+
+                        let onload = Closure::wrap(Box::new(move || {
+                            init_map();
+                        }) as Box<dyn FnMut()>);
+                        let onerror = {
+                            let map_error = map_error.clone();
+                            let script = script.clone();
+                            Closure::wrap(Box::new(move || {
+                                console::error_1(&"Failed to load Mapbox GL JS".into());
+                                // Dropped so a retry injects a fresh script
+                                script.remove();
+                                map_error.set(Some(MAP_LOAD_ERROR.to_string()));
+                            }) as Box<dyn FnMut()>)
+                        };
+
+                        script.set_onload(Some(onload.as_ref().unchecked_ref()));
+                        script.set_onerror(Some(onerror.as_ref().unchecked_ref()));
+                        document.head().unwrap().append_child(&script).unwrap();
+                        onload.forget();
+                        onerror.forget();
+                    }
+                }
+                || {}
+            },
+            ((*stations_data).clone(), *init_attempt),
+        );
+    }
 
-                    script.set_onload(Some(onload.as_ref().unchecked_ref()));
-                    document.head().unwrap().append_child(&script).unwrap();
-                    onload.forget();
+    // Filter stations and trains to the selected lines
+    use_effect_with_deps(
+        move |(map, active_lines): &(Option<JsValue>, HashSet<String>)| {
+            if let Some(map) = map {
+                let active_lines = active_lines.clone();
+                with_layers(map, move |map| apply_line_filter(map, &active_lines));
+            }
+            || {}
+        },
+        ((*map_ref).clone(), props.active_lines.clone()),
+    );
+
+    // Label trains with their bullet, or their raw progress while debugging
+    use_effect_with_deps(
+        move |(map, show_progress): &(Option<JsValue>, bool)| {
+            if let Some(map) = map {
+                let show_progress = *show_progress;
+                with_layers(map, move |map| apply_train_label(map, show_progress));
+            }
+            || {}
+        },
+        ((*map_ref).clone(), props.show_progress),
+    );
+
+    // Show the snapshot at the replayed timestamp, or resume live updates
+    {
+        let replaying = replaying.clone();
+        use_effect_with_deps(
+            move |(map, replay_at): &(Option<JsValue>, Option<i64>)| {
+                *replaying.borrow_mut() = replay_at.is_some();
+                match (map.clone(), *replay_at) {
+                    (Some(map), Some(at)) => spawn_local(async move {
+                        match fetch_train_positions(TrainSource::Replay { at }).await {
+                            Ok(collection) => set_train_data(&map, &collection),
+                            Err(e) => console::error_1(
+                                &format!("Failed to fetch replayed train positions: {:?}", e)
+                                    .into(),
+                            ),
+                        }
+                    }),
+                    // Trains tracked before the replay are stale by now
+                    (_, None) => reset_train_states(),
+                    (None, Some(_)) => {}
                 }
                 || {}
             },
-            (*stations_data).clone(),
+            ((*map_ref).clone(), *replay_at),
         );
     }
 
+    let replay_offset = replay_at.map(|at| (at - now_secs()).clamp(-REPLAY_WINDOW_SECS, 0));
+    let on_scrub = {
+        let replay_at = replay_at.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            if let Ok(offset) = input.value().parse::<i64>() {
+                replay_at.set(Some(now_secs() + offset));
+            }
+        })
+    };
+    let on_live = {
+        let replay_at = replay_at.clone();
+        Callback::from(move |_| replay_at.set(None))
+    };
+    let on_retry = {
+        let map_error = map_error.clone();
+        let init_attempt = init_attempt.clone();
+        Callback::from(move |_| {
+            map_error.set(None);
+            init_attempt.set(*init_attempt + 1);
+        })
+    };
+
     html! {
         <div class="h-full w-full relative">
             <div
@@ -962,6 +1550,18 @@ fn map_view(_props: &MapProps) -> Html {
                 class="absolute inset-0 m-4 rounded-2xl overflow-hidden bg-zinc-800"
             />
 
+            if let Some(error) = (*map_error).clone() {
+                <div class="absolute inset-0 m-4 rounded-2xl bg-zinc-800 flex flex-col items-center justify-center gap-3" style="z-index: 3;">
+                    <div class="text-zinc-300">{error}</div>
+                    <button
+                        class="text-sm px-3 py-1 rounded-lg bg-zinc-700 text-zinc-300 hover:bg-zinc-600"
+                        onclick={on_retry}
+                    >
+                        {"Retry"}
+                    </button>
+                </div>
+            }
+
             <div class="absolute bottom-8 left-4 bg-zinc-900/90 p-4 rounded-2xl shadow-lg" style="z-index: 2;">
                 <div class="space-y-2">
                     <div class="flex items-center gap-2">
@@ -978,6 +1578,39 @@ fn map_view(_props: &MapProps) -> Html {
                     </div>
                 </div>
             </div>
+
+            <div class="absolute bottom-8 right-4 bg-zinc-900/90 p-4 rounded-2xl shadow-lg flex items-center gap-3" style="z-index: 2;">
+                <button
+                    class={if replay_offset.is_none() {
+                        "text-sm px-2 py-1 rounded-lg bg-green-800/30 text-green-300/90"
+                    } else {
+                        "text-sm px-2 py-1 rounded-lg bg-zinc-700 text-zinc-300 hover:bg-zinc-600"
+                    }}
+                    onclick={on_live}
+                >
+                    {"Live"}
+                </button>
+                <input
+                    type="range"
+                    min={(-REPLAY_WINDOW_SECS).to_string()}
+                    max="0"
+                    step="10"
+                    value={replay_offset.unwrap_or(0).to_string()}
+                    oninput={on_scrub}
+                />
+                <div class="text-sm text-zinc-300 w-20">{replay_label(replay_offset)}</div>
+                <button
+                    class={if props.show_progress {
+                        "text-xs px-2 py-1 rounded-lg bg-amber-800/30 text-amber-300/90"
+                    } else {
+                        "text-xs px-2 py-1 rounded-lg bg-zinc-700 text-zinc-400 hover:bg-zinc-600"
+                    }}
+                    title="Label trains with their raw progress for debugging"
+                    onclick={props.on_toggle_progress.reform(|_| ())}
+                >
+                    {"Progress"}
+                </button>
+            </div>
         </div>
     }
 }
@@ -985,8 +1618,10 @@ fn map_view(_props: &MapProps) -> Html {
 /// Main application component that combines the status panel and map view
 #[function_component(App)]
 fn app() -> Html {
-    let statuses = use_state(Vec::<SubwayStatus>::new);
-    let active_line = use_state(|| None::<String>);
+    let statuses = use_state(|| StatusLoad::Loading);
+    let active_lines = use_state(HashSet::<String>::new);
+    // Developer overlay labelling trains with their progress
+    let show_progress = use_state(|| false);
 
     {
         let statuses = statuses.clone();
@@ -999,26 +1634,11 @@ fn app() -> Html {
                         let statuses = statuses.clone();
                         async move {
                             console::log_1(&"Fetching subway status...".into());
-                            match Request::get("http://localhost:3000/api/subway/status")
-                                .send()
-                                .await
-                            {
-                                Ok(response) => match response.json::<Vec<SubwayStatus>>().await {
-                                    Ok(mut data) => {
-                                        console::log_1(
-                                            &format!("Received {} statuses", data.len()).into(),
-                                        );
-                                        data.sort_by(|a, b| a.line.cmp(&b.line));
-                                        statuses.set(data);
-                                    }
-                                    Err(e) => console::error_1(
-                                        &format!("Error parsing response: {:?}", e).into(),
-                                    ),
-                                },
-                                Err(e) => console::error_1(
-                                    &format!("Error fetching status: {:?}", e).into(),
-                                ),
+                            let result = fetch_statuses().await;
+                            if let Err(message) = &result {
+                                console::error_1(&message.into());
                             }
+                            statuses.set(StatusLoad::from_result(result));
                         }
                     })
                 };
@@ -1045,18 +1665,25 @@ fn app() -> Html {
             <div class="h-full flex gap-4 p-4">
                 <div class="w-1/3 bg-zinc-800/50 rounded-2xl overflow-hidden backdrop-blur shadow-lg">
                     <StatusPanel
-                        statuses={(*statuses).clone()}
-                        active_line={(*active_line).clone()}
+                        load={(*statuses).clone()}
+                        active_lines={(*active_lines).clone()}
                         on_line_click={
-                            let active_line = active_line.clone();
-                            Callback::from(move |line| active_line.set(Some(line)))
+                            let active_lines = active_lines.clone();
+                            Callback::from(move |line| {
+                                active_lines.set(toggle_line(&active_lines, line))
+                            })
                         }
                     />
                 </div>
                 <div class="w-2/3 bg-zinc-800/50 rounded-2xl overflow-hidden backdrop-blur shadow-lg">
                     <MapView
-                        statuses={(*statuses).clone()}
-                        active_line={(*active_line).clone()}
+                        statuses={statuses.statuses().to_vec()}
+                        active_lines={(*active_lines).clone()}
+                        show_progress={*show_progress}
+                        on_toggle_progress={
+                            let show_progress = show_progress.clone();
+                            Callback::from(move |()| show_progress.set(!*show_progress))
+                        }
                     />
                 </div>
             </div>
@@ -1068,3 +1695,81 @@ fn app() -> Html {
 fn main() {
     yew::Renderer::<App>::new().render();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toggle_line_adds_and_removes() {
+        let lines = toggle_line(&HashSet::new(), "A".to_string());
+        let lines = toggle_line(&lines, "L".to_string());
+        assert_eq!(lines, HashSet::from(["A".to_string(), "L".to_string()]));
+
+        let lines = toggle_line(&lines, "A".to_string());
+        assert_eq!(lines, HashSet::from(["L".to_string()]));
+
+        let lines = toggle_line(&lines, "L".to_string());
+        assert!(lines.is_empty());
+    }
+
+    #[test]
+    fn test_line_badge_classes() {
+        let yellow = line_badge_classes("N");
+        assert!(yellow.contains(&"bg-yellow-500"));
+        assert!(yellow.contains(&"text-black"));
+        assert!(!yellow.contains(&"text-white"));
+
+        assert_eq!(line_badge_classes("A"), ["bg-blue-500", "text-white"]);
+    }
+
+    #[test]
+    fn test_status_load_states() {
+        let status = |line: &str| SubwayStatus {
+            line: line.to_string(),
+            status: "Good Service".to_string(),
+            timestamp: Default::default(),
+            delays: false,
+            changed_at: Default::default(),
+        };
+        let loaded = StatusLoad::from_result(Ok(vec![status("L"), status("A")]));
+        let lines: Vec<&str> = loaded.statuses().iter().map(|s| s.line.as_str()).collect();
+        assert_eq!(lines, ["A", "L"]);
+
+        let failed = StatusLoad::from_result(Err("Error fetching status".to_string()));
+        assert_eq!(
+            failed,
+            StatusLoad::Error("Error fetching status".to_string())
+        );
+        assert!(failed.statuses().is_empty());
+        assert!(StatusLoad::Loading.statuses().is_empty());
+    }
+
+    #[test]
+    fn test_planned_work_is_amber() {
+        let status = |label: &str, delays: bool| SubwayStatus {
+            line: "L".to_string(),
+            status: label.to_string(),
+            timestamp: Default::default(),
+            delays,
+            changed_at: Default::default(),
+        };
+        assert_eq!(
+            status_text_class(&status("Planned Work", false)),
+            "text-amber-400"
+        );
+        assert_eq!(status_text_class(&status("Delays", true)), "text-red-400");
+        assert_eq!(
+            status_text_class(&status("Good Service", false)),
+            "text-green-400"
+        );
+    }
+
+    #[test]
+    fn test_replay_label() {
+        assert_eq!(replay_label(None), "Live");
+        assert_eq!(replay_label(Some(0)), "0s ago");
+        assert_eq!(replay_label(Some(-45)), "45s ago");
+        assert_eq!(replay_label(Some(-600)), "10 min ago");
+    }
+}
@@ -8,8 +8,10 @@
 //! ## Key Components
 //!
 //! - `SubwayStationResponse`: Raw station data from MTA API
-//! - `GeoJsonCollection`/`GeoJsonFeature`: GeoJSON structures for map display
-//! - `TrainPosition`/`TrainState`: Real-time train tracking
+//! - `GeoJsonCollection`/`GeoJsonFeature`: GeoJSON structures for map display, shared
+//!   with the backend via `nyc_pulse_common::geojson`
+//! - `TrainPosition`/`TrainState`: Real-time train tracking; `TrainPosition` and its
+//!   GeoJSON conversion live in `nyc_pulse_common::trains`
 //!
 //! ## Data Flow
 //!
@@ -19,23 +21,90 @@
 //! 4. Data is converted to GeoJSON for map rendering
 
 use gloo_net::http::Request;
+use nyc_pulse_common::geohash::{self, GEOHASH_PRECISION};
+use nyc_pulse_common::lines::route_color;
+use nyc_pulse_common::open_data::{self, STATIONS_API_URL, STATIONS_PAGE_SIZE};
+use nyc_pulse_common::serde_helpers::string_or_number_to_f64;
+pub use nyc_pulse_common::geojson::{
+    GeoJsonCollection, GeoJsonCoordinates, GeoJsonFeature, GeoJsonGeometry, GeoJsonProperties,
+};
+pub use nyc_pulse_common::trains::{StopLocation, TrainPosition};
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 use std::collections::HashMap;
 
 /// Represents the current state of a train including its position and movement progress
 #[derive(Clone)]
 struct TrainState {
     position: TrainPosition,
+    /// Progress currently drawn on the map
     current_progress: f64,
+    /// Progress the train is easing toward, from the latest update or dead reckoning
+    target_progress: f64,
     last_update: f64,
+    /// Consecutive updates this train has been missing from
+    missed_updates: u32,
+}
+
+/// Consecutive missed updates a train is dead-reckoned through before it freezes
+pub const MAX_MISSED_UPDATES: u32 = 6;
+
+/// How often, in milliseconds, live train positions are fetched from the backend
+pub const TRAIN_FETCH_INTERVAL_MS: u32 = 2000;
+
+/// How often, in milliseconds, tracked trains are eased and redrawn
+///
+/// Independent of [`TRAIN_FETCH_INTERVAL_MS`], so trains keep gliding toward
+/// their targets between fetches instead of jumping when one returns.
+pub const ANIMATION_FRAME_MS: u32 = 50;
+
+/// Fraction of the remaining distance to its target a train covers each frame
+///
+/// At [`ANIMATION_FRAME_MS`] a train covers about 95% of the gap before the next fetch.
+const PROGRESS_EASING: f64 = 0.08;
+
+/// Most progress a missing train is dead-reckoned forward by in one update
+const MAX_PROGRESS_INCREMENT: f64 = 0.1;
+
+/// Gap between updates, in seconds, beyond which a missing train isn't advanced
+///
+/// A gap this long usually means the tab was backgrounded, so on resume the
+/// train waits for the feed instead of jumping to the end of its segment.
+const MAX_UPDATE_GAP_SECS: f64 = 30.0;
+
+/// Progress a missing train is dead-reckoned forward by after `time_delta` seconds
+/// of a `total_journey_time` second segment
+///
+/// Capped at [`MAX_PROGRESS_INCREMENT`], and zero for a gap longer than
+/// [`MAX_UPDATE_GAP_SECS`] or a segment without a duration.
+fn progress_increment(time_delta: f64, total_journey_time: f64) -> f64 {
+    if total_journey_time <= 0.0 || !(0.0..=MAX_UPDATE_GAP_SECS).contains(&time_delta) {
+        return 0.0;
+    }
+    (time_delta / total_journey_time).min(MAX_PROGRESS_INCREMENT)
+}
+
+/// Moves `current` a `factor` of the way toward `target`, snapping once within 0.001
+///
+/// Repeated calls converge on `target` without overshooting for `factor` in `0.0..=1.0`.
+pub fn ease_progress(current: f64, target: f64, factor: f64) -> f64 {
+    let eased = current + (target - current) * factor;
+    if (target - eased).abs() < 1e-3 {
+        target
+    } else {
+        eased
+    }
 }
 
 /// Global state for tracking all active trains
 static TRAIN_STATES: Lazy<Mutex<HashMap<String, TrainState>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
+/// Last live train layer built, shown again when a fetch fails
+static LAST_TRAIN_COLLECTION: Lazy<Mutex<Option<GeoJsonCollection>>> =
+    Lazy::new(|| Mutex::new(None));
+
 /// Raw subway station data received from the MTA API
 #[derive(Debug, Deserialize, Clone)]
 pub struct SubwayStationResponse {
@@ -44,10 +113,10 @@ pub struct SubwayStationResponse {
     /// Comma-separated list of train lines serving this station
     pub daytime_routes: String,
     /// Station latitude coordinate
-    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    #[serde(deserialize_with = "string_or_number_to_f64")]
     pub gtfs_latitude: f64,
     /// Station longitude coordinate
-    #[serde(deserialize_with = "deserialize_string_to_f64")]
+    #[serde(deserialize_with = "string_or_number_to_f64")]
     pub gtfs_longitude: f64,
     /// MTA division (e.g. IRT, BMT, IND)
     pub division: String,
@@ -65,121 +134,96 @@ pub struct SubwayStationResponse {
     pub south_direction_label: Option<String>,
 }
 
-/// Deserializes string coordinates into f64 values
-fn deserialize_string_to_f64<'de, D>(deserializer: D) -> Result<f64, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    let s = String::deserialize(deserializer)?;
-    s.parse::<f64>().map_err(serde::de::Error::custom)
-}
-
-/// A GeoJSON Feature representing a subway station or train
-#[derive(Debug, Serialize, Clone)]
-pub struct GeoJsonFeature {
-    #[serde(rename = "type")]
-    pub feature_type: String,
-    pub properties: GeoJsonProperties,
-    pub geometry: GeoJsonGeometry,
-}
-
-/// Properties associated with a GeoJSON Feature
-#[derive(Debug, Serialize, Clone)]
-pub struct GeoJsonProperties {
-    pub name: String,
-    pub lines: String,
-    pub division: String,
-    pub borough: String,
-    pub ada: bool,
-    pub ada_notes: String,
-    pub north_direction: String,
-    pub south_direction: String,
-    pub color: String,
-}
-
-/// Geometry component of a GeoJSON Feature
-#[derive(Debug, Serialize, Clone)]
-pub struct GeoJsonGeometry {
-    #[serde(rename = "type")]
-    pub geometry_type: String,
-    #[serde(rename = "coordinates")]
-    pub coordinates: GeoJsonCoordinates,
+/// First route in a station's route list, which may be space or comma separated
+fn first_route(routes: &str) -> &str {
+    routes
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .find(|route| !route.is_empty())
+        .unwrap_or_default()
 }
 
-/// Coordinates for either a Point or LineString geometry
-#[derive(Debug, Serialize, Clone)]
-#[serde(untagged)]
-pub enum GeoJsonCoordinates {
-    Point([f64; 2]),
-    LineString(Vec<[f64; 2]>),
-}
+/// Builds the station map layer from subway station data
+///
+/// Stations are colored by the first of their daytime routes.
+pub fn stations_geojson(stations: Vec<SubwayStationResponse>) -> GeoJsonCollection {
+    let features = stations
+        .into_iter()
+        .map(|station| GeoJsonFeature {
+            feature_type: "Feature".to_string(),
+            properties: GeoJsonProperties {
+                color: route_color(first_route(&station.daytime_routes)).to_string(),
+                name: station.stop_name,
+                lines: station.daytime_routes,
+                division: station.division,
+                borough: station.borough,
+                ada: station.ada.unwrap_or_default() == "TRUE",
+                ada_notes: station.ada_notes.unwrap_or_default(),
+                north_direction: station.north_direction_label.unwrap_or_default(),
+                south_direction: station.south_direction_label.unwrap_or_default(),
+                bullet: String::new(),
+                progress: None,
+                text_color: String::new(),
+                geohash: geohash::encode(
+                    station.gtfs_latitude,
+                    station.gtfs_longitude,
+                    GEOHASH_PRECISION,
+                ),
+            },
+            geometry: GeoJsonGeometry::point([station.gtfs_longitude, station.gtfs_latitude]),
+        })
+        .collect();
 
-/// Collection of GeoJSON Features
-#[derive(Debug, Serialize, Clone)]
-pub struct GeoJsonCollection {
-    #[serde(rename = "type")]
-    pub collection_type: String,
-    pub features: Vec<GeoJsonFeature>,
+    GeoJsonCollection::from_features(features)
 }
 
-impl GeoJsonCollection {
-    /// Creates a new GeoJSON collection from subway station data
-    pub fn new(stations: Vec<SubwayStationResponse>) -> Self {
-        let features = stations
-            .into_iter()
-            .map(|station| GeoJsonFeature {
-                feature_type: "Feature".to_string(),
-                properties: GeoJsonProperties {
-                    name: station.stop_name,
-                    lines: station.daytime_routes,
-                    division: station.division,
-                    borough: station.borough,
-                    ada: station.ada.unwrap_or_default() == "TRUE",
-                    ada_notes: station.ada_notes.unwrap_or_default(),
-                    north_direction: station.north_direction_label.unwrap_or_default(),
-                    south_direction: station.south_direction_label.unwrap_or_default(),
-                    color: match station.line.chars().next().unwrap_or('_') {
-                        'A' | 'C' | 'E' => "#0039A6",       // Dark blue
-                        'B' | 'D' | 'F' | 'M' => "#FF6319", // Orange
-                        'G' => "#6CBE45",                   // Green
-                        'J' | 'Z' => "#996633",             // Brown
-                        'L' => "#A7A9AC",                   // Gray
-                        'N' | 'Q' | 'R' | 'W' => "#FCCC0A", // Yellow
-                        '1' | '2' | '3' => "#EE352E",       // Red
-                        '4' | '5' | '6' => "#00933C",       // Green
-                        '7' => "#B933AD",                   // Purple
-                        'S' => "#808183",                   // Gray
-                        _ => "#808183",                     // Default gray
-                    }
-                    .to_string(),
-                },
-                geometry: GeoJsonGeometry {
-                    geometry_type: "Point".to_string(),
-                    coordinates: GeoJsonCoordinates::Point([
-                        station.gtfs_longitude,
-                        station.gtfs_latitude,
-                    ]),
-                },
-            })
-            .collect();
-
-        GeoJsonCollection {
-            collection_type: "FeatureCollection".to_string(),
-            features,
-        }
-    }
+/// Parses each raw station record on its own, so one bad row doesn't fail the rest
+///
+/// # Returns
+/// - The stations that parsed, in order, and how many records were skipped
+fn parse_station_records(records: Vec<serde_json::Value>) -> (Vec<SubwayStationResponse>, usize) {
+    let total = records.len();
+    let stations: Vec<SubwayStationResponse> = records
+        .into_iter()
+        .filter_map(|record| serde_json::from_value(record).ok())
+        .collect();
+    let skipped = total - stations.len();
+    (stations, skipped)
 }
 
 /// Fetches subway station data from the NY Open Data API
+///
+/// Stations are requested a page at a time until a short page, so none are
+/// lost to the API's default row limit. Records that don't parse are skipped
+/// and counted in a console warning instead of failing the whole load.
 pub async fn fetch_subway_stations() -> Result<GeoJsonCollection, gloo_net::Error> {
-    let response = Request::get("https://data.ny.gov/resource/39hk-dx4f.json")
-        .send()
-        .await?;
+    let mut stations: Vec<SubwayStationResponse> = Vec::new();
+    let mut fetched = 0;
+    let mut skipped = 0;
+    loop {
+        let url = open_data::page_url(STATIONS_API_URL, STATIONS_PAGE_SIZE, fetched);
+        let page: Vec<serde_json::Value> = Request::get(&url).send().await?.json().await?;
+        let last = open_data::is_last_page(page.len(), STATIONS_PAGE_SIZE);
+        fetched += page.len();
+        let (parsed, page_skipped) = parse_station_records(page);
+        stations.extend(parsed);
+        skipped += page_skipped;
+        if last {
+            break;
+        }
+    }
 
-    let stations: Vec<SubwayStationResponse> = response.json().await?;
-    Ok(GeoJsonCollection::new(stations))
+    if skipped > 0 {
+        web_sys::console::warn_1(
+            &format!("Skipped {} malformed subway station records", skipped).into(),
+        );
+    }
+    web_sys::console::log_1(&format!("Loaded {} subway stations", stations.len()).into());
+    Ok(stations_geojson(stations))
 }
 
+/// Style [`get_line_style`] falls back to for lines it doesn't know
+pub const FALLBACK_LINE_STYLE: &str = "bg-gray-400";
+
 /// Returns the Tailwind CSS class for styling a subway line indicator
 pub fn get_line_style(line: &str) -> &'static str {
     match line {
@@ -188,70 +232,28 @@ pub fn get_line_style(line: &str) -> &'static str {
         "G" => "bg-green-500",
         "J" | "Z" => "bg-brown-500",
         "L" => "bg-gray-500",
-        "N" | "Q" | "R" | "W" => "bg-yellow-500 text-black",
+        "N" | "Q" | "R" | "W" => "bg-yellow-500",
         "1" | "2" | "3" => "bg-red-500",
         "4" | "5" | "6" => "bg-green-500",
         "7" => "bg-purple-500",
-        "S" => "bg-gray-500",
-        _ => "bg-gray-400",
+        "S" | "GS" | "FS" | "H" | "SI" => "bg-gray-500",
+        _ => FALLBACK_LINE_STYLE,
     }
 }
 
-/// Real-time train position data from the MTA API
-#[derive(Debug, Deserialize, Clone)]
-pub struct TrainPosition {
-    pub trip_id: String,
-    pub route_id: String,
-    pub from_stop: StopLocation,
-    pub to_stop: StopLocation,
-    pub progress: f64,
-    pub start_time: i64,
-    pub end_time: i64,
-}
-
-/// Location data for a subway stop/station
-#[derive(Debug, Deserialize, Clone)]
-pub struct StopLocation {
-    pub stop_id: String,
-    pub latitude: f64,
-    pub longitude: f64,
-}
-
-/// GeoJSON Feature specifically for train positions
-#[derive(Debug, Serialize, Clone)]
-pub struct TrainFeature {
-    #[serde(rename = "type")]
-    pub feature_type: String,
-    pub properties: TrainProperties,
-    pub geometry: GeoJsonGeometry,
-}
-
-/// Properties specific to train features
-#[derive(Debug, Serialize, Clone)]
-pub struct TrainProperties {
-    pub trip_id: String,
-    pub route_id: String,
-    pub progress: f64,
-}
-
-/// Fetches and processes real-time train position data
+/// Applies one position update to the tracked trains
 ///
-/// This function:
-/// 1. Fetches latest positions from the API
-/// 2. Updates the global train state
-/// 3. Interpolates positions for smooth animation
-/// 4. Converts to GeoJSON format
-pub async fn fetch_train_positions() -> Result<GeoJsonCollection, gloo_net::Error> {
-    let response = Request::get("http://localhost:3000/api/trains")
-        .send()
-        .await?;
-
-    let text = response.text().await?;
-    let new_positions: Vec<TrainPosition> = serde_json::from_str(&text)?;
-    let current_time = js_sys::Date::now() / 1000.0;
-
-    let mut train_states = TRAIN_STATES.lock();
-
+/// Only targets move here; [`step_train_states`] eases trains toward them.
+/// Trains missing from the update are dead-reckoned forward for up to
+/// `max_missed_updates` consecutive updates (see [`progress_increment`]), then
+/// frozen in place until the feed reports them again, so a stopped train doesn't
+/// sail past its stop.
+fn update_train_states(
+    train_states: &mut HashMap<String, TrainState>,
+    new_positions: Vec<TrainPosition>,
+    current_time: f64,
+    max_missed_updates: u32,
+) {
     // Clear any trains that are at the end of their journey (progress >= 1.0)
     train_states.retain(|_, state| state.current_progress < 1.0);
 
@@ -264,96 +266,211 @@ pub async fn fetch_train_positions() -> Result<GeoJsonCollection, gloo_net::Erro
     // Update existing trains with new data or continue their movement
     for (trip_id, state) in train_states.iter_mut() {
         if !updated_trips.contains(trip_id) {
-            // Train wasn't in the update, continue its movement
-            let time_delta = current_time - state.last_update;
-            let total_journey_time = (state.position.end_time - state.position.start_time) as f64;
-            let progress_increment = if total_journey_time > 0.0 {
-                time_delta / total_journey_time
-            } else {
-                0.0
-            };
-            state.current_progress = (state.current_progress + progress_increment).min(1.0);
+            // Train wasn't in the update; keep it moving for a few updates, then freeze it
+            state.missed_updates += 1;
+            if state.missed_updates <= max_missed_updates {
+                let time_delta = current_time - state.last_update;
+                let total_journey_time =
+                    (state.position.end_time - state.position.start_time) as f64;
+                let increment = progress_increment(time_delta, total_journey_time);
+                state.target_progress = (state.target_progress + increment).min(1.0);
+            }
             state.last_update = current_time;
         }
     }
 
     // Process new position updates
     for new_pos in new_positions {
+        let progress = new_pos.progress;
         train_states
             .entry(new_pos.trip_id.clone())
             .and_modify(|state| {
-                // Only update if the train has moved to a new segment
+                // A new segment is a hard reset; within a segment the train eases to the update
                 if state.position.from_stop.stop_id != new_pos.from_stop.stop_id
                     || state.position.to_stop.stop_id != new_pos.to_stop.stop_id
                 {
-                    state.position = new_pos.clone();
-                    state.current_progress = new_pos.progress;
-                    state.last_update = current_time;
+                    state.current_progress = progress;
                 }
+                state.position = new_pos.clone();
+                state.target_progress = progress;
+                state.last_update = current_time;
+                state.missed_updates = 0;
             })
             .or_insert_with(|| TrainState {
                 position: new_pos,
                 current_progress: 0.0,
+                target_progress: progress,
                 last_update: current_time,
+                missed_updates: 0,
             });
     }
+}
+
+/// Eases every tracked train one frame toward its target and builds the train map layer
+///
+/// Trains that have reached the end of their segment are left off the map.
+fn step_train_states(train_states: &mut HashMap<String, TrainState>) -> GeoJsonCollection {
+    let features = train_states
+        .values_mut()
+        .filter_map(|state| {
+            state.current_progress = ease_progress(
+                state.current_progress,
+                state.target_progress,
+                PROGRESS_EASING,
+            );
+            (state.current_progress < 1.0)
+                .then(|| state.position.to_geojson_feature_at(state.current_progress))
+        })
+        .collect();
+    GeoJsonCollection::from_features(features)
+}
+
+/// Advances the tracked trains one animation frame, for redrawing every [`ANIMATION_FRAME_MS`]
+///
+/// The frame is cached as the fallback for failed fetches, so falling back
+/// doesn't jump trains back to where the last fetch left them.
+pub fn animate_train_positions() -> GeoJsonCollection {
+    let collection = step_train_states(&mut TRAIN_STATES.lock());
+    *LAST_TRAIN_COLLECTION.lock() = Some(collection.clone());
+    collection
+}
+
+/// Where train positions are fetched from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrainSource {
+    /// Current positions, eased between polls
+    Live,
+    /// The positions recorded at a Unix timestamp, shown as-is
+    Replay { at: i64 },
+}
+
+impl TrainSource {
+    /// Backend endpoint serving this source's positions
+    pub fn url(self) -> String {
+        match self {
+            TrainSource::Live => "http://localhost:3000/api/trains".to_string(),
+            TrainSource::Replay { at } => {
+                format!("http://localhost:3000/api/trains/replay?at={}", at)
+            }
+        }
+    }
+}
+
+/// Builds the train map layer straight from a snapshot, without easing
+fn snapshot_geojson(positions: Vec<TrainPosition>) -> GeoJsonCollection {
+    let features = positions
+        .iter()
+        .filter(|position| position.progress < 1.0)
+        .map(TrainPosition::to_geojson_feature)
+        .collect();
+    GeoJsonCollection::from_features(features)
+}
+
+/// Forgets every tracked train, so the next live update starts fresh
+pub fn reset_train_states() {
+    TRAIN_STATES.lock().clear();
+    *LAST_TRAIN_COLLECTION.lock() = None;
+}
+
+/// Caches a successfully built train layer, or falls back to the cached one
+///
+/// # Returns
+/// - The fresh collection, or a copy of the last cached one if `result` failed
+/// - The error if it failed and nothing has been cached yet
+fn with_cached_fallback<E>(
+    result: Result<GeoJsonCollection, E>,
+    cache: &Mutex<Option<GeoJsonCollection>>,
+) -> Result<GeoJsonCollection, E> {
+    let mut cached = cache.lock();
+    match result {
+        Ok(collection) => {
+            *cached = Some(collection.clone());
+            Ok(collection)
+        }
+        Err(e) => cached.clone().ok_or(e),
+    }
+}
+
+/// Fetches the positions served by `source`, retrying once on failure
+async fn fetch_positions(source: TrainSource) -> Result<Vec<TrainPosition>, gloo_net::Error> {
+    async fn fetch_once(url: &str) -> Result<Vec<TrainPosition>, gloo_net::Error> {
+        let text = Request::get(url).send().await?.text().await?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    let url = source.url();
+    match fetch_once(&url).await {
+        Ok(positions) => Ok(positions),
+        Err(e) => {
+            web_sys::console::warn_1(
+                &format!("Retrying train positions after error: {:?}", e).into(),
+            );
+            fetch_once(&url).await
+        }
+    }
+}
+
+/// Fetches and processes train position data from `source`
+///
+/// For [`TrainSource::Live`] this function:
+/// 1. Fetches latest positions from the API
+/// 2. Updates the global train state (see [`update_train_states`])
+/// 3. Converts the tracked trains, as currently drawn, to GeoJSON format
+///
+/// Live trains are animated toward the fetched positions separately, by
+/// [`animate_train_positions`].
+///
+/// A [`TrainSource::Replay`] snapshot is converted directly and leaves the
+/// tracked trains untouched.
+///
+/// A failed request is retried once. If a live fetch still fails, the last
+/// live collection built is returned instead, so a network blip doesn't blank
+/// the map; the error is only returned when there's nothing to fall back to.
+pub async fn fetch_train_positions(
+    source: TrainSource,
+) -> Result<GeoJsonCollection, gloo_net::Error> {
+    let fetched = fetch_positions(source).await;
+    if let TrainSource::Replay { .. } = source {
+        return fetched.map(snapshot_geojson);
+    }
+    let new_positions = match fetched {
+        Ok(positions) => positions,
+        Err(e) => {
+            web_sys::console::warn_1(
+                &format!("Showing last train positions after error: {:?}", e).into(),
+            );
+            return with_cached_fallback(Err(e), &LAST_TRAIN_COLLECTION);
+        }
+    };
+    let current_time = js_sys::Date::now() / 1000.0;
+
+    let mut train_states = TRAIN_STATES.lock();
+    update_train_states(
+        &mut train_states,
+        new_positions,
+        current_time,
+        MAX_MISSED_UPDATES,
+    );
 
     // Only include trains that are actively moving (progress < 1.0)
     let features: Vec<GeoJsonFeature> = train_states
-        .iter()
-        .filter(|(_, state)| state.current_progress < 1.0)
-        .map(|(_, state)| {
-            // Calculate interpolated position
-            let current_lat = state.position.from_stop.latitude
-                + (state.position.to_stop.latitude - state.position.from_stop.latitude)
-                    * state.current_progress;
-            let current_lon = state.position.from_stop.longitude
-                + (state.position.to_stop.longitude - state.position.from_stop.longitude)
-                    * state.current_progress;
-
-            GeoJsonFeature {
-                feature_type: "Feature".to_string(),
-                properties: GeoJsonProperties {
-                    name: format!("Train {}", state.position.route_id), // Changed from trip_id to route_id
-                    lines: state.position.route_id.clone(),
-                    division: String::new(),
-                    borough: String::new(),
-                    ada: false,
-                    ada_notes: String::new(),
-                    north_direction: String::new(),
-                    south_direction: String::new(),
-                    color: match state.position.route_id.chars().next().unwrap_or('_') {
-                        'A' | 'C' | 'E' => "#0039A6",
-                        'B' | 'D' | 'F' | 'M' => "#FF6319",
-                        'G' => "#6CBE45",
-                        'J' | 'Z' => "#996633",
-                        'L' => "#A7A9AC",
-                        'N' | 'Q' | 'R' | 'W' => "#FCCC0A",
-                        '1' | '2' | '3' => "#EE352E",
-                        '4' | '5' | '6' => "#00933C",
-                        '7' => "#B933AD",
-                        'S' => "#808183",
-                        _ => "#808183",
-                    }
-                    .to_string(),
-                },
-                geometry: GeoJsonGeometry {
-                    geometry_type: "Point".to_string(),
-                    coordinates: GeoJsonCoordinates::Point([current_lon, current_lat]),
-                },
-            }
-        })
+        .values()
+        .filter(|state| state.current_progress < 1.0)
+        .map(|state| state.position.to_geojson_feature_at(state.current_progress))
         .collect();
+    drop(train_states);
 
-    Ok(GeoJsonCollection {
-        collection_type: "FeatureCollection".to_string(),
-        features,
-    })
+    with_cached_fallback(
+        Ok(GeoJsonCollection::from_features(features)),
+        &LAST_TRAIN_COLLECTION,
+    )
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use nyc_pulse_common::feeds::SUBWAY_FEEDS;
+    use nyc_pulse_common::lines::line_info;
 
     #[test]
     fn test_line_style_colors() {
@@ -378,6 +495,53 @@ mod tests {
         assert_eq!(get_line_style("unknown"), "bg-gray-400");
     }
 
+    #[test]
+    fn test_shuttle_styles() {
+        for shuttle in ["GS", "FS", "H", "SI"] {
+            assert_eq!(get_line_style(shuttle), "bg-gray-500", "{}", shuttle);
+            assert_eq!(route_color(shuttle), "#808183", "{}", shuttle);
+        }
+    }
+
+    #[test]
+    fn test_every_feed_line_is_styled_and_colored() {
+        for feed in SUBWAY_FEEDS.iter() {
+            for &line in feed.lines {
+                assert_ne!(get_line_style(line), FALLBACK_LINE_STYLE, "{}", line);
+                // Shuttles share the fallback color, so check for an entry instead
+                let info = line_info(line);
+                assert!(info.is_some(), "{} has no color", line);
+                assert_eq!(route_color(line), info.unwrap().color, "{}", line);
+            }
+        }
+    }
+
+    #[test]
+    fn test_malformed_station_record_is_skipped() {
+        let records = vec![
+            serde_json::json!({
+                "stop_name": "Bedford Av",
+                "daytime_routes": "L",
+                "gtfs_latitude": "40.717304",
+                "gtfs_longitude": "-73.956872",
+                "division": "BMT",
+                "line": "Canarsie",
+                "borough": "Bk"
+            }),
+            serde_json::json!({
+                "stop_name": "Nowhere",
+                "gtfs_latitude": "not a number"
+            }),
+        ];
+
+        let (stations, skipped) = parse_station_records(records);
+
+        assert_eq!(skipped, 1);
+        assert_eq!(stations.len(), 1);
+        assert_eq!(stations[0].stop_name, "Bedford Av");
+        assert_eq!(stations[0].gtfs_latitude, 40.717304);
+    }
+
     #[test]
     fn test_geojson_collection_creation() {
         let stations = vec![
@@ -409,7 +573,7 @@ mod tests {
             },
         ];
 
-        let collection = GeoJsonCollection::new(stations);
+        let collection = stations_geojson(stations);
 
         assert_eq!(collection.collection_type, "FeatureCollection");
         assert_eq!(collection.features.len(), 2);
@@ -419,6 +583,7 @@ mod tests {
         assert_eq!(first.feature_type, "Feature");
         assert_eq!(first.properties.name, "14th St");
         assert_eq!(first.properties.lines, "L");
+        assert_eq!(first.properties.color, "#A7A9AC");
 
         if let GeoJsonCoordinates::Point(coords) = &first.geometry.coordinates {
             assert_eq!(coords[0], -73.9);
@@ -436,52 +601,219 @@ mod tests {
         }
     }
 
+    /// Station layer built from `tests/fixtures/stations.json`, one station per
+    /// route color, compared against `tests/fixtures/stations.golden.geojson`
+    ///
+    /// Run with `BLESS=1` to rewrite the golden file after an intended change
+    /// to the Mapbox-facing format.
+    #[test]
+    fn test_stations_geojson_matches_golden() {
+        let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let records: Vec<serde_json::Value> =
+            serde_json::from_str(&std::fs::read_to_string(fixtures.join("stations.json")).unwrap())
+                .unwrap();
+        let (stations, skipped) = parse_station_records(records);
+        assert_eq!(skipped, 0);
+
+        let collection = stations_geojson(stations);
+        let actual = serde_json::to_string_pretty(&collection).unwrap() + "\n";
+
+        let golden = fixtures.join("stations.golden.geojson");
+        if std::env::var_os("BLESS").is_some_and(|bless| bless == "1") {
+            std::fs::write(&golden, &actual).unwrap();
+            return;
+        }
+        let expected = std::fs::read_to_string(&golden).unwrap();
+        assert!(
+            actual == expected,
+            "station GeoJSON differs from {}; rerun with BLESS=1 if intended:\n{}",
+            golden.display(),
+            actual
+        );
+    }
+
     #[test]
     fn test_train_feature_creation() {
         let train = TrainPosition {
             trip_id: "123".to_string(),
             route_id: "L".to_string(),
-            from_stop: StopLocation {
-                stop_id: "L06".to_string(),
-                latitude: 40.7,
-                longitude: -73.9,
-            },
-            to_stop: StopLocation {
-                stop_id: "L08".to_string(),
-                latitude: 40.71,
-                longitude: -73.92,
-            },
+            is_known: true,
+            express: false,
+            from_stop: StopLocation::new("L06".to_string(), Some("1 Av".to_string()), 40.7, -73.9),
+            to_stop: StopLocation::new("L08".to_string(), None, 40.71, -73.92),
             progress: 0.5,
             start_time: 1000,
             end_time: 2000,
+            direction: None,
+            destination_stop_id: None,
+            destination_name: None,
+            occupancy: None,
+            remaining_stops: Vec::new(),
+            eta_to_destination_secs: None,
+            speed_kmh: None,
         };
 
-        let feature = GeoJsonFeature {
-            feature_type: "Feature".to_string(),
-            properties: GeoJsonProperties {
-                name: format!("Train {}", train.route_id),
-                lines: train.route_id.clone(),
-                division: String::new(),
-                borough: String::new(),
-                ada: false,
-                ada_notes: String::new(),
-                north_direction: String::new(),
-                south_direction: String::new(),
-                color: "#A7A9AC".to_string(),
-            },
-            geometry: GeoJsonGeometry {
-                geometry_type: "Point".to_string(),
-                coordinates: GeoJsonCoordinates::Point([-73.91, 40.705]),
-            },
-        };
+        let feature = train.to_geojson_feature();
 
         assert_eq!(feature.feature_type, "Feature");
-        assert_eq!(feature.properties.name, "Train L");
+        assert_eq!(feature.properties.name, "L train: 1 Av → L08");
         assert_eq!(feature.properties.lines, "L");
+        assert_eq!(feature.properties.color, "#A7A9AC");
 
         if let GeoJsonCoordinates::Point(coords) = &feature.geometry.coordinates {
-            assert_eq!(coords[0], -73.91); // Interpolated longitude
-            assert_eq!(coords[1], 40.705); // Interpolated latitude
+            assert!((coords[0] - -73.91).abs() < 1e-9); // Interpolated longitude
+            assert!((coords[1] - 40.705).abs() < 1e-9); // Interpolated latitude
+        }
+    }
+
+    fn position(trip_id: &str, progress: f64) -> TrainPosition {
+        TrainPosition {
+            trip_id: trip_id.to_string(),
+            route_id: "L".to_string(),
+            is_known: true,
+            express: false,
+            from_stop: StopLocation::new("L06N".to_string(), None, 40.7, -73.9),
+            to_stop: StopLocation::new("L08N".to_string(), None, 40.71, -73.92),
+            progress,
+            start_time: 0,
+            end_time: 1000,
+            direction: None,
+            destination_stop_id: None,
+            destination_name: None,
+            occupancy: None,
+            remaining_stops: Vec::new(),
+            eta_to_destination_secs: None,
+            speed_kmh: None,
+        }
+    }
+
+    #[test]
+    fn test_missing_train_stops_advancing() {
+        let mut states = HashMap::new();
+        update_train_states(&mut states, vec![position("t1", 0.1)], 0.0, 3);
+
+        // Each empty update is 10s, i.e. 0.01 of the 1000s segment
+        for update in 1..=10 {
+            update_train_states(&mut states, Vec::new(), update as f64 * 10.0, 3);
+        }
+
+        let state = &states["t1"];
+        assert_eq!(state.missed_updates, 10);
+        assert!(
+            (state.target_progress - 0.13).abs() < 1e-9,
+            "advanced past the cap: {}",
+            state.target_progress
+        );
+
+        // Reappearing resets the count and follows the feed again
+        update_train_states(&mut states, vec![position("t1", 0.5)], 110.0, 3);
+        assert_eq!(states["t1"].missed_updates, 0);
+        assert_eq!(states["t1"].target_progress, 0.5);
+    }
+
+    #[test]
+    fn test_long_pause_does_not_snap_train_forward() {
+        let mut states = HashMap::new();
+        update_train_states(&mut states, vec![position("t1", 0.1)], 0.0, 3);
+
+        // Tab backgrounded for ten minutes, longer than the whole segment
+        update_train_states(&mut states, Vec::new(), 600.0, 3);
+        assert_eq!(states["t1"].target_progress, 0.1);
+        assert_eq!(states["t1"].last_update, 600.0);
+
+        // Updates after the resume advance from the refreshed time
+        update_train_states(&mut states, Vec::new(), 610.0, 3);
+        assert!((states["t1"].target_progress - 0.11).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_progress_increment_is_capped() {
+        assert_eq!(progress_increment(10.0, 1000.0), 0.01);
+        // A 20s update across a 5s segment would otherwise jump four segments' worth
+        assert_eq!(progress_increment(20.0, 5.0), MAX_PROGRESS_INCREMENT);
+        assert_eq!(progress_increment(MAX_UPDATE_GAP_SECS + 1.0, 1000.0), 0.0);
+        assert_eq!(progress_increment(-5.0, 1000.0), 0.0);
+        assert_eq!(progress_increment(10.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_ease_progress_converges_to_target() {
+        let mut progress = 0.2;
+        let mut previous_gap = 0.6;
+        for _ in 0..100 {
+            progress = ease_progress(progress, 0.8, PROGRESS_EASING);
+            let gap = 0.8 - progress;
+            assert!(gap >= 0.0, "overshot target: {}", progress);
+            assert!(gap <= previous_gap);
+            previous_gap = gap;
+        }
+        assert_eq!(progress, 0.8);
+
+        // Easing backward doesn't undershoot either
+        assert!(ease_progress(0.5, 0.4, PROGRESS_EASING) > 0.4);
+    }
+
+    #[test]
+    fn test_frames_ease_between_fetches() {
+        let mut states = HashMap::new();
+        update_train_states(&mut states, vec![position("t1", 0.2)], 0.0, 3);
+        update_train_states(&mut states, vec![position("t1", 0.6)], 2.0, 3);
+
+        // A fetch only moves the target; frames carry the train toward it
+        assert_eq!(states["t1"].current_progress, 0.0);
+        let mut previous = 0.0;
+        let frames = TRAIN_FETCH_INTERVAL_MS / ANIMATION_FRAME_MS;
+        for _ in 0..frames {
+            let collection = step_train_states(&mut states);
+            assert_eq!(collection.features.len(), 1);
+            let progress = states["t1"].current_progress;
+            assert!(progress > previous && progress <= 0.6, "{}", progress);
+            previous = progress;
+        }
+        assert!(previous > 0.55, "still far from target: {}", previous);
+
+        // Trains at the end of their segment are left off the map
+        update_train_states(&mut states, vec![position("t2", 1.0)], 4.0, 3);
+        states.get_mut("t2").unwrap().current_progress = 1.0;
+        assert_eq!(step_train_states(&mut states).features.len(), 1);
+    }
+
+    #[test]
+    fn test_failed_fetch_falls_back_to_cached_collection() {
+        let cache = Mutex::new(None);
+
+        // Nothing cached yet, so the error comes through
+        let result = with_cached_fallback(Err("offline"), &cache);
+        assert_eq!(result.unwrap_err(), "offline");
+
+        let fresh = snapshot_geojson(vec![position("a", 0.5)]);
+        let result: Result<_, &str> = with_cached_fallback(Ok(fresh), &cache);
+        assert_eq!(result.unwrap().features.len(), 1);
+
+        let fallback = with_cached_fallback(Err("offline"), &cache).unwrap();
+        assert_eq!(fallback.features.len(), 1);
+        assert_eq!(fallback.features[0].properties.lines, "L");
+    }
+
+    #[test]
+    fn test_train_source_urls() {
+        assert_eq!(TrainSource::Live.url(), "http://localhost:3000/api/trains");
+        assert_eq!(
+            TrainSource::Replay { at: 1_700_000_000 }.url(),
+            "http://localhost:3000/api/trains/replay?at=1700000000"
+        );
+    }
+
+    #[test]
+    fn test_snapshot_shows_recorded_progress() {
+        let collection = snapshot_geojson(vec![position("a", 0.5), position("b", 1.0)]);
+
+        assert_eq!(collection.features.len(), 1);
+        match &collection.features[0].geometry.coordinates {
+            GeoJsonCoordinates::Point(point) => {
+                assert_eq!(*point, [-73.91, 40.705])
+            }
+            other => panic!("expected a point, got {:?}", other),
         }
     }
 }